@@ -0,0 +1,8 @@
+#[macro_use]
+extern crate downcast_rs;
+
+// sqliters : a small sqlite like database. Programs embed it through Connection ;
+// the learndb binary runs the shell of sqmain.
+mod sqliters;
+
+pub use sqliters::*;
@@ -1,5 +1,4 @@
-#[macro_use]
-extern crate downcast_rs;
+extern crate sqliters;
 
 // mod fileapi;
 // mod bplustree;
@@ -10,7 +9,6 @@ extern crate downcast_rs;
 // mod deque;
 // mod unsafelist;
 // mod transactional;
-mod sqliters;
 // use std::sync::Arc;
 
 fn main() {
@@ -0,0 +1,194 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use sqliters::{row, expr};
+use sqliters::ast::{Expr, AggregateFunction};
+
+// Aggregation of rows into groups.
+// Every aggregate call of a query gets an accumulator per group ; null values are skipped. Output expressions of a group
+// are evaluated after group by expressions and aggregate calls in them are replaced by their values.
+
+#[derive(Debug)]
+pub enum Accumulator {
+    Count(i64),
+    // None till a value is added.
+    Sum(Option<Total>),
+    Avg { sum: Total, count: i64 },
+    Min(Option<row::Value>),
+    Max(Option<row::Value>)
+}
+
+// sum of numbers ; sums of integers are bigints and sums of bigints only overflow when they are finished.
+#[derive(Debug, Clone, Copy)]
+pub enum Total {
+    Integer(i64),
+    BigInt(i128),
+    Real(f64)
+}
+
+impl Accumulator {
+    pub fn new(function: AggregateFunction) -> Self {
+        match function {
+            AggregateFunction::Count => Accumulator::Count(0),
+            AggregateFunction::Sum => Accumulator::Sum(None),
+            AggregateFunction::Avg => Accumulator::Avg { sum: Total::Integer(0), count: 0 },
+            AggregateFunction::Min => Accumulator::Min(None),
+            AggregateFunction::Max => Accumulator::Max(None)
+        }
+    }
+
+    // value is only checked for null by count ; count(*) gets a non null value for every row.
+    pub fn add(&mut self, value: row::Value) -> Result<(), String> {
+        if value == row::Value::Null {
+            return Ok(())
+        }
+        match *self {
+            Accumulator::Count(ref mut count) => *count += 1,
+            Accumulator::Sum(ref mut sum) => *sum = Some(sum.unwrap_or(Total::Integer(0)).add(&value)?),
+            Accumulator::Avg { ref mut sum, ref mut count } => {
+                *sum = sum.add(&value)?;
+                *count += 1;
+            },
+            Accumulator::Min(ref mut min) => keep_if(min, value, Ordering::Less)?,
+            Accumulator::Max(ref mut max) => keep_if(max, value, Ordering::Greater)?
+        }
+        Ok(())
+    }
+
+    // value of aggregate over added values ; min, max, sum and avg of no values are null and count of no values is 0.
+    // count is a bigint and avg is a real.
+    pub fn finish(&self, aggregate: &Expr) -> Result<row::Value, String> {
+        match *self {
+            Accumulator::Count(count) => Ok(row::Value::BigInt(count)),
+            Accumulator::Sum(None) => Ok(row::Value::Null),
+            Accumulator::Sum(Some(sum)) => sum.value(aggregate),
+            Accumulator::Avg { count: 0, .. } => Ok(row::Value::Null),
+            Accumulator::Avg { sum, count } => Total::Real(sum.real() / count as f64).value(aggregate),
+            Accumulator::Min(ref v) | Accumulator::Max(ref v) => Ok(v.clone().unwrap_or(row::Value::Null))
+        }
+    }
+}
+
+impl Total {
+    fn add(self, value: &row::Value) -> Result<Total, String> {
+        match (self, value) {
+            (Total::Integer(t), &row::Value::Integer(v)) => Ok(Total::Integer(t + i64::from(v))),
+            (Total::Integer(t), &row::Value::BigInt(v)) => Ok(Total::BigInt(i128::from(t) + i128::from(v))),
+            (Total::BigInt(t), &row::Value::Integer(v)) => Ok(Total::BigInt(t + i128::from(v))),
+            (Total::BigInt(t), &row::Value::BigInt(v)) => Ok(Total::BigInt(t + i128::from(v))),
+            (Total::Real(t), &row::Value::Integer(v)) => Ok(Total::Real(t + f64::from(v))),
+            (Total::Real(t), &row::Value::BigInt(v)) => Ok(Total::Real(t + v as f64)),
+            (total, &row::Value::Real(v)) => Ok(Total::Real(total.real() + v)),
+            _ => Err(format!("Can not add {} to a number", value))
+        }
+    }
+
+    fn real(self) -> f64 {
+        match self {
+            Total::Integer(t) => t as f64,
+            Total::BigInt(t) => t as f64,
+            Total::Real(t) => t
+        }
+    }
+
+    fn value(self, aggregate: &Expr) -> Result<row::Value, String> {
+        match self {
+            Total::Integer(t) => Ok(row::Value::BigInt(t)),
+            Total::BigInt(t) => i64::try_from(t).map(row::Value::BigInt)
+                .map_err(|_| format!("Integer overflow in {} : {}", aggregate, t)),
+            Total::Real(t) if t.is_finite() => Ok(row::Value::Real(t)),
+            Total::Real(t) => Err(format!("Real overflow in {} : {:?}", aggregate, t))
+        }
+    }
+}
+
+// replaces current with value when value compares as ordering to it.
+fn keep_if(current: &mut Option<row::Value>, value: row::Value, ordering: Ordering) -> Result<(), String> {
+    let replace = match *current {
+        None => true,
+        Some(ref c) => expr::compare(&value, c)? == ordering
+    };
+    if replace {
+        *current = Some(value);
+    }
+    Ok(())
+}
+
+// distinct aggregate calls in exprs in order of appearance.
+pub fn collect_aggregates(exprs: &[&Expr]) -> Vec<Expr> {
+    let mut aggregates = vec![];
+    for expr in exprs {
+        collect(expr, &mut aggregates);
+    }
+    aggregates
+}
+
+fn collect(expr: &Expr, aggregates: &mut Vec<Expr>) {
+    match *expr {
+        Expr::Aggregate { .. } => {
+            if !aggregates.contains(expr) {
+                aggregates.push(expr.clone());
+            }
+        },
+        Expr::Literal(_) | Expr::Column(_) | Expr::Excluded(_) => {},
+        Expr::Binary(_, ref left, ref right) => {
+            collect(left, aggregates);
+            collect(right, aggregates);
+        },
+        Expr::Not(ref inner) | Expr::IsNull { expr: ref inner, .. } => collect(inner, aggregates),
+        Expr::Between { ref expr, ref low, ref high, .. } => {
+            collect(expr, aggregates);
+            collect(low, aggregates);
+            collect(high, aggregates);
+        }
+    }
+}
+
+// a column of output expression should be inside an aggregate or a group by expression.
+pub fn check_grouped(expr: &Expr, group_by: &[Expr]) -> Result<(), String> {
+    if group_by.contains(expr) {
+        return Ok(())
+    }
+
+    match *expr {
+        Expr::Column(ref name) => Err(format!("Column '{}' must be in GROUP BY or used in an aggregate", name)),
+        Expr::Aggregate { .. } | Expr::Literal(_) | Expr::Excluded(_) => Ok(()),
+        Expr::Binary(_, ref left, ref right) => {
+            check_grouped(left, group_by)?;
+            check_grouped(right, group_by)
+        },
+        Expr::Not(ref inner) | Expr::IsNull { expr: ref inner, .. } => check_grouped(inner, group_by),
+        Expr::Between { ref expr, ref low, ref high, .. } => {
+            check_grouped(expr, group_by)?;
+            check_grouped(low, group_by)?;
+            check_grouped(high, group_by)
+        }
+    }
+}
+
+// expr with group by expressions and aggregates replaced by their values in a group.
+pub fn bind_group(expr: &Expr, group_by: &[Expr], group_values: &[row::Value], aggregates: &[Expr], results: &[row::Value]) -> Expr {
+    if let Some(pos) = group_by.iter().position(|e| e == expr) {
+        return Expr::Literal(expr::value_literal(&group_values[pos]))
+    }
+
+    let bind = |e: &Expr| Box::new(bind_group(e, group_by, group_values, aggregates, results));
+    match *expr {
+        Expr::Aggregate { .. } => {
+            let pos = aggregates.iter().position(|e| e == expr).expect("aggregates are collected from output expressions");
+            Expr::Literal(expr::value_literal(&results[pos]))
+        },
+        Expr::Literal(_) | Expr::Column(_) | Expr::Excluded(_) => expr.clone(),
+        Expr::Binary(operator, ref left, ref right) => Expr::Binary(operator, bind(left), bind(right)),
+        Expr::Not(ref inner) => Expr::Not(bind(inner)),
+        Expr::IsNull { ref expr, negated } => Expr::IsNull {
+            expr: bind(expr),
+            negated
+        },
+        Expr::Between { ref expr, ref low, ref high, negated } => Expr::Between {
+            expr: bind(expr),
+            low: bind(low),
+            high: bind(high),
+            negated
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use std::fmt;
+use sqliters::{schema, timestamp, error};
+
+// Statements produced by the parser.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    CreateTable(CreateTableStatement),
+    CreateIndex(CreateIndexStatement),
+    Insert(InsertStatement),
+    Select(SelectStatement),
+    Delete(DeleteStatement),
+    Update(UpdateStatement)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Boolean(bool),
+    String(String),
+    Blob(Vec<u8>), // x'hex digits'
+    Timestamp(i64), // timestamp 'YYYY-MM-DD HH:MM:SS'
+    // ? of a prepared statement ; replaced by its bound value before the statement runs.
+    Parameter(usize)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    Equals,
+    NotEquals,
+    Less,
+    LessEquals,
+    Greater,
+    GreaterEquals,
+    And,
+    Or,
+    Plus,
+    Minus,
+    Multiply,
+    Divide
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Literal),
+    Column(String),
+    // excluded.column : value of column in the row which could not be inserted.
+    Excluded(String),
+    Binary(BinaryOperator, Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    // expr [NOT] BETWEEN low AND high
+    Between {
+        expr: Box<Expr>,
+        low: Box<Expr>,
+        high: Box<Expr>,
+        negated: bool
+    },
+    // expr IS [NOT] NULL
+    IsNull {
+        expr: Box<Expr>,
+        negated: bool
+    },
+    // function(expr) over rows of a group ; arg is None for count(*).
+    Aggregate {
+        function: AggregateFunction,
+        arg: Option<Box<Expr>>
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateFunction {
+    Count,
+    Min,
+    Max,
+    Sum,
+    Avg
+}
+
+impl AggregateFunction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "count" => Some(AggregateFunction::Count),
+            "min" => Some(AggregateFunction::Min),
+            "max" => Some(AggregateFunction::Max),
+            "sum" => Some(AggregateFunction::Sum),
+            "avg" => Some(AggregateFunction::Avg),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnDef {
+    pub name: String,
+    pub column_type: schema::ColumnType,
+    pub primary_key: bool,
+    pub autoincrement: bool,
+    pub unique: bool,
+    pub not_null: bool
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateTableStatement {
+    pub table_name: String,
+    pub columns: Vec<ColumnDef>,
+    // columns of a primary key (a, b) table constraint ; empty when there is none.
+    pub primary_key: Vec<String>
+}
+
+// create [unique] index name on t (column)
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateIndexStatement {
+    pub unique: bool,
+    pub index_name: String,
+    pub table_name: String,
+    pub column: String
+}
+
+// insert [or replace | or ignore] into t [(c1, c2)] values (v1, v2), (v3, v4)
+//   [on conflict [(c1)] do update set c2 = expr | do nothing]
+#[derive(Debug, Clone, PartialEq)]
+pub struct InsertStatement {
+    pub table_name: String,
+    pub columns: Option<Vec<String>>,
+    pub rows: Vec<Vec<Literal>>,
+    pub on_conflict: OnConflict,
+    pub conflict_target: Option<String>
+}
+
+// what to do when a row with same primary key is already present.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OnConflict {
+    Abort,
+    Replace,
+    Ignore,
+    Update(Vec<Assignment>)
+}
+
+// select * | expr [as name] [, ...] from t [where expr] [group by expr [, ...]]
+//   [order by expr [asc | desc] [, ...]] [limit n [offset m]]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectStatement {
+    pub projection: Vec<SelectItem>,
+    pub table_name: String,
+    pub where_clause: Option<Expr>,
+    pub group_by: Vec<Expr>,
+    pub order_by: Vec<OrderByItem>,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderByItem {
+    pub expr: Expr,
+    pub descending: bool
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectItem {
+    // all columns of table
+    Wildcard,
+    Expr {
+        expr: Expr,
+        alias: Option<String>
+    }
+}
+
+// delete from t [where expr]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeleteStatement {
+    pub table_name: String,
+    pub where_clause: Option<Expr>
+}
+
+// update t set c1 = expr [, c2 = expr] [where expr]
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdateStatement {
+    pub table_name: String,
+    pub assignments: Vec<Assignment>,
+    pub where_clause: Option<Expr>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub column: String,
+    pub value: Expr
+}
+
+impl CreateTableStatement {
+    // a table without primary key is keyed on a hidden rowid column after its columns.
+    pub fn schema(&self) -> Result<schema::Schema, error::Error> {
+        let mut key_columns: Vec<usize> = self.columns.iter()
+            .enumerate()
+            .filter(|&(_, c)| c.primary_key)
+            .map(|(pos, _)| pos)
+            .collect();
+        if key_columns.len() + if self.primary_key.is_empty() { 0 } else { 1 } > 1 {
+            return Err(error::Error::InvalidSchema(format!("Table '{}' has more than one primary key", self.table_name)))
+        }
+        for name in self.primary_key.iter() {
+            let pos = self.columns.iter()
+                .position(|c| c.name.eq_ignore_ascii_case(name))
+                .ok_or_else(|| error::Error::InvalidSchema(format!("No such column '{}' in primary key of table '{}'", name, self.table_name)))?;
+            key_columns.push(pos);
+        }
+
+        let mut columns: Vec<schema::Column> = self.columns.iter()
+            .map(|c| {
+                let column = schema::Column::new(&c.name, c.column_type.clone());
+                let column = if c.not_null { column.not_null() } else { column };
+                if c.autoincrement { column.autoincrement() } else { column }
+            })
+            .collect();
+        if key_columns.is_empty() {
+            columns.push(schema::Column::new(schema::ROWID_COLUMN_NAME, schema::ColumnType::BigInt).hidden());
+            key_columns.push(columns.len() - 1);
+        }
+        schema::Schema::new(columns, key_columns)
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Literal::Null => fmt.write_str("NULL"),
+            Literal::Integer(v) => write!(fmt, "{}", v),
+            // debug format keeps a fraction or an exponent, so the value reads back as real.
+            Literal::Real(v) => write!(fmt, "{:?}", v),
+            Literal::Boolean(v) => fmt.write_str(if v { "TRUE" } else { "FALSE" }),
+            Literal::String(ref v) => write!(fmt, "'{}'", v.replace('\'', "''")),
+            Literal::Timestamp(v) => write!(fmt, "timestamp '{}'", timestamp::format(v)),
+            Literal::Blob(ref v) => {
+                fmt.write_str("x'")?;
+                for b in v {
+                    write!(fmt, "{:02x}", b)?;
+                }
+                fmt.write_str("'")
+            },
+            Literal::Parameter(_) => fmt.write_str("?")
+        }
+    }
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let operator = match *self {
+            BinaryOperator::Equals => "=",
+            BinaryOperator::NotEquals => "!=",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessEquals => "<=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterEquals => ">=",
+            BinaryOperator::And => "AND",
+            BinaryOperator::Or => "OR",
+            BinaryOperator::Plus => "+",
+            BinaryOperator::Minus => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/"
+        };
+        fmt.write_str(operator)
+    }
+}
+
+// sql text of expression ; nested operators are put in parentheses.
+impl fmt::Display for Expr {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expr::Literal(ref literal) => write!(fmt, "{}", literal),
+            Expr::Column(ref name) => fmt.write_str(name),
+            Expr::Excluded(ref name) => write!(fmt, "excluded.{}", name),
+            Expr::Binary(operator, ref left, ref right) => write!(fmt, "{} {} {}", Operand(left), operator, Operand(right)),
+            Expr::Not(ref inner) => write!(fmt, "NOT {}", Operand(inner)),
+            Expr::Between { ref expr, ref low, ref high, negated } => {
+                write!(fmt, "{} {}BETWEEN {} AND {}", Operand(expr), if negated { "NOT " } else { "" }, Operand(low), Operand(high))
+            },
+            Expr::IsNull { ref expr, negated } => write!(fmt, "{} IS {}NULL", Operand(expr), if negated { "NOT " } else { "" }),
+            Expr::Aggregate { function, ref arg } => match *arg {
+                Some(ref arg) => write!(fmt, "{}({})", function, arg),
+                None => write!(fmt, "{}(*)", function)
+            }
+        }
+    }
+}
+
+impl fmt::Display for AggregateFunction {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            AggregateFunction::Count => "count",
+            AggregateFunction::Min => "min",
+            AggregateFunction::Max => "max",
+            AggregateFunction::Sum => "sum",
+            AggregateFunction::Avg => "avg"
+        };
+        fmt.write_str(name)
+    }
+}
+
+struct Operand<'a>(&'a Expr);
+
+impl<'a> fmt::Display for Operand<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self.0 {
+            Expr::Literal(_) | Expr::Column(_) | Expr::Excluded(_) | Expr::Aggregate { .. } => write!(fmt, "{}", self.0),
+            _ => write!(fmt, "({})", self.0)
+        }
+    }
+}
@@ -0,0 +1,358 @@
+use sqliters::{pager, page, error};
+
+// B+tree operations on the tree rooted at root_page_num.
+// Root page never moves ; when root is split its cells are moved to a new page.
+
+// returns page_num of the leaf which should contain the key and cell position of key in it.
+pub fn find_key_pos(pager: &mut pager::Pager, root_page_num: u64, key: &[u8]) -> Result<(u64, u64), error::Error> {
+    let mut page_num = root_page_num;
+    loop {
+        let page = pager.get_page(page_num as usize)?;
+        if page.is_leaf() {
+            return Ok((page_num, page.find_key_pos(key)))
+        }
+
+        let pos = page.find_key_pos(key);
+        let child_page_num = page.get_page_num(pos);
+        if child_page_num == page_num {
+            page.print();
+            return Err(error::Error::Other(format!("Page {} refers to itself as child", page_num)))
+        }
+        page_num = child_page_num;
+    }
+}
+
+// adds cell of key to its leaf ; key should not be present.
+// Full internal nodes on the path from root are split top down, so that the parent of the leaf
+// always has space for the separator key when the leaf is split with the cell.
+pub fn insert_cell(pager: &mut pager::Pager, root_page_num: u64, key: &[u8], cell: &[u8]) -> Result<(), error::Error> {
+    {
+        let root = pager.get_page(root_page_num as usize)?;
+        if !root.is_leaf() && root.is_full() {
+            let child_page_num = move_root_down(pager, root_page_num)?;
+            split_child(pager, root_page_num, 0, child_page_num)?;
+        }
+    }
+
+    // (page_num, child_pos) of parent of the leaf.
+    let mut parent = None;
+    let mut page_num = root_page_num;
+    loop {
+        let (pos, child_page_num) = {
+            let page = pager.get_page(page_num as usize)?;
+            if page.is_leaf() {
+                break;
+            }
+            let pos = page.find_key_pos(key);
+            (pos, page.get_page_num(pos))
+        };
+
+        let child = pager.get_page(child_page_num as usize)?;
+        if !child.is_leaf() && child.is_full() {
+            split_child(pager, page_num, pos, child_page_num)?;
+            // key can go to either half ; search this page again.
+            continue;
+        }
+
+        parent = Some((page_num, pos));
+        page_num = child_page_num;
+    }
+
+    let cell_pos = {
+        let leaf = pager.get_page(page_num as usize)?;
+        let cell_pos = leaf.find_key_pos(key);
+        if leaf.has_room(cell.len()) {
+            return leaf.add_data(cell_pos, &cell.to_vec())
+        }
+        cell_pos
+    };
+
+    let (parent_page_num, child_pos) = match parent {
+        Some(parent) => parent,
+        None => {
+            page_num = move_root_down(pager, root_page_num)?;
+            (root_page_num, 0)
+        }
+    };
+    // leaf is split in a copy, so it keeps its cells when there is no page for the new half.
+    let mut leaf = pager.get_page(page_num as usize)?.clone();
+    let (new_page, separator_key) = leaf.split_with(cell_pos, cell)?;
+    let new_page_num = pager.new_page(new_page)?;
+    leaf.set_next_sibling_num(new_page_num);
+    *pager.get_page(page_num as usize)? = leaf;
+    pager.get_page(parent_page_num as usize)?
+        .insert_child(child_pos, page_num, &separator_key, new_page_num)
+}
+
+// moves root to a new page and makes root an internal node with it as only child ; returns the new page_num.
+fn move_root_down(pager: &mut pager::Pager, root_page_num: u64) -> Result<u64, error::Error> {
+    let page_size = pager.page_size();
+    let mut old_root = pager.get_page(root_page_num as usize)?.clone();
+    old_root.set_non_root();
+    let key_size = old_root.key_size();
+    let child_page_num = pager.new_page(old_root)?;
+    *pager.get_page(root_page_num as usize)? = page::Page::new_internal(true, page_size, key_size, child_page_num);
+    Ok(child_page_num)
+}
+
+// splits internal child at child_pos of internal node at page_num.
+// Like leaves, the child is split in a copy.
+fn split_child(pager: &mut pager::Pager, page_num: u64, child_pos: u64, child_page_num: u64) -> Result<(), error::Error> {
+    let mut child = pager.get_page(child_page_num as usize)?.clone();
+    let (new_page, separator_key) = child.split();
+    let new_page_num = pager.new_page(new_page)?;
+    *pager.get_page(child_page_num as usize)? = child;
+    pager.get_page(page_num as usize)?
+        .insert_child(child_pos, child_page_num, &separator_key, new_page_num)
+}
+
+// number of keys in the tree ; cells of leaves are counted without reading rows.
+pub fn count_keys(pager: &mut pager::Pager, page_num: u64) -> Result<u64, error::Error> {
+    let (num_cells, is_leaf) = {
+        let page = pager.get_page(page_num as usize)?;
+        (page.num_cells(), page.is_leaf())
+    };
+    if is_leaf {
+        return Ok(num_cells)
+    }
+
+    let mut count = 0;
+    for child_pos in 0 .. num_cells + 1 {
+        let child_page_num = pager.get_page(page_num as usize)?.get_page_num(child_pos);
+        count += count_keys(pager, child_page_num)?;
+    }
+    Ok(count)
+}
+
+// frees all pages of the tree ; cells of the tree should have no overflow pages.
+pub fn free_tree(pager: &mut pager::Pager, page_num: u64) -> Result<(), error::Error> {
+    let (num_cells, is_leaf) = {
+        let page = pager.get_page(page_num as usize)?;
+        (page.num_cells(), page.is_leaf())
+    };
+    if !is_leaf {
+        for child_pos in 0 .. num_cells + 1 {
+            let child_page_num = pager.get_page(page_num as usize)?.get_page_num(child_pos);
+            free_tree(pager, child_page_num)?;
+        }
+    }
+    pager.free_page(page_num)
+}
+
+// removes key from the tree ; returns false when key is not present.
+// Separator keys stay upper bounds of their left subtree when max key of a leaf is removed,
+// so parents are only updated when cells move between siblings.
+pub fn delete_key(pager: &mut pager::Pager, root_page_num: u64, key: &[u8]) -> Result<bool, error::Error> {
+    // (page_num, child_pos) of internal nodes from root to leaf.
+    let mut path = vec![];
+    let mut page_num = root_page_num;
+    loop {
+        let page = pager.get_page(page_num as usize)?;
+        if page.is_leaf() {
+            break;
+        }
+        let pos = page.find_key_pos(key);
+        path.push((page_num, pos));
+        page_num = page.get_page_num(pos);
+    }
+
+    {
+        let leaf = pager.get_page(page_num as usize)?;
+        let pos = leaf.find_key_pos(key);
+        if pos >= leaf.num_cells() || leaf.get_key_at(pos) != key {
+            return Ok(false)
+        }
+        leaf.remove_cell(pos)?;
+    }
+
+    // removing cell from a child can make its parent underflow on merge ; fix bottom up.
+    while let Some((parent_page_num, child_pos)) = path.pop() {
+        rebalance_child(pager, parent_page_num, child_pos)?;
+    }
+    shrink_root(pager, root_page_num)?;
+    Ok(true)
+}
+
+// child at child_pos of internal node at page_num borrows cells from a sibling while it is under full,
+// or merges with the sibling when the sibling can not lend a cell.
+fn rebalance_child(pager: &mut pager::Pager, page_num: u64, child_pos: u64) -> Result<(), error::Error> {
+    let (num_cells, child_page_num) = {
+        let page = pager.get_page(page_num as usize)?;
+        (page.num_cells(), page.get_page_num(child_pos))
+    };
+    // root with a single child has no sibling ; shrink_root takes care of it.
+    if num_cells == 0 {
+        return Ok(())
+    }
+
+    // siblings at left_pos and left_pos + 1 ; child is one of them.
+    let left_pos = if child_pos == num_cells { child_pos - 1 } else { child_pos };
+    let sibling_page_num = pager.get_page(page_num as usize)?.get_page_num(if left_pos == child_pos { left_pos + 1 } else { left_pos });
+    while pager.get_page(child_page_num as usize)?.is_under_full() {
+        let can_lend = {
+            let sibling = pager.get_page(sibling_page_num as usize)?;
+            let edge_pos = if left_pos == child_pos { 0 } else { sibling.num_cells() - 1 };
+            sibling.can_lend(edge_pos)
+        };
+
+        if !can_lend {
+            return merge_children(pager, page_num, left_pos)
+        }
+        if left_pos == child_pos {
+            move_first_cell_left(pager, page_num, left_pos)?;
+        } else {
+            move_last_cell_right(pager, page_num, left_pos)?;
+        }
+    }
+    Ok(())
+}
+
+// moves last cell of child at left_pos to the start of child at left_pos + 1.
+fn move_last_cell_right(pager: &mut pager::Pager, page_num: u64, left_pos: u64) -> Result<(), error::Error> {
+    let (left_page_num, right_page_num, separator_key) = children_at(pager, page_num, left_pos)?;
+
+    let (moved_cell, new_separator_key) = {
+        let left = pager.get_page(left_page_num as usize)?;
+        let last_pos = left.num_cells() - 1;
+        let cell = left.get_cell(last_pos);
+        left.remove_cell(last_pos)?;
+        if left.is_leaf() {
+            (cell, left.max_key())
+        } else {
+            // separator comes down as key of old right page ; last cell's page becomes right page.
+            let old_right_page_num = left.get_page_num(left.num_cells());
+            left.update_right_page_num(page::internal_node_left_page_num(&cell));
+            (page::internal_node_cell(&separator_key, old_right_page_num), page::internal_node_key(&cell).to_vec())
+        }
+    };
+
+    pager.get_page(right_page_num as usize)?.add_data(0, &moved_cell)?;
+    pager.get_page(page_num as usize)?.update_data(left_pos, &page::internal_node_cell(&new_separator_key, left_page_num))
+}
+
+// moves first cell of child at left_pos + 1 to the end of child at left_pos.
+fn move_first_cell_left(pager: &mut pager::Pager, page_num: u64, left_pos: u64) -> Result<(), error::Error> {
+    let (left_page_num, right_page_num, separator_key) = children_at(pager, page_num, left_pos)?;
+
+    let (cell, is_leaf) = {
+        let right = pager.get_page(right_page_num as usize)?;
+        let cell = right.get_cell(0);
+        right.remove_cell(0)?;
+        (cell, right.is_leaf())
+    };
+
+    let new_separator_key = {
+        let left = pager.get_page(left_page_num as usize)?;
+        let end_pos = left.num_cells();
+        if is_leaf {
+            left.add_data(end_pos, &cell)?;
+            left.max_key()
+        } else {
+            // separator comes down as key of old right page ; first cell's page becomes right page.
+            let old_right_page_num = left.get_page_num(end_pos);
+            left.add_data(end_pos, &page::internal_node_cell(&separator_key, old_right_page_num))?;
+            left.update_right_page_num(page::internal_node_left_page_num(&cell));
+            page::internal_node_key(&cell).to_vec()
+        }
+    };
+
+    pager.get_page(page_num as usize)?.update_data(left_pos, &page::internal_node_cell(&new_separator_key, left_page_num))
+}
+
+// moves all cells of child at left_pos + 1 into child at left_pos and frees it.
+fn merge_children(pager: &mut pager::Pager, page_num: u64, left_pos: u64) -> Result<(), error::Error> {
+    let (left_page_num, right_page_num, separator_key) = children_at(pager, page_num, left_pos)?;
+    let right = pager.get_page(right_page_num as usize)?.clone();
+
+    {
+        let left = pager.get_page(left_page_num as usize)?;
+        if left.is_leaf() {
+            left.set_next_sibling_num(right.next_sibling_num());
+        } else {
+            let old_right_page_num = left.get_page_num(left.num_cells());
+            let end_pos = left.num_cells();
+            left.add_data(end_pos, &page::internal_node_cell(&separator_key, old_right_page_num))?;
+            left.update_right_page_num(right.get_page_num(right.num_cells()));
+        }
+
+        for cell_pos in 0 .. right.num_cells() {
+            let end_pos = left.num_cells();
+            left.add_data(end_pos, &right.get_cell(cell_pos))?;
+        }
+    }
+
+    {
+        // merged page takes place of the right page ; separator of left page goes away.
+        let page = pager.get_page(page_num as usize)?;
+        if left_pos + 1 == page.num_cells() {
+            page.update_right_page_num(left_page_num);
+        } else {
+            let cell = page::internal_node_cell(page.get_key_at(left_pos + 1), left_page_num);
+            page.update_data(left_pos + 1, &cell)?;
+        }
+        page.remove_cell(left_pos)?;
+    }
+
+    pager.free_page(right_page_num)
+}
+
+// (left page num, right page num, separator key) of children at left_pos and left_pos + 1.
+fn children_at(pager: &mut pager::Pager, page_num: u64, left_pos: u64) -> Result<(u64, u64, Vec<u8>), error::Error> {
+    let page = pager.get_page(page_num as usize)?;
+    Ok((page.get_page_num(left_pos), page.get_page_num(left_pos + 1), page.get_key_at(left_pos).to_vec()))
+}
+
+// root internal node left with only right page is replaced by that page.
+fn shrink_root(pager: &mut pager::Pager, root_page_num: u64) -> Result<(), error::Error> {
+    loop {
+        let child_page_num = {
+            let root = pager.get_page(root_page_num as usize)?;
+            if root.is_leaf() || root.num_cells() > 0 {
+                return Ok(())
+            }
+            root.get_page_num(0)
+        };
+
+        let mut child = pager.get_page(child_page_num as usize)?.clone();
+        child.set_root();
+        *pager.get_page(root_page_num as usize)? = child;
+        pager.free_page(child_page_num)?;
+    }
+}
+
+// checks that keys are sorted, separators bound their subtrees and non root nodes are not under full.
+#[cfg(test)]
+pub fn validate(pager: &mut pager::Pager, root_page_num: u64) -> Result<(), error::Error> {
+    validate_node(pager, root_page_num, true, None, None).map(|_| ())
+}
+
+// keys of node at page_num should be in (lower, upper] ; returns number of keys in subtree.
+#[cfg(test)]
+fn validate_node(pager: &mut pager::Pager, page_num: u64, is_root: bool, lower: Option<&[u8]>, upper: Option<&[u8]>) -> Result<u64, error::Error> {
+    let page = pager.get_page(page_num as usize)?.clone();
+    if !is_root && page.is_under_full() {
+        return Err(error::Error::Other(format!("Page {} is under full with {} cells", page_num, page.num_cells())))
+    }
+
+    let mut previous = lower;
+    for cell_pos in 0 .. page.num_cells() {
+        let key = page.get_key_at(cell_pos);
+        if previous.is_some_and(|p| key <= p) || upper.is_some_and(|u| key > u) {
+            return Err(error::Error::Other(format!("Page {} has key {:?} out of order : previous {:?}, upper {:?}", page_num, key, previous, upper)))
+        }
+        previous = Some(key);
+    }
+
+    if page.is_leaf() {
+        return Ok(page.num_cells())
+    }
+
+    let mut num_keys = 0;
+    let mut lower = lower;
+    for child_pos in 0 .. page.num_cells() + 1 {
+        let child_upper = if child_pos < page.num_cells() { Some(page.get_key_at(child_pos)) } else { upper };
+        num_keys += validate_node(pager, page.get_page_num(child_pos), false, lower, child_upper)?;
+        lower = child_upper;
+    }
+    Ok(num_keys)
+}
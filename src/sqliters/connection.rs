@@ -0,0 +1,150 @@
+use std::vec;
+use sqliters::{context, database, error, row, sqlcommands, statement};
+
+// A database file opened by a program which embeds sqliters. Statements are the commands of the shell ;
+// rows of a query are returned to the caller instead of being printed.
+pub struct Connection {
+    db: database::Database,
+    context: context::Context
+}
+
+// Rows of a query in the order of select. Rows are read from the tree before query returns,
+// so the connection can be used while they are iterated ; they are all in memory till Rows is dropped.
+pub struct Rows {
+    columns: Vec<String>,
+    rows: vec::IntoIter<row::Row>
+}
+
+impl Connection {
+    // database file at path is created when it does not exist.
+    pub fn open(path: &str) -> Result<Self, error::Error> {
+        Ok(Connection::with_db(database::Database::new(path)?))
+    }
+
+    // page size of config is used when the database file is created.
+    pub fn open_with_config(path: &str, config: database::Config) -> Result<Self, error::Error> {
+        Ok(Connection::with_db(database::Database::with_config(path, config)?))
+    }
+
+    fn with_db(db: database::Database) -> Self {
+        Connection {
+            db,
+            context: context::Context::new(Box::new(context::CollectOutFn::new()))
+        }
+    }
+
+    // executes a statement ; rows of a select are dropped.
+    pub fn execute(&mut self, sql: &str) -> Result<(), error::Error> {
+        let statement = self.prepare(sql)?;
+        self.execute_prepared(&statement)
+    }
+
+    // executes a select and returns its rows ; other statements have no rows.
+    // Every row of the result is buffered with its values, overflow values too, before query returns :
+    // a select of a large table takes memory for the whole table. Use where and limit to bound it.
+    pub fn query(&mut self, sql: &str) -> Result<Rows, error::Error> {
+        let statement = self.prepare(sql)?;
+        self.query_prepared(&statement)
+    }
+
+    // statement with ? parameters which is bound and executed by execute_prepared or query_prepared.
+    pub fn prepare(&mut self, sql: &str) -> Result<statement::PreparedStatement, error::Error> {
+        sqlcommands::prepare(&mut self.context, sql)
+    }
+
+    pub fn execute_prepared(&mut self, statement: &statement::PreparedStatement) -> Result<(), error::Error> {
+        self.query_prepared(statement).map(|_| ())
+    }
+
+    // rows are buffered like rows of query.
+    pub fn query_prepared(&mut self, statement: &statement::PreparedStatement) -> Result<Rows, error::Error> {
+        let result = statement.execute(&mut self.context, &mut self.db);
+        // rows of a failed select are taken too, so that they do not show up in next query.
+        let (columns, rows) = self.context.take_collected().expect("connection collects rows of select");
+        result?;
+        Ok(Rows {
+            columns: columns.iter().map(|c| c.name().to_string()).collect(),
+            rows: rows.into_iter()
+        })
+    }
+
+    // key of last row inserted into a table keyed on an integer column ; 0 before any insert.
+    pub fn last_insert_rowid(&self) -> i64 {
+        self.db.last_insert_rowid()
+    }
+}
+
+impl Rows {
+    // names of selected columns ; an expression without alias is named by its text.
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    // columns are matched ignoring case.
+    pub fn column_position(&self, name: &str) -> Result<usize, error::Error> {
+        self.columns.iter()
+            .position(|c| c.eq_ignore_ascii_case(name))
+            .ok_or_else(|| error::Error::NoSuchColumn(name.to_string()))
+    }
+}
+
+impl Iterator for Rows {
+    type Item = row::Row;
+
+    fn next(&mut self) -> Option<row::Row> {
+        self.rows.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::Path};
+
+    #[test]
+    fn test_connection_query()
+    {
+        let db_filename = "test_connection.db";
+        if Path::new(db_filename).exists() {
+            fs::remove_file(db_filename).expect("Should be able to delete db file before starting test");
+        }
+
+        {
+            let mut conn = Connection::open(db_filename).expect("Unable to open connection");
+            conn.execute("create table items (id integer primary key autoincrement, name text, price real, stock bigint)").expect("create should work");
+            let mut insert = conn.prepare("insert into items (name, price, stock) values (?, ?, ?)").expect("insert should prepare");
+            for (name, price) in [("pen", 1.5), ("ink", 7.0)].iter() {
+                insert.bind(1, row::Value::Text(name.to_string())).expect("bind should work");
+                insert.bind(2, row::Value::Real(*price)).expect("bind should work");
+                insert.bind(3, row::Value::Null).expect("bind should work");
+                conn.execute_prepared(&insert).expect("insert should work");
+            }
+            assert!(conn.last_insert_rowid() == 2, "{}", conn.last_insert_rowid());
+            assert!(conn.execute("insert into items values (1, 'pen', 1.0, 0)") ==
+                Err(error::Error::unique_violation("items", "id", &row::Value::Integer(1))), "errors are returned");
+        }
+
+        let mut conn = Connection::open(db_filename).expect("Unable to open connection");
+        let rows = conn.query("select id, name as title, price * 2, stock from items order by price desc").expect("query should work");
+        assert!(rows.columns() == ["id", "title", "price * 2", "stock"], "{:?}", rows.columns());
+        assert!(rows.column_position("TITLE") == Ok(1) && rows.column_position("name").is_err());
+
+        let items = rows
+            .map(|row| Ok((row.get_as::<i64>(0)?, row.get_as::<String>(1)?, row.get_as::<f64>(2)?, row.get_as::<Option<i64>>(3)?)))
+            .collect::<Result<Vec<_>, String>>()
+            .expect("values should have types");
+        assert!(items == vec![(2, String::from("ink"), 14.0, None), (1, String::from("pen"), 3.0, None)], "{:?}", items);
+
+        let mut row = conn.query("select name from items where id = 1").expect("query should work");
+        let row = row.next().expect("row with id 1");
+        assert!(row.get_as::<i32>(0).is_err() && row.get_as::<String>(1).is_err(), "type and position are checked");
+
+        let rows = conn.query("select * from items where id > 5").expect("query should work");
+        assert!(rows.columns().len() == 4 && rows.count() == 0, "a query without rows has columns");
+        assert!(conn.query("delete from items where id = 2").expect("delete should work").count() == 0);
+        assert!(conn.query("select * from missing").is_err());
+        assert!(conn.query("select id from items").expect("query should work").count() == 1, "rows of one query are not seen by next");
+
+        fs::remove_file(db_filename).expect("Unable to delete test db");
+    }
+}
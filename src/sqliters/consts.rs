@@ -1,19 +1,62 @@
 use std::mem;
 
-// Struct related
-pub const ID_SIZE: usize = mem::size_of::<i32>();
-pub const USERNAME_SIZE: usize = 32;
-pub const EMAIL_SIZE: usize = 32;
-
-pub const ID_OFFSET: usize = 0;
-pub const USERNAME_OFFSET: usize = ID_OFFSET + ID_SIZE;
-pub const EMAIL_OFFSET: usize = USERNAME_OFFSET + USERNAME_SIZE;
-pub const INSERT_STATEMENT_SIZE: usize = EMAIL_OFFSET + EMAIL_SIZE;
+// Column types
+// Numbers are big endian ; reals are IEEE 754 doubles and timestamps are seconds since unix epoch.
+pub const INTEGER_SIZE: usize = mem::size_of::<i32>();
+pub const BIGINT_SIZE: usize = mem::size_of::<i64>();
+pub const REAL_SIZE: usize = mem::size_of::<f64>();
+pub const BOOLEAN_SIZE: usize = mem::size_of::<u8>();
+pub const TIMESTAMP_SIZE: usize = mem::size_of::<i64>();
+// Text of a maximum size : LENGTH : BYTES ; only bytes of the value are stored.
+pub const TEXT_LENGTH_SIZE: usize = mem::size_of::<u16>();
+// Variable length text and blob : LENGTH : FIRST OVERFLOW PAGE NUM : LOCAL BYTES
+// A value is kept whole in its cell when the row fits in the largest local cell of the page ; page num 0 means
+// that the value has no chain. Otherwise bytes after a prefix of VARLEN_PREFIX_SIZE go to a chain of overflow pages.
+pub const VARLEN_LENGTH_SIZE: usize = mem::size_of::<u32>();
+pub const OVERFLOW_PAGE_NUM_SIZE: usize = mem::size_of::<u64>();
+pub const VARLEN_HEADER_SIZE: usize = VARLEN_LENGTH_SIZE + OVERFLOW_PAGE_NUM_SIZE;
+pub const VARLEN_PREFIX_SIZE: usize = 32;
+pub const VARLEN_SIZE: usize = VARLEN_HEADER_SIZE + VARLEN_PREFIX_SIZE;
 
 // Page
-pub const PAGE_SIZE: usize = 2046;
-pub const TABLE_MAX_PAGES: usize = 12;
-pub const ROW_SIZE: usize = INSERT_STATEMENT_SIZE;
+// Page size is chosen when a db file is created and kept in its header ; it is a power of two in this range.
+pub const DEFAULT_PAGE_SIZE: usize = 4096;
+pub const MIN_PAGE_SIZE: usize = 512;
+pub const MAX_PAGE_SIZE: usize = 65536;
+// pages of db file which are kept in memory.
+pub const PAGE_CACHE_SIZE: usize = 256;
+// Rows of order by are sorted in memory up to these many pages ; more rows spill to a temporary file.
+pub const SORT_MEMORY_PAGES: usize = 4;
+// Spilled runs merged at once ; each run being merged keeps a sort file open.
+pub const SORT_MERGE_RUNS: usize = 64;
+
+// Statements
+// Parsed statements are cached by their sql text ; least recently used ones are evicted beyond this many.
+pub const STATEMENT_CACHE_SIZE: usize = 32;
+
+// Header page
+// Page 0 of db file : MAGIC : FORMAT VERSION : PAGE SIZE : PAGE COUNT : FREELIST HEAD : SCHEMA ROOT ; rest of the page is unused.
+// Page count includes the header page and schema root is the root page of the catalog.
+// Free pages make a list from freelist head through their next sibling. Page num 0 ends it, like it ends
+// overflow chains and leaves, as page 0 is never a page of a B+tree.
+pub const HEADER_PAGE_NUM: u64 = 0;
+pub const DB_MAGIC: &[u8] = b"sqliters format\0";
+pub const FORMAT_VERSION: u32 = 1;
+pub const MAGIC_OFFSET: usize = 0;
+pub const MAGIC_SIZE: usize = 16;
+pub const FORMAT_VERSION_OFFSET: usize = MAGIC_OFFSET + MAGIC_SIZE;
+pub const FORMAT_VERSION_SIZE: usize = mem::size_of::<u32>();
+pub const DB_PAGE_SIZE_OFFSET: usize = FORMAT_VERSION_OFFSET + FORMAT_VERSION_SIZE;
+pub const DB_PAGE_SIZE_SIZE: usize = mem::size_of::<u32>();
+pub const PAGE_COUNT_OFFSET: usize = DB_PAGE_SIZE_OFFSET + DB_PAGE_SIZE_SIZE;
+pub const PAGE_COUNT_SIZE: usize = mem::size_of::<u64>();
+pub const FREELIST_HEAD_OFFSET: usize = PAGE_COUNT_OFFSET + PAGE_COUNT_SIZE;
+pub const FREELIST_HEAD_SIZE: usize = mem::size_of::<u64>();
+pub const SCHEMA_ROOT_OFFSET: usize = FREELIST_HEAD_OFFSET + FREELIST_HEAD_SIZE;
+pub const SCHEMA_ROOT_SIZE: usize = mem::size_of::<u64>();
+pub const DB_HEADER_SIZE: usize = SCHEMA_ROOT_OFFSET + SCHEMA_ROOT_SIZE;
+// Files written before the header page are pages of this size with the catalog root at page 0.
+pub const HEADERLESS_PAGE_SIZE: usize = 2046;
 
 // Header size
 pub const PAGE_TYPE_SIZE: usize = mem::size_of::<u8>();
@@ -24,42 +67,57 @@ pub const NUM_ENTRIES_SIZE: usize = mem::size_of::<u64>();
 pub const NUM_ENTRIES_OFFSET: usize = IS_ROOT_OFFSET + IS_ROOT_SIZE;
 pub const NEXT_LEAF_NODE_NUM_SIZE: usize = mem::size_of::<u64>();
 pub const NEXT_LEAF_NODE_OFFSET: usize = NUM_ENTRIES_OFFSET + NUM_ENTRIES_SIZE;
-pub const PAGE_HEADER_SIZE: usize = NEXT_LEAF_NODE_OFFSET + NEXT_LEAF_NODE_NUM_SIZE;
+pub const CELL_SIZE_SIZE: usize = mem::size_of::<u64>();
+pub const CELL_SIZE_OFFSET: usize = NEXT_LEAF_NODE_OFFSET + NEXT_LEAF_NODE_NUM_SIZE;
+pub const KEY_SIZE_SIZE: usize = mem::size_of::<u16>();
+pub const KEY_SIZE_OFFSET: usize = CELL_SIZE_OFFSET + CELL_SIZE_SIZE;
+pub const PAGE_HEADER_SIZE: usize = KEY_SIZE_OFFSET + KEY_SIZE_SIZE;
 
-// Leaf node : Offsets in body
-pub const KEY_SIZE: usize = ID_SIZE;
+// Leaf node : slotted page
+// HEADER : CELL POINTERS -> free space <- CELL CONTENT
+// Cell pointers are kept in key order ; each has the offset and size of its cell.
+// Cell content grows from the end of page, so cells can have different sizes.
+// Cell size in the page header is the size of the largest cell of the B+tree.
+// Keys are compared as bytes ; size of keys of a B+tree is stored in the page header.
+// Tables are keyed on their primary key columns ; keys of indexes also have the primary key.
 pub const KEY_OFFSET: usize = 0;
-pub const VALUE_SIZE: usize = ROW_SIZE;
-pub const VALUE_OFFSET: usize = KEY_OFFSET + KEY_SIZE;
-pub const CELL_SIZE: usize = VALUE_OFFSET + VALUE_SIZE;
-pub const CELLS_PER_PAGE: usize = (PAGE_SIZE - PAGE_HEADER_SIZE) / CELL_SIZE;
-
-pub const TABLE_MAX_ROWS: usize = 153; // CELLS_PER_PAGE * TABLE_MAX_PAGES;
+pub const CELL_POINTER_OFFSET_SIZE: usize = mem::size_of::<u16>();
+pub const CELL_POINTER_SIZE_SIZE: usize = mem::size_of::<u16>();
+pub const CELL_POINTER_SIZE: usize = CELL_POINTER_OFFSET_SIZE + CELL_POINTER_SIZE_SIZE;
+pub const CELL_POINTER_ARRAY_OFFSET: usize = PAGE_HEADER_SIZE;
 
 pub const LEAF_NODE_TYPE: u8 = 1;
 pub const NONLEAF_NODE_TYPE: u8 = LEAF_NODE_TYPE + 1;
+pub const FREE_PAGE_TYPE: u8 = NONLEAF_NODE_TYPE + 1;
+pub const OVERFLOW_PAGE_TYPE: u8 = FREE_PAGE_TYPE + 1;
 pub const IS_ROOT_TYPE: u8 = 67;
 pub const NON_ROOT_TYPE: u8 = IS_ROOT_TYPE - 1;
 
 // Internal node
 pub const INTERNAL_NODE_PAGE_NUM_SIZE: usize = mem::size_of::<u64>();
-pub const INTERNAL_NODE_KEY_SIZE: usize = ID_SIZE;
 pub const INTERNAL_NODE_LEFT_PAGE_NUM_OFFSET: usize = 0;
 pub const INTERNAL_NODE_KEY_OFFSET: usize = INTERNAL_NODE_LEFT_PAGE_NUM_OFFSET + INTERNAL_NODE_PAGE_NUM_SIZE;
 
 // HEADER : RIGHT-MOST-PAGE-NUM : [ CELL [ LEFT : KEY ] ] : [ CELL [ LEFT : KEY ] ]
 // Wasting next_leaf_node_num_size in internal node header
+// Internal cell size is INTERNAL_NODE_PAGE_NUM_SIZE + key size of the B+tree.
 pub const INTERNAL_NODE_RIGHT_PAGE_NUM_OFFSET: usize = PAGE_HEADER_SIZE;
-pub const INTERNAL_NODE_CELL_SIZE: usize = INTERNAL_NODE_PAGE_NUM_SIZE + INTERNAL_NODE_KEY_SIZE;
 pub const INTERNAL_NODE_CELL_START_OFFSET: usize = PAGE_HEADER_SIZE + INTERNAL_NODE_PAGE_NUM_SIZE;
 
+// Overflow page
+// HEADER : PAYLOAD ; num cells of header is the number of payload bytes and next sibling is the next page of the chain.
+pub const OVERFLOW_PAYLOAD_OFFSET: usize = PAGE_HEADER_SIZE;
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use sqliters::{page, schema};
 
     #[test]
     fn test_consts() {
-        assert!(TABLE_MAX_ROWS == 153, "TABLE_MAX_ROWS {}", TABLE_MAX_ROWS);
-        assert!(CELLS_PER_PAGE == 28, "CELLS_PER_PAGE {}", CELLS_PER_PAGE);
+        let cell_size = schema::Schema::default_users().cell_size();
+        let cells_per_page = page::leaf_max_cells(DEFAULT_PAGE_SIZE, cell_size);
+        assert!(cells_per_page == 50, "cells_per_page {}", cells_per_page);
+        assert!(page::leaf_max_cells(MIN_PAGE_SIZE, cell_size) == 5 && page::leaf_max_cells(MAX_PAGE_SIZE, cell_size) == 808);
     }
 }
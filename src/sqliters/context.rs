@@ -1,15 +1,19 @@
-use sqliters::statement;
+use sqliters::{consts, row, schema, statement};
 use downcast_rs::Downcast;
 
 pub struct Context {
-    select_outfn: Box<OutFn>
+    select_outfn: Box<OutFn>,
+    statements: statement::StatementCache
 }
 
+// receives result rows of select ; values of row are in order of columns.
 pub trait OutFn: Downcast {
-    fn outfn(&mut self, &statement::InsertStatement);
+    fn outfn(&mut self, columns: &[schema::Column], row: &row::Row);
+
+    // called once before rows of a select, also when it has no rows.
+    fn columns(&mut self, _columns: &[schema::Column]) {}
 }
 
-#[cfg(test)]
 impl_downcast!(OutFn);
 
 pub struct ConsoleOutFn {}
@@ -21,20 +25,68 @@ impl ConsoleOutFn {
 }
 
 impl OutFn for ConsoleOutFn {
-    fn outfn(&mut self, insert: &statement::InsertStatement) {
-        println!("row: {}", insert)
+    fn outfn(&mut self, columns: &[schema::Column], row: &row::Row) {
+        let values: Vec<String> = columns.iter().zip(row.values().iter())
+            .map(|(column, value)| format!("{}: {}", column.name(), value))
+            .collect();
+        println!("row: ({})", values.join(", "))
+    }
+}
+
+// keeps result rows of select until they are taken ; all rows of a select are in memory at once.
+pub struct CollectOutFn {
+    columns: Vec<schema::Column>,
+    rows: Vec<row::Row>
+}
+
+impl CollectOutFn {
+    pub fn new() -> Self {
+        CollectOutFn {
+            columns: vec![],
+            rows: vec![]
+        }
+    }
+
+    // columns and rows of last select ; rows are cleared.
+    pub fn take(&mut self) -> (Vec<schema::Column>, Vec<row::Row>) {
+        (self.columns.drain(..).collect(), self.rows.drain(..).collect())
+    }
+}
+
+impl OutFn for CollectOutFn {
+    fn outfn(&mut self, _columns: &[schema::Column], row: &row::Row) {
+        self.rows.push(row.clone())
+    }
+
+    fn columns(&mut self, columns: &[schema::Column]) {
+        self.columns = columns.to_vec();
+        self.rows.clear();
     }
 }
 
 impl Context {
     pub fn new(select_outfn: Box<OutFn>) -> Self {
         Context {
-            select_outfn: select_outfn
+            select_outfn: select_outfn,
+            statements: statement::StatementCache::new(consts::STATEMENT_CACHE_SIZE)
         }
     }
 
-    pub fn select_out(&mut self, insert: &statement::InsertStatement) {
-        self.select_outfn.outfn(insert)
+    pub fn statements(&mut self) -> &mut statement::StatementCache {
+        &mut self.statements
+    }
+
+    pub fn select_columns(&mut self, columns: &[schema::Column]) {
+        self.select_outfn.columns(columns)
+    }
+
+    pub fn select_out(&mut self, columns: &[schema::Column], row: &row::Row) {
+        self.select_outfn.outfn(columns, row)
+    }
+
+    // columns and rows which were collected since last take ; None when rows of select are not collected.
+    pub fn take_collected(&mut self) -> Option<(Vec<schema::Column>, Vec<row::Row>)> {
+        self.select_outfn.downcast_mut::<CollectOutFn>().map(|out| out.take())
     }
 
     #[cfg(test)]
@@ -1,130 +1,247 @@
-use std;
-use sqliters::{table, page, consts};
+use sqliters::{pager, table, page, consts, btree, row, schema, error};
 
 #[derive(Debug)]
 pub struct Cursor<'a> {
-    table: &'a mut table::Table,
+    pager: &'a mut pager::Pager,
+    root_page_num: u64,
     page_num: u64, // 0..N
     cell_num: u64,
-    end_of_table: bool
+    end_of_table: bool,
+    // (page_num, child_pos) of internal nodes from root to leaf ; only kept by backward cursors
+    // since leaves link only to their next sibling.
+    path: Vec<(u64, u64)>
 }
 
 impl<'a> Cursor<'a> {
-    pub fn table_start(table: &'a mut table::Table) -> Result<Self, String> {
-        let num_pages = table.num_pages();
-        let mut page_num = 0;
-        let mut cell_num = 0;
-        if num_pages != 0 {
-            let key_pos = table.find_key_pos(std::i32::MIN)?; // go to last leaf.
-            page_num = key_pos.0;
-            cell_num = key_pos.1;
-        }
+    pub fn table_start(pager: &'a mut pager::Pager, table: &table::Table) -> Result<Self, error::Error> {
+        // go to first leaf.
+        Cursor::table_seek(pager, table, &vec![0; table.schema().key_size()])
+    }
+
+    // points to first row with key >= given key.
+    pub fn table_seek(pager: &'a mut pager::Pager, table: &table::Table, key: &[u8]) -> Result<Self, error::Error> {
+        Cursor::seek(pager, table.root_page_num(), key)
+    }
+
+    // points to first cell with key >= given key in B+tree rooted at root_page_num.
+    pub fn seek(pager: &'a mut pager::Pager, root_page_num: u64, key: &[u8]) -> Result<Self, error::Error> {
+        let (page_num, cell_num) = btree::find_key_pos(pager, root_page_num, key)?;
+
+        let mut cursor = Cursor {
+            pager,
+            root_page_num,
+            page_num,
+            cell_num,
+            end_of_table: false,
+            path: vec![]
+        };
+        // key can be larger than all keys of its leaf.
+        cursor.skip_exhausted_pages()?;
+        Ok(cursor)
+    }
+
+    pub fn table_find(pager: &'a mut pager::Pager, table: &table::Table, key: &[u8]) -> Result<Self, error::Error> {
+        Cursor::find(pager, table.root_page_num(), key)
+    }
+
+    // points to the position where key is or should be inserted ; it does not move forward.
+    pub fn find(pager: &'a mut pager::Pager, root_page_num: u64, key: &[u8]) -> Result<Self, error::Error> {
+        let (page_num, cell_num) = btree::find_key_pos(pager, root_page_num, key)?;
 
         Ok(Cursor {
-            table: table,
-            page_num: page_num,
-            cell_num: cell_num,
-            end_of_table: num_pages == 0
+            pager,
+            root_page_num,
+            page_num, // page_num is index.
+            cell_num,
+            end_of_table: true, // we don't want to advance ahead.
+            path: vec![]
         })
     }
 
-    pub fn table_find(table: &'a mut table::Table, key: i32) -> Result<Self, String> {
-        let num_pages = table.num_pages();
-        let mut page_num = 0;
-        let mut cell_num = 0;
-        if num_pages != 0 {
-            let key_pos = table.find_key_pos(key)?;
-            page_num = key_pos.0;
-            cell_num = key_pos.1;
+    // points to last row with key <= given key ; retreat_cursor moves to smaller keys.
+    pub fn table_seek_back(pager: &'a mut pager::Pager, table: &table::Table, key: &[u8]) -> Result<Self, error::Error> {
+        let root_page_num = table.root_page_num();
+        let mut path = vec![];
+        let mut page_num = root_page_num;
+        loop {
+            let page = pager.get_page(page_num as usize)?;
+            if page.is_leaf() {
+                break;
+            }
+            let pos = page.find_key_pos(key);
+            path.push((page_num, pos));
+            page_num = page.get_page_num(pos);
         }
 
-        // println!("For key {}, num_pages {}, cell_num {}", key, num_pages, cell_num);
+        // one past the last cell with key <= given key.
+        let cell_num = {
+            let page = pager.get_page(page_num as usize)?;
+            let pos = page.find_key_pos(key);
+            if pos < page.num_cells() && page.get_key_at(pos) == key { pos + 1 } else { pos }
+        };
+
+        let mut cursor = Cursor {
+            pager,
+            root_page_num,
+            page_num,
+            cell_num,
+            end_of_table: false,
+            path
+        };
+        cursor.step_back()?;
+        Ok(cursor)
+    }
 
-        Ok(Cursor {
-            table: table,
-            page_num: page_num, // page_num is index.
-            cell_num: cell_num,
-            end_of_table: true // we don't want to advance ahead.
-        })
+    pub fn cell_key(&mut self) -> Result<Vec<u8>, error::Error> {
+        let key_size = self.pager.get_page(self.page_num as usize)?.key_size();
+        self.cell_slot()
+            .map(|c| c[consts::KEY_OFFSET .. consts::KEY_OFFSET + key_size].to_vec())
     }
 
-    pub fn cursor_value(&mut self) -> Result<&mut[u8], String> {
+    // true when cursor points to the cell of key.
+    pub fn is_at(&mut self, key: &[u8]) -> Result<bool, error::Error> {
+        let page = self.pager.get_page(self.page_num as usize)?;
+        Ok(self.cell_num < page.num_cells() && page.get_key_at(self.cell_num) == key)
+    }
+
+    pub fn cursor_value(&mut self) -> Result<&[u8], error::Error> {
+        let key_size = self.pager.get_page(self.page_num as usize)?.key_size();
         self.cell_slot()
-            .map(|c| &mut c[consts::VALUE_OFFSET..])
+            .map(|c| &c[consts::KEY_OFFSET + key_size ..])
+    }
+
+    // row of the cell ; parts of values which are not in the cell are read from overflow pages.
+    pub fn cursor_row(&mut self, schema: &schema::Schema) -> Result<row::Row, error::Error> {
+        let serialized = self.cursor_value()?.to_vec();
+        row::deserialize_row(self.pager, schema, &serialized)
     }
 
-    pub fn advance_cursor(&mut self) -> Result<(), String> {
+    pub fn advance_cursor(&mut self) -> Result<(), error::Error> {
         if !self.end_of_table {
             self.cell_num += 1;
-            loop {
-                let page = self.table.get_page(self.page_num as usize)?;
-                if self.cell_num >= page.num_cells() as u64 {
-                    let old_page_num = self.page_num;
-                    self.page_num = page.next_sibling_num();
-                    // value 0 means that no next_sibling.
-                    if self.page_num == 0 {
+            self.skip_exhausted_pages()?;
+        }
+        Ok(())
+    }
+
+    pub fn retreat_cursor(&mut self) -> Result<(), error::Error> {
+        if !self.end_of_table {
+            self.step_back()?;
+        }
+        Ok(())
+    }
+
+    // moves to the cell before cell_num ; goes up the path to the previous leaf when cell_num is 0.
+    fn step_back(&mut self) -> Result<(), error::Error> {
+        loop {
+            if self.cell_num > 0 {
+                self.cell_num -= 1;
+                return Ok(())
+            }
+
+            // nearest ancestor which has a child left of the path.
+            let (page_num, child_pos) = loop {
+                match self.path.pop() {
+                    Some((page_num, child_pos)) if child_pos > 0 => break (page_num, child_pos - 1),
+                    Some(_) => continue,
+                    None => {
                         self.end_of_table = true;
                         return Ok(())
                     }
-
-                    assert!(old_page_num != self.page_num, "{} referes to next_sibling {}", old_page_num, self.page_num);
-                    // otherwise, since cells are sorted, start from next page's 0th cell_num
-                    self.cell_num = 0;
-                    continue;
                 }
+            };
 
-                break;
+            // rightmost leaf of that child.
+            self.path.push((page_num, child_pos));
+            self.page_num = self.pager.get_page(page_num as usize)?.get_page_num(child_pos);
+            loop {
+                let page = self.pager.get_page(self.page_num as usize)?;
+                if page.is_leaf() {
+                    self.cell_num = page.num_cells();
+                    break;
+                }
+                let right_pos = page.num_cells();
+                self.path.push((self.page_num, right_pos));
+                self.page_num = page.get_page_num(right_pos);
             }
         }
-        Ok(())
     }
 
-    pub fn serialize_row_add(&mut self, data: Vec<u8>) -> Result<(), String> {
-        let key = page::deserialize_key(&data[0 .. consts::KEY_SIZE]);
-
-        if self.table.get_page(self.page_num as usize)?.num_cells() >= consts::CELLS_PER_PAGE as u64 {
-            // split this page.
-            self.table.split_page(self.page_num)?;
-            let key_pos = self.table.find_key_pos(key)?;
-            self.page_num = key_pos.0;
-            self.cell_num = key_pos.1;
-        }
+    // moves to next sibling leaf till cursor points to a cell.
+    fn skip_exhausted_pages(&mut self) -> Result<(), error::Error> {
+        loop {
+            let page = self.pager.get_page(self.page_num as usize)?;
+            if self.cell_num < page.num_cells() {
+                return Ok(())
+            }
 
-        self.add_row(key, data)?;
-        self.advance_cursor()
-    }
+            let old_page_num = self.page_num;
+            self.page_num = page.next_sibling_num();
+            // value 0 means that no next_sibling.
+            if self.page_num == 0 {
+                self.end_of_table = true;
+                return Ok(())
+            }
 
-    pub fn end_of_table(&self) -> bool {
-        self.end_of_table
+            assert!(old_page_num != self.page_num, "{} referes to next_sibling {}", old_page_num, self.page_num);
+            // otherwise, since cells are sorted, start from next page's 0th cell_num
+            self.cell_num = 0;
+        }
     }
 
-    fn cell_slot(&mut self) -> Result<&mut [u8], String> {
-        if self.cell_num >= consts::TABLE_MAX_ROWS as u64 {
-            // currently we have only one page..
-            return Err(format!("{} row is out of space allocated to table {}", self.cell_num, consts::TABLE_MAX_ROWS))
+    // adds a cell at cursor position ; cursor should come from find for the same key.
+    pub fn insert(&mut self, key: &[u8], data: Vec<u8>) -> Result<(), error::Error> {
+        {
+            let page = self.pager.get_page(self.page_num as usize)?;
+            if key.len() != page.key_size() || key.len() + data.len() > page.cell_size() {
+                return Err(error::Error::Other(format!("Can't store a key of size {} and data of size {} in cell of size {}", key.len(), data.len(), page.cell_size())))
+            }
+            if self.cell_num < page.num_cells() && key == page.get_key_at(self.cell_num) {
+                return Err(error::Error::duplicate_key(key))
+            }
         }
 
-        let row_offset = consts::PAGE_HEADER_SIZE + (self.cell_num as usize * consts::CELL_SIZE);
-        let page = self.table.get_page(self.page_num as usize)?;
-
-        return Ok(&mut page.get_data()[row_offset .. row_offset + consts::CELL_SIZE])
+        // leaf is split when the cell does not fit ; cell can end up in the new page.
+        btree::insert_cell(self.pager, self.root_page_num, key, &page::leaf_node_cell(key, &data))?;
+        let key_pos = btree::find_key_pos(self.pager, self.root_page_num, key)?;
+        self.page_num = key_pos.0;
+        self.cell_num = key_pos.1;
+        self.advance_cursor()
     }
 
-    fn add_row(&mut self, key: i32, data: Vec<u8>) -> Result<(), String> {
-        if data.len() != consts::ROW_SIZE {
-            return Err(format!("Can't store a data of size {} != {}", data.len(), consts::ROW_SIZE))
+    // replaces value of the cell at cursor ; a value of another size which does not fit in the page,
+    // or leaves it under full, is moved through the B+tree.
+    pub fn replace_value(&mut self, data: Vec<u8>) -> Result<(), error::Error> {
+        let key = self.cell_key()?;
+        let cell = page::leaf_node_cell(&key, &data);
+        {
+            let page = self.pager.get_page(self.page_num as usize)?;
+            if cell.len() > page.cell_size() {
+                return Err(error::Error::Other(format!("Can't store data of size {} in cell of size {}", data.len(), page.cell_size())))
+            }
+            if page.update_data(self.cell_num, &cell).is_ok() && (self.page_num == self.root_page_num || !page.is_under_full()) {
+                return Ok(())
+            }
         }
 
-        let page = self.table.get_page(self.page_num as usize)?;
+        btree::delete_key(self.pager, self.root_page_num, &key)?;
+        btree::insert_cell(self.pager, self.root_page_num, &key, &cell)?;
+        let key_pos = btree::find_key_pos(self.pager, self.root_page_num, &key)?;
+        self.page_num = key_pos.0;
+        self.cell_num = key_pos.1;
+        Ok(())
+    }
 
-        // println!("adding row : key: {}, page_num {}, cell_num {}, page {:?}", key, self.page_num, self.cell_num, page);
+    pub fn end_of_table(&self) -> bool {
+        self.end_of_table
+    }
 
-        if key == page.get_key_at(self.cell_num) {
-            return Err(format!("Can not insert duplicate keys {}; Already present at pos: {}", key, self.cell_num))
+    fn cell_slot(&mut self) -> Result<&[u8], error::Error> {
+        let page = self.pager.get_page(self.page_num as usize)?;
+        if self.cell_num >= page.num_cells() {
+            return Err(error::Error::Other(format!("{} cell is out of {} cells of page {}", self.cell_num, page.num_cells(), self.page_num)))
         }
 
-        page.add_data(self.cell_num, &data)?;
-
-        Ok(())
+        Ok(page.leaf_cell(self.cell_num))
     }
 }
\ No newline at end of file
@@ -0,0 +1,339 @@
+use std::convert::TryFrom;
+use sqliters::{pager, page, replacement, consts, schema, table, index, cursor, btree, row, parser, ast, error};
+use sqliters::schema::{Column, ColumnType};
+
+// Name of the catalog table. Like sqlite_master it has one row per table and index :
+// id : name : root page num of its B+tree : create table / create index sql.
+// Rows are loaded in id order, so a table is loaded before its indexes.
+pub const CATALOG_TABLE_NAME: &str = "sqliters_master";
+// Root page of catalog B+tree is the schema root of the header page.
+const CATALOG_NAME_SIZE: usize = 32;
+// Like sqlite_sequence it has the largest id given to rows of each table with an AUTOINCREMENT key :
+// table name : largest id. It is created with the first such table.
+pub const SEQUENCE_TABLE_NAME: &str = "sqliters_sequence";
+
+// How a db file is opened. Page size is only used when the file is created ; an existing file
+// keeps the page size of its header.
+pub struct Config {
+    pub page_size: usize,
+    // at most these many pages of the db file are kept in memory.
+    pub cache_size: usize,
+    // chooses the page which leaves the cache when it is full.
+    pub policy: Box<dyn replacement::ReplacementPolicy>
+}
+
+#[derive(Debug)]
+pub struct Database {
+    pager: pager::Pager,
+    catalog: table::Table,
+    tables: Vec<table::Table>,
+    next_table_id: i32,
+    // key of the last row inserted into a table keyed on an integer column.
+    last_insert_rowid: i64
+}
+
+impl Database {
+    // opens the db file and loads all tables from the catalog.
+    pub fn new(db_filepath: &str) -> Result<Self, error::Error> {
+        Database::with_config(db_filepath, Config::default())
+    }
+
+    pub fn with_config(db_filepath: &str, config: Config) -> Result<Self, error::Error> {
+        let mut pager = pager::Pager::new(config.page_size, config.cache_size, config.policy, db_filepath)?;
+        let schema = catalog_schema();
+        if pager.schema_root() == 0 {
+            let page_size = pager.page_size();
+            let root_page_num = pager.new_page(page::Page::new_leaf(true, page_size, schema.key_size(), schema.leaf_cell_size(page_size)))?;
+            pager.set_schema_root(root_page_num);
+        }
+        let catalog = table::Table::new(CATALOG_TABLE_NAME, pager.schema_root(), schema);
+
+        let mut db = Database {
+            pager,
+            catalog,
+            tables: vec![],
+            next_table_id: 1,
+            last_insert_rowid: 0
+        };
+        db.load_tables()?;
+        Ok(db)
+    }
+
+    pub fn create_table(&mut self, name: &str, schema: schema::Schema) -> Result<(), error::Error> {
+        self.check_name_is_free(name)?;
+
+        let page_size = self.pager.page_size();
+        if page::leaf_max_cells(page_size, schema.cell_size()) < 2 {
+            return Err(error::Error::Other(format!("Row size {} of table '{}' is too big : atleast 2 rows should fit in a page", schema.row_size(), name)))
+        }
+        if page::internal_max_cells(page_size, schema.key_size()) < 2 {
+            return Err(error::Error::Other(format!("Key size {} of table '{}' is too big : atleast 2 keys should fit in a page", schema.key_size(), name)))
+        }
+
+        // sequence table comes first, so that a table with autoincrement is never without it.
+        let autoincrement = schema.columns().iter().any(|c| c.is_autoincrement());
+        if autoincrement && self.get_table(SEQUENCE_TABLE_NAME).is_err() {
+            self.create_table(SEQUENCE_TABLE_NAME, sequence_schema())?;
+        }
+
+        let root_page_num = self.pager.new_page(page::Page::new_leaf(true, page_size, schema.key_size(), schema.leaf_cell_size(page_size)))?;
+        let sql = format!("create table {} {}", schema::quote_identifier(name), schema);
+        if let Err(e) = self.add_catalog_row(name, root_page_num, sql) {
+            self.pager.free_page(root_page_num)?;
+            return Err(e)
+        }
+        self.tables.push(table::Table::new(name, root_page_num, schema));
+        Ok(())
+    }
+
+    // builds the index from rows already in the table ; rows should have distinct values for a unique index.
+    // Pages of the index are freed when it fails.
+    pub fn create_index(&mut self, name: &str, table_name: &str, column_name: &str, unique: bool) -> Result<(), error::Error> {
+        let table_pos = self.tables.iter()
+            .position(|t| t.name().eq_ignore_ascii_case(table_name))
+            .ok_or_else(|| error::Error::NoSuchTable(table_name.to_string()))?;
+        let table = self.tables[table_pos].clone();
+        self.check_new_index(name, table.schema(), column_name)?;
+
+        let column = table.schema().columns()[table.schema().column_position(column_name)?].clone();
+        let key_size = index::key_size(column.column_type(), table.schema());
+        let page_size = self.pager.page_size();
+
+        let root_page_num = self.pager.new_page(page::Page::new_leaf(true, page_size, key_size, key_size))?;
+        let index = index::Index::new(name, table.schema(), column_name, unique, root_page_num)?;
+        let sql = format!("create {}index {} on {} ({})", if unique { "unique " } else { "" }, schema::quote_identifier(name),
+                          schema::quote_identifier(table.name()), schema::quote_identifier(column.name()));
+        if let Err(e) = self.add_rows_to_index(&table, &index).and_then(|_| self.add_catalog_row(name, root_page_num, sql)) {
+            btree::free_tree(&mut self.pager, root_page_num)?;
+            return Err(e)
+        }
+        self.tables[table_pos].add_index(index);
+        Ok(())
+    }
+
+    // errors create_index would return before writing anything for an index of a table with schema ;
+    // the table need not exist yet, so that indexes of a new table are checked before the table is created.
+    pub fn check_new_index(&self, name: &str, schema: &schema::Schema, column_name: &str) -> Result<(), error::Error> {
+        self.check_name_is_free(name)?;
+        let column = &schema.columns()[schema.column_position(column_name)?];
+        let key_size = index::key_size(column.column_type(), schema);
+        let page_size = self.pager.page_size();
+        if page::leaf_max_cells(page_size, key_size) < 2 || page::internal_max_cells(page_size, key_size) < 2 {
+            return Err(error::Error::Other(format!("Column '{}' of size {} is too big to be indexed", column_name, column.column_type().size())))
+        }
+        Ok(())
+    }
+
+    // tables are looked up by name ignoring case.
+    pub fn get_table(&self, name: &str) -> Result<table::Table, error::Error> {
+        if name.eq_ignore_ascii_case(CATALOG_TABLE_NAME) {
+            return Ok(self.catalog.clone())
+        }
+
+        self.tables.iter()
+            .find(|t| t.name().eq_ignore_ascii_case(name))
+            .cloned()
+            .ok_or_else(|| error::Error::NoSuchTable(name.to_string()))
+    }
+
+    pub fn tables(&self) -> &[table::Table] {
+        &self.tables
+    }
+
+    pub fn pager(&mut self) -> &mut pager::Pager {
+        &mut self.pager
+    }
+
+    pub fn last_insert_rowid(&self) -> i64 {
+        self.last_insert_rowid
+    }
+
+    pub fn set_last_insert_rowid(&mut self, rowid: i64) {
+        self.last_insert_rowid = rowid;
+    }
+
+    pub fn delete_db(&mut self) -> Result<(), error::Error> {
+        self.pager.delete_db_file()
+    }
+
+    pub fn close_db(&mut self) -> Result<(), error::Error> {
+        self.pager.flush_pages()?;
+        self.pager.close_db()
+    }
+
+    pub fn print(&self) -> bool {
+        self.pager.print();
+        true
+    }
+
+    // tables and indexes share names ; names are kept in a text column of catalog.
+    fn check_name_is_free(&self, name: &str) -> Result<(), error::Error> {
+        if name.len() > CATALOG_NAME_SIZE {
+            return Err(error::Error::Other(format!("Name '{}' of {} bytes is too long : names should be atmost {} bytes", name, name.len(), CATALOG_NAME_SIZE)))
+        }
+        if self.get_table(name).is_ok() {
+            return Err(error::Error::already_exists("Table", name))
+        }
+        if self.tables.iter().flat_map(|t| t.indexes()).any(|i| i.name().eq_ignore_ascii_case(name)) {
+            return Err(error::Error::already_exists("Index", name))
+        }
+        Ok(())
+    }
+
+    // rows are read one at a time, so that a big table is not read into memory ; the cursor is found
+    // again for each row as adding it to the index needs the pager.
+    fn add_rows_to_index(&mut self, table: &table::Table, index: &index::Index) -> Result<(), error::Error> {
+        let mut last_key: Option<Vec<u8>> = None;
+        loop {
+            let row = {
+                let mut cursor = match last_key {
+                    None => cursor::Cursor::table_start(&mut self.pager, table)?,
+                    Some(ref key) => {
+                        // rows are not changed, so the cursor is at last_key.
+                        let mut cursor = cursor::Cursor::table_seek(&mut self.pager, table, key)?;
+                        cursor.advance_cursor()?;
+                        cursor
+                    }
+                };
+                if cursor.end_of_table() {
+                    return Ok(())
+                }
+                cursor.cursor_row(table.schema())?
+            };
+
+            let value = &row.values()[index.column_pos()];
+            if index.is_unique() && self.has_indexed_value(table, index, value)? {
+                return Err(error::Error::unique_violation(table.name(), index.column().name(), value))
+            }
+            index.add(&mut self.pager, table.schema(), &row)?;
+            last_key = Some(row.key(table.schema())?);
+        }
+    }
+
+    // true when a row in index has value ; index keys only have a prefix of long values.
+    fn has_indexed_value(&mut self, table: &table::Table, index: &index::Index, value: &row::Value) -> Result<bool, error::Error> {
+        for key in index.find(&mut self.pager, value)? {
+            let mut cursor = cursor::Cursor::table_find(&mut self.pager, table, &key)?;
+            if cursor.is_at(&key)? && cursor.cursor_row(table.schema())?.values()[index.column_pos()] == *value {
+                return Ok(true)
+            }
+        }
+        Ok(false)
+    }
+
+    // nothing is written when it fails.
+    fn add_catalog_row(&mut self, name: &str, root_page_num: u64, sql: String) -> Result<(), error::Error> {
+        let catalog_row = row::Row::new(vec![
+            row::Value::Integer(self.next_table_id),
+            row::Value::Text(name.to_string()),
+            root_page_value(root_page_num)?,
+            row::Value::Text(sql)
+        ]);
+        let key = catalog_row.key(self.catalog.schema())?;
+        let max_row_size = self.catalog.max_row_size(&mut self.pager)?;
+        let serialized = row::serialize_row(&mut self.pager, self.catalog.schema(), &catalog_row, max_row_size)?;
+        let inserted = cursor::Cursor::table_find(&mut self.pager, &self.catalog, &key)
+            .and_then(|mut cursor| cursor.insert(&key, serialized.clone()));
+        if let Err(e) = inserted {
+            row::free_overflow(&mut self.pager, self.catalog.schema(), &serialized)?;
+            return Err(e)
+        }
+
+        self.next_table_id += 1;
+        Ok(())
+    }
+
+    fn load_tables(&mut self) -> Result<(), error::Error> {
+        let catalog_schema = self.catalog.schema().clone();
+        let mut catalog_rows = vec![];
+        {
+            let mut cursor = cursor::Cursor::table_start(&mut self.pager, &self.catalog)?;
+            while !cursor.end_of_table() {
+                catalog_rows.push(cursor.cursor_row(&catalog_schema)?);
+                cursor.advance_cursor()?;
+            }
+        }
+
+        for catalog_row in catalog_rows {
+            let values = catalog_row.values();
+            match (&values[0], &values[1], &values[2], &values[3]) {
+                (row::Value::Integer(id), row::Value::Text(name), row::Value::Integer(root_page_num), row::Value::Text(sql)) => {
+                    let root_page_num = u64::try_from(*root_page_num)
+                        .map_err(|_| error::Error::Corrupt(format!("Catalog of '{}' has root page {}", name, root_page_num)))?;
+                    match parser::parse(sql)? {
+                        ast::Statement::CreateTable(create) => {
+                            self.tables.push(table::Table::new(name, root_page_num, create.schema()?));
+                        },
+                        ast::Statement::CreateIndex(create) => {
+                            let table = self.tables.iter_mut()
+                                .find(|t| t.name().eq_ignore_ascii_case(&create.table_name))
+                                .ok_or_else(|| format!("Catalog of index '{}' refers to missing table '{}'", name, create.table_name))?;
+                            let index = index::Index::new(name, table.schema(), &create.column, create.unique, root_page_num)?;
+                            table.add_index(index);
+                        },
+                        _ => return Err(error::Error::Other(format!("Catalog of table '{}' has bad sql '{}'", name, sql)))
+                    }
+                    self.next_table_id = self.next_table_id.max(id + 1);
+                },
+                _ => return Err(error::Error::Other(format!("Bad catalog row {}", catalog_row)))
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            page_size: consts::DEFAULT_PAGE_SIZE,
+            cache_size: consts::PAGE_CACHE_SIZE,
+            // scans of tables do not evict pages of point lookups from a 2Q cache.
+            policy: Box::new(replacement::TwoQueue::new())
+        }
+    }
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        self.close_db().expect("Unable to close db.")
+    }
+}
+
+// root_page column of catalog is an integer ; a bigger page num would be truncated.
+fn root_page_value(root_page_num: u64) -> Result<row::Value, error::Error> {
+    i32::try_from(root_page_num)
+        .map(row::Value::Integer)
+        .map_err(|_| error::Error::Other(format!("Root page {} does not fit in the catalog : root pages should be below {}", root_page_num, i32::MAX)))
+}
+
+fn catalog_schema() -> schema::Schema {
+    let columns = vec![
+        Column::new("id", ColumnType::Integer),
+        Column::new("name", ColumnType::Text(CATALOG_NAME_SIZE)),
+        Column::new("root_page", ColumnType::Integer),
+        Column::new("sql", ColumnType::VarText)
+    ];
+    schema::Schema::new(columns, vec![0]).expect("catalog schema should be valid")
+}
+
+fn sequence_schema() -> schema::Schema {
+    let columns = vec![
+        Column::new("name", ColumnType::Text(CATALOG_NAME_SIZE)),
+        Column::new("seq", ColumnType::BigInt)
+    ];
+    schema::Schema::new(columns, vec![0]).expect("sequence schema should be valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root_page_value() {
+        assert!(root_page_value(7).unwrap() == row::Value::Integer(7));
+        assert!(root_page_value(i32::MAX as u64).unwrap() == row::Value::Integer(i32::MAX));
+        assert!(root_page_value(i32::MAX as u64 + 1).is_err());
+        assert!(root_page_value(u64::MAX).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use std::{error, fmt, io};
+use std::rc::Rc;
+use sqliters::{row, expr, schema};
+
+// Errors of sqliters. Failures which a caller may handle are variants of their own ; the rest
+// are still plain messages. Pure parts like parser and arithmetic on values give String errors,
+// which become Other when they reach storage or commands, except that parse errors become Parse.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    // sql text is not a statement.
+    Parse(String),
+    // row has values of primary key or unique columns which another row already has.
+    // columns are empty and the value is the key when only the B+tree knows of it.
+    UniqueViolation {
+        table: String,
+        columns: Vec<String>,
+        values: Vec<row::Value>
+    },
+    // row has null in a not null column.
+    NotNullViolation {
+        table: String,
+        column: String
+    },
+    // statement names a table which is not in the database.
+    NoSuchTable(String),
+    NoSuchColumn(String),
+    // table or index is created with the name of a table or index which is there ; kind is Table or Index.
+    AlreadyExists {
+        kind: String,
+        name: String
+    },
+    // value or expression has a type which the column or operator does not take ; the message tells which.
+    TypeMismatch(String),
+    // table definition which can not make a schema : no columns, duplicate names or a bad primary key.
+    InvalidSchema(String),
+    // disk has no room for more pages of the database file.
+    Full,
+    // reading or writing the database or a temporary file failed.
+    Io {
+        message: String,
+        source: IoSource
+    },
+    // bytes of the database file do not make a valid page, row or overflow chain.
+    Corrupt(String),
+    // file is not a sqliters database, or has a format which this version can not read.
+    NotADatabase(String),
+    // parameter of a prepared statement which is not there or has no value.
+    ParameterOutOfRange {
+        index: usize,
+        count: usize
+    },
+    UnboundParameter(usize),
+    Other(String)
+}
+
+// Numbers of errors for callers which only keep a code. They are the primary result codes of sqlite.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorCode {
+    Error = 1,
+    IoErr = 10,
+    Corrupt = 11,
+    Full = 13,
+    Constraint = 19,
+    Mismatch = 20,
+    Range = 25,
+    NotADb = 26
+}
+
+// io error which caused an Io error ; errors of same kind are equal.
+#[derive(Debug, Clone)]
+pub struct IoSource(Rc<io::Error>);
+
+impl Error {
+    pub fn unique_violation(table: &str, column: &str, value: &row::Value) -> Self {
+        Error::UniqueViolation {
+            table: table.to_string(),
+            columns: vec![column.to_string()],
+            values: vec![value.clone()]
+        }
+    }
+
+    // row has the primary key of another row.
+    pub fn key_violation(table: &str, schema: &schema::Schema, row: &row::Row) -> Self {
+        Error::UniqueViolation {
+            table: table.to_string(),
+            columns: schema.key_columns().iter().map(|&pos| schema.columns()[pos].name().to_string()).collect(),
+            values: row.key_values(schema)
+        }
+    }
+
+    pub fn already_exists(kind: &str, name: &str) -> Self {
+        Error::AlreadyExists {
+            kind: kind.to_string(),
+            name: name.to_string()
+        }
+    }
+
+    // key is already in a B+tree which does not know its table ; callers which know give key_violation.
+    pub fn duplicate_key(key: &[u8]) -> Self {
+        Error::UniqueViolation {
+            table: String::new(),
+            columns: vec![],
+            values: vec![row::Value::Blob(key.to_vec())]
+        }
+    }
+
+    pub fn not_null_violation(table: &str, column: &str) -> Self {
+        Error::NotNullViolation {
+            table: table.to_string(),
+            column: column.to_string()
+        }
+    }
+
+    // message tells what was being done when source failed ; a full disk is Full.
+    pub fn io(message: String, source: io::Error) -> Self {
+        if source.kind() == io::ErrorKind::StorageFull {
+            return Error::Full
+        }
+        Error::Io {
+            message,
+            source: IoSource(Rc::new(source))
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match *self {
+            Error::Parse(_) | Error::Other(_) | Error::NoSuchTable(_) | Error::NoSuchColumn(_) | Error::AlreadyExists { .. }
+                | Error::InvalidSchema(_) => ErrorCode::Error,
+            Error::TypeMismatch(_) => ErrorCode::Mismatch,
+            Error::UniqueViolation { .. } | Error::NotNullViolation { .. } => ErrorCode::Constraint,
+            Error::Full => ErrorCode::Full,
+            Error::Io { .. } => ErrorCode::IoErr,
+            Error::Corrupt(_) => ErrorCode::Corrupt,
+            Error::NotADatabase(_) => ErrorCode::NotADb,
+            Error::ParameterOutOfRange { .. } | Error::UnboundParameter(_) => ErrorCode::Range
+        }
+    }
+}
+
+impl IoSource {
+    pub fn get(&self) -> &io::Error {
+        &self.0
+    }
+}
+
+impl PartialEq for IoSource {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.kind() == other.0.kind()
+    }
+}
+
+impl From<String> for Error {
+    fn from(msg: String) -> Self {
+        Error::Other(msg)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Parse(ref msg) => fmt.write_str(msg),
+            Error::UniqueViolation { ref table, ref columns, ref values } => {
+                let columns: Vec<String> = columns.iter().map(|c| format!("{}.{}", table, c)).collect();
+                let values: Vec<String> = values.iter().map(|v| expr::value_literal(v).to_string()).collect();
+                if columns.is_empty() {
+                    write!(fmt, "UNIQUE constraint failed : key {} is already present", values.join(", "))
+                } else if values.len() == 1 {
+                    write!(fmt, "UNIQUE constraint failed : {} = {}", columns[0], values[0])
+                } else {
+                    write!(fmt, "UNIQUE constraint failed : {} = ({})", columns.join(", "), values.join(", "))
+                }
+            },
+            Error::NotNullViolation { ref table, ref column } => write!(fmt, "NOT NULL constraint failed : {}.{}", table, column),
+            Error::NoSuchTable(ref name) => write!(fmt, "No such table '{}'", name),
+            Error::NoSuchColumn(ref name) => write!(fmt, "No such column '{}'", name),
+            Error::AlreadyExists { ref kind, ref name } => write!(fmt, "{} '{}' already exists", kind, name),
+            Error::TypeMismatch(ref msg) | Error::InvalidSchema(ref msg) => fmt.write_str(msg),
+            Error::Full => fmt.write_str("Database or disk is full"),
+            Error::Io { ref message, ref source } => write!(fmt, "{} : error {}", message, source.get()),
+            Error::Corrupt(ref msg) => write!(fmt, "Database is corrupt : {}", msg),
+            Error::NotADatabase(ref msg) => write!(fmt, "File is not a database : {}", msg),
+            Error::ParameterOutOfRange { index, count } => write!(fmt, "Parameter index {} is out of range : statement has {} parameters", index, count),
+            Error::UnboundParameter(n) => write!(fmt, "Parameter ?{} is not bound", n),
+            Error::Other(ref msg) => fmt.write_str(msg)
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io { ref source, .. } => Some(source.get()),
+            _ => None
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use sqliters::{ast, row, schema, error};
+use sqliters::ast::{Expr, BinaryOperator, AggregateFunction};
+use sqliters::schema::ColumnType;
+
+// true when row satisfies the predicate.
+// Values are true when they are true / non zero / non empty ; null is not true.
+pub fn matches(expr: &Expr, schema: &schema::Schema, row: &row::Row) -> Result<bool, error::Error> {
+    Ok(truth(&evaluate(expr, schema, row)?) == Some(true))
+}
+
+// value of expr for row ; comparisons and logical operators give booleans.
+// Null is an unknown value : operators on null give null, except that false AND null is false
+// and true OR null is true.
+pub fn evaluate(expr: &Expr, schema: &schema::Schema, row: &row::Row) -> Result<row::Value, error::Error> {
+    match *expr {
+        Expr::Literal(ref literal) => Ok(literal_value(literal)?),
+        Expr::Column(ref name) => {
+            let pos = schema.column_position(name)?;
+            Ok(row.values()[pos].clone())
+        },
+        Expr::Excluded(ref name) => Err(error::Error::Other(format!("excluded.{} can only be used in ON CONFLICT DO UPDATE", name))),
+        Expr::Binary(operator @ BinaryOperator::And, ref left, ref right) | Expr::Binary(operator @ BinaryOperator::Or, ref left, ref right) => {
+            // the left side decides when it is false for AND or true for OR.
+            let decisive = operator == BinaryOperator::Or;
+            let l = truth(&evaluate(left, schema, row)?);
+            if l == Some(decisive) {
+                return Ok(row::Value::Boolean(decisive))
+            }
+            match (l, truth(&evaluate(right, schema, row)?)) {
+                (_, Some(r)) if r == decisive => Ok(row::Value::Boolean(decisive)),
+                (Some(_), Some(_)) => Ok(row::Value::Boolean(!decisive)),
+                _ => Ok(row::Value::Null)
+            }
+        },
+        Expr::Binary(operator, ref left, ref right) => {
+            Ok(binary(operator, &evaluate(left, schema, row)?, &evaluate(right, schema, row)?)?)
+        },
+        Expr::Not(ref inner) => Ok(truth(&evaluate(inner, schema, row)?).map_or(row::Value::Null, |v| row::Value::Boolean(!v))),
+        Expr::Between { ref expr, ref low, ref high, negated } => {
+            let (value, low, high) = (evaluate(expr, schema, row)?, evaluate(low, schema, row)?, evaluate(high, schema, row)?);
+            if value == row::Value::Null || low == row::Value::Null || high == row::Value::Null {
+                return Ok(row::Value::Null)
+            }
+            let in_range = compare(&value, &low)? != Ordering::Less && compare(&value, &high)? != Ordering::Greater;
+            Ok(row::Value::Boolean(in_range != negated))
+        },
+        Expr::IsNull { ref expr, negated } => Ok(row::Value::Boolean((evaluate(expr, schema, row)? == row::Value::Null) != negated)),
+        // aggregates are replaced by their values before a group is evaluated.
+        Expr::Aggregate { .. } => Err(error::Error::Other(format!("Aggregate {} can only be used in select list or order by", expr)))
+    }
+}
+
+// type of values of expr in rows of schema ; checks columns and operand types before any row is read.
+// Text literals have their own length as size ; arithmetic on integers and bigints gives a bigint
+// and arithmetic with a real gives a real.
+pub fn result_type(expr: &Expr, schema: &schema::Schema) -> Result<ColumnType, error::Error> {
+    match *expr {
+        Expr::Literal(ref literal) => match literal_value(literal)? {
+            row::Value::Null => Ok(ColumnType::Null),
+            row::Value::Integer(_) => Ok(ColumnType::Integer),
+            row::Value::BigInt(_) => Ok(ColumnType::BigInt),
+            row::Value::Real(_) => Ok(ColumnType::Real),
+            row::Value::Boolean(_) => Ok(ColumnType::Boolean),
+            row::Value::Timestamp(_) => Ok(ColumnType::Timestamp),
+            row::Value::Text(ref v) => Ok(ColumnType::Text(v.len())),
+            row::Value::Blob(_) => Ok(ColumnType::Blob)
+        },
+        Expr::Column(ref name) => {
+            let pos = schema.column_position(name)?;
+            Ok(schema.columns()[pos].column_type().clone())
+        },
+        Expr::Excluded(ref name) => Err(error::Error::Other(format!("excluded.{} can only be used in ON CONFLICT DO UPDATE", name))),
+        Expr::Binary(operator, ref left, ref right) => {
+            let (l, r) = (result_type(left, schema)?, result_type(right, schema)?);
+            match operator {
+                BinaryOperator::And | BinaryOperator::Or => {},
+                _ if is_arithmetic(operator) => {
+                    return numeric_type(&l, &r).ok_or_else(|| error::Error::TypeMismatch(format!("Can not apply {:?} on {} and {}", operator, l, r)))
+                },
+                _ => check_comparable(&l, &r)?
+            }
+            Ok(ColumnType::Boolean)
+        },
+        Expr::Not(ref inner) => result_type(inner, schema).map(|_| ColumnType::Boolean),
+        Expr::Between { ref expr, ref low, ref high, .. } => {
+            let value_type = result_type(expr, schema)?;
+            check_comparable(&value_type, &result_type(low, schema)?)?;
+            check_comparable(&value_type, &result_type(high, schema)?)?;
+            Ok(ColumnType::Boolean)
+        },
+        Expr::IsNull { ref expr, .. } => result_type(expr, schema).map(|_| ColumnType::Boolean),
+        Expr::Aggregate { function, ref arg } => {
+            let arg_type = match *arg {
+                Some(ref arg) if contains_aggregate(arg) => return Err(error::Error::Other(format!("Aggregate {} can not contain another aggregate", expr))),
+                Some(ref arg) => result_type(arg, schema)?,
+                None => return Ok(ColumnType::BigInt)
+            };
+            match function {
+                AggregateFunction::Count => Ok(ColumnType::BigInt),
+                AggregateFunction::Min | AggregateFunction::Max => Ok(arg_type),
+                AggregateFunction::Sum | AggregateFunction::Avg => {
+                    if !arg_type.is_numeric() && arg_type != ColumnType::Null {
+                        return Err(error::Error::TypeMismatch(format!("Can not apply {} on {}", function, arg_type)))
+                    }
+                    // sums of integers are bigints so that they do not overflow ; avg is not rounded.
+                    match (function, arg_type) {
+                        (AggregateFunction::Avg, _) => Ok(ColumnType::Real),
+                        (_, ColumnType::Integer) => Ok(ColumnType::BigInt),
+                        (_, arg_type) => Ok(arg_type)
+                    }
+                }
+            }
+        }
+    }
+}
+
+// true when expr has an aggregate function call.
+pub fn contains_aggregate(expr: &Expr) -> bool {
+    match *expr {
+        Expr::Aggregate { .. } => true,
+        Expr::Literal(_) | Expr::Column(_) | Expr::Excluded(_) => false,
+        Expr::Binary(_, ref left, ref right) => contains_aggregate(left) || contains_aggregate(right),
+        Expr::Not(ref inner) | Expr::IsNull { expr: ref inner, .. } => contains_aggregate(inner),
+        Expr::Between { ref expr, ref low, ref high, .. } => contains_aggregate(expr) || contains_aggregate(low) || contains_aggregate(high)
+    }
+}
+
+// numbers of any type compare with each other ; other values only compare with values of their type and null.
+fn check_comparable(left: &ColumnType, right: &ColumnType) -> Result<(), error::Error> {
+    match (left, right) {
+        (ColumnType::Null, _) | (_, ColumnType::Null) => Ok(()),
+        (ColumnType::Boolean, ColumnType::Boolean) | (ColumnType::Timestamp, ColumnType::Timestamp) | (ColumnType::Blob, ColumnType::Blob) => Ok(()),
+        _ if left.is_numeric() && right.is_numeric() => Ok(()),
+        _ if left.is_text() && right.is_text() => Ok(()),
+        _ => Err(error::Error::TypeMismatch(format!("Can not compare {} with {}", left, right)))
+    }
+}
+
+// type of arithmetic on numbers of types left and right ; None when either is not a number or null.
+fn numeric_type(left: &ColumnType, right: &ColumnType) -> Option<ColumnType> {
+    match (left, right) {
+        (ColumnType::Null, other) | (other, ColumnType::Null) if other.is_numeric() || *other == ColumnType::Null => Some(other.clone()),
+        _ if !left.is_numeric() || !right.is_numeric() => None,
+        (ColumnType::Real, _) | (_, ColumnType::Real) => Some(ColumnType::Real),
+        (ColumnType::BigInt, _) | (_, ColumnType::BigInt) => Some(ColumnType::BigInt),
+        _ => Some(ColumnType::Integer)
+    }
+}
+
+fn is_arithmetic(operator: BinaryOperator) -> bool {
+    matches!(operator, BinaryOperator::Plus | BinaryOperator::Minus | BinaryOperator::Multiply | BinaryOperator::Divide)
+}
+
+// replaces excluded.column with value of column in excluded row.
+pub fn bind_excluded(expr: &Expr, schema: &schema::Schema, excluded: &row::Row) -> Result<Expr, error::Error> {
+    let bind = |e: &Expr| bind_excluded(e, schema, excluded).map(Box::new);
+    Ok(match *expr {
+        Expr::Excluded(ref name) => Expr::Literal(value_literal(&excluded.values()[schema.column_position(name)?])),
+        Expr::Literal(_) | Expr::Column(_) => expr.clone(),
+        Expr::Binary(operator, ref left, ref right) => Expr::Binary(operator, bind(left)?, bind(right)?),
+        Expr::Not(ref inner) => Expr::Not(bind(inner)?),
+        Expr::IsNull { ref expr, negated } => Expr::IsNull {
+            expr: bind(expr)?,
+            negated
+        },
+        Expr::Between { ref expr, ref low, ref high, negated } => Expr::Between {
+            expr: bind(expr)?,
+            low: bind(low)?,
+            high: bind(high)?,
+            negated
+        },
+        Expr::Aggregate { function, ref arg } => Expr::Aggregate {
+            function,
+            arg: match *arg {
+                Some(ref arg) => Some(bind(arg)?),
+                None => None
+            }
+        }
+    })
+}
+
+// replaces each literal of expr with map of it ; parameters of a prepared statement are bound this way.
+pub fn map_literals<F>(expr: &Expr, map: &mut F) -> Result<Expr, String>
+    where F: FnMut(&ast::Literal) -> Result<ast::Literal, String>
+{
+    let mut bind = |e: &Expr| map_literals(e, map).map(Box::new);
+    Ok(match *expr {
+        Expr::Literal(ref literal) => Expr::Literal(map(literal)?),
+        Expr::Column(_) | Expr::Excluded(_) => expr.clone(),
+        Expr::Binary(operator, ref left, ref right) => Expr::Binary(operator, bind(left)?, bind(right)?),
+        Expr::Not(ref inner) => Expr::Not(bind(inner)?),
+        Expr::IsNull { ref expr, negated } => Expr::IsNull {
+            expr: bind(expr)?,
+            negated
+        },
+        Expr::Between { ref expr, ref low, ref high, negated } => Expr::Between {
+            expr: bind(expr)?,
+            low: bind(low)?,
+            high: bind(high)?,
+            negated
+        },
+        Expr::Aggregate { function, ref arg } => Expr::Aggregate {
+            function,
+            arg: match *arg {
+                Some(ref arg) => Some(bind(arg)?),
+                None => None
+            }
+        }
+    })
+}
+
+// bigints become integer literals ; they are read back as integers when they are in range of integer.
+pub fn value_literal(value: &row::Value) -> ast::Literal {
+    match *value {
+        row::Value::Null => ast::Literal::Null,
+        row::Value::Integer(v) => ast::Literal::Integer(i64::from(v)),
+        row::Value::BigInt(v) => ast::Literal::Integer(v),
+        row::Value::Real(v) => ast::Literal::Real(v),
+        row::Value::Boolean(v) => ast::Literal::Boolean(v),
+        row::Value::Timestamp(v) => ast::Literal::Timestamp(v),
+        row::Value::Text(ref v) => ast::Literal::String(v.clone()),
+        row::Value::Blob(ref v) => ast::Literal::Blob(v.clone())
+    }
+}
+
+// integer literals out of range of integer are bigints.
+pub fn literal_value(literal: &ast::Literal) -> Result<row::Value, String> {
+    match *literal {
+        ast::Literal::Null => Ok(row::Value::Null),
+        ast::Literal::Integer(v) => Ok(i32::try_from(v).map_or(row::Value::BigInt(v), row::Value::Integer)),
+        ast::Literal::Real(v) => Ok(row::Value::Real(v)),
+        ast::Literal::Boolean(v) => Ok(row::Value::Boolean(v)),
+        ast::Literal::Timestamp(v) => Ok(row::Value::Timestamp(v)),
+        ast::Literal::String(ref v) => Ok(row::Value::Text(v.clone())),
+        ast::Literal::Blob(ref v) => Ok(row::Value::Blob(v.clone())),
+        ast::Literal::Parameter(n) => Err(format!("Parameter ?{} is not bound", n))
+    }
+}
+
+// numbers compare by value whatever their types are ; false is less than true.
+// Null sorts before any other value ; comparison operators give null before they compare.
+pub fn compare(left: &row::Value, right: &row::Value) -> Result<Ordering, String> {
+    match (left, right) {
+        (row::Value::Null, row::Value::Null) => Ok(Ordering::Equal),
+        (row::Value::Null, _) => Ok(Ordering::Less),
+        (_, row::Value::Null) => Ok(Ordering::Greater),
+        (row::Value::Integer(l), row::Value::Integer(r)) => Ok(l.cmp(r)),
+        (row::Value::Boolean(l), row::Value::Boolean(r)) => Ok(l.cmp(r)),
+        (row::Value::Timestamp(l), row::Value::Timestamp(r)) => Ok(l.cmp(r)),
+        (row::Value::Text(l), row::Value::Text(r)) => Ok(l.cmp(r)),
+        (row::Value::Blob(l), row::Value::Blob(r)) => Ok(l.cmp(r)),
+        _ => match (integer(left), integer(right), real(left), real(right)) {
+            (Some(l), Some(r), _, _) => Ok(l.cmp(&r)),
+            (_, _, Some(l), Some(r)) => l.partial_cmp(&r).ok_or_else(|| format!("Can not compare {} with {}", left, right)),
+            _ => Err(format!("Can not compare {} with {}", left, right))
+        }
+    }
+}
+
+fn integer(value: &row::Value) -> Option<i64> {
+    match *value {
+        row::Value::Integer(v) => Some(i64::from(v)),
+        row::Value::BigInt(v) => Some(v),
+        _ => None
+    }
+}
+
+fn real(value: &row::Value) -> Option<f64> {
+    match *value {
+        row::Value::Real(v) => Some(v),
+        _ => integer(value).map(|v| v as f64)
+    }
+}
+
+fn binary(operator: BinaryOperator, left: &row::Value, right: &row::Value) -> Result<row::Value, String> {
+    if *left == row::Value::Null || *right == row::Value::Null {
+        return Ok(row::Value::Null)
+    }
+    let accept = match operator {
+        BinaryOperator::Equals => [Ordering::Equal].as_ref(),
+        BinaryOperator::NotEquals => [Ordering::Less, Ordering::Greater].as_ref(),
+        BinaryOperator::Less => [Ordering::Less].as_ref(),
+        BinaryOperator::LessEquals => [Ordering::Less, Ordering::Equal].as_ref(),
+        BinaryOperator::Greater => [Ordering::Greater].as_ref(),
+        BinaryOperator::GreaterEquals => [Ordering::Greater, Ordering::Equal].as_ref(),
+        _ => return arithmetic(operator, left, right)
+    };
+    Ok(row::Value::Boolean(accept.contains(&compare(left, right)?)))
+}
+
+// integers stay integers and a bigint operand gives a bigint ; any real operand gives a real.
+fn arithmetic(operator: BinaryOperator, left: &row::Value, right: &row::Value) -> Result<row::Value, String> {
+    if let (Some(l), Some(r)) = (integer(left), integer(right)) {
+        let result = match operator {
+            BinaryOperator::Plus => l.checked_add(r),
+            BinaryOperator::Minus => l.checked_sub(r),
+            BinaryOperator::Multiply => l.checked_mul(r),
+            BinaryOperator::Divide => {
+                if r == 0 {
+                    return Err(format!("Division by zero : {} / {}", l, r))
+                }
+                l.checked_div(r)
+            },
+            _ => return Err(format!("{:?} is not an arithmetic operator", operator))
+        };
+        let value = match (left, right) {
+            (row::Value::Integer(_), row::Value::Integer(_)) => result.and_then(|v| i32::try_from(v).ok()).map(row::Value::Integer),
+            _ => result.map(row::Value::BigInt)
+        };
+        return value.ok_or_else(|| format!("Integer overflow in {:?} of {} and {}", operator, l, r))
+    }
+
+    let (l, r) = match (real(left), real(right)) {
+        (Some(l), Some(r)) => (l, r),
+        _ => return Err(format!("Can not apply {:?} on {} and {}", operator, left, right))
+    };
+    let result = match operator {
+        BinaryOperator::Plus => l + r,
+        BinaryOperator::Minus => l - r,
+        BinaryOperator::Multiply => l * r,
+        BinaryOperator::Divide => {
+            if r == 0.0 {
+                return Err(format!("Division by zero : {:?} / {:?}", l, r))
+            }
+            l / r
+        },
+        _ => return Err(format!("{:?} is not an arithmetic operator", operator))
+    };
+    if !result.is_finite() {
+        return Err(format!("Real overflow in {:?} of {:?} and {:?}", operator, l, r))
+    }
+    Ok(row::Value::Real(result))
+}
+
+// None when value is null.
+fn truth(value: &row::Value) -> Option<bool> {
+    match *value {
+        row::Value::Null => None,
+        row::Value::Integer(v) => Some(v != 0),
+        row::Value::BigInt(v) | row::Value::Timestamp(v) => Some(v != 0),
+        row::Value::Real(v) => Some(v != 0.0),
+        row::Value::Boolean(v) => Some(v),
+        row::Value::Text(ref v) => Some(!v.is_empty()),
+        row::Value::Blob(ref v) => Some(!v.is_empty())
+    }
+}
@@ -0,0 +1,85 @@
+use sqliters::{consts, error};
+
+// Header at the start of page 0 of db file ; see consts for its layout.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub page_size: usize,
+    pub page_count: u64,
+    pub freelist_head: u64,
+    pub schema_root: u64
+}
+
+impl Header {
+    // bytes start with the magic of sqliters.
+    pub fn has_magic(bytes: &[u8]) -> bool {
+        bytes.len() >= consts::MAGIC_SIZE && &bytes[consts::MAGIC_OFFSET .. consts::MAGIC_OFFSET + consts::MAGIC_SIZE] == consts::DB_MAGIC
+    }
+
+    pub fn read(bytes: &[u8]) -> Result<Self, error::Error> {
+        if !Header::has_magic(bytes) {
+            return Err(error::Error::NotADatabase(String::from("file does not start with the magic of sqliters")))
+        }
+        if bytes.len() < consts::DB_HEADER_SIZE {
+            return Err(error::Error::Corrupt(format!("Header has {} of {} bytes", bytes.len(), consts::DB_HEADER_SIZE)))
+        }
+
+        let version = read_u32(bytes, consts::FORMAT_VERSION_OFFSET);
+        if version != consts::FORMAT_VERSION {
+            return Err(error::Error::NotADatabase(format!("format version {} is not supported : version {} is", version, consts::FORMAT_VERSION)))
+        }
+        let header = Header {
+            page_size: read_u32(bytes, consts::DB_PAGE_SIZE_OFFSET) as usize,
+            page_count: read_u64(bytes, consts::PAGE_COUNT_OFFSET),
+            freelist_head: read_u64(bytes, consts::FREELIST_HEAD_OFFSET),
+            schema_root: read_u64(bytes, consts::SCHEMA_ROOT_OFFSET)
+        };
+        if !is_valid_page_size(header.page_size) && header.page_size != consts::HEADERLESS_PAGE_SIZE {
+            return Err(error::Error::NotADatabase(format!("page size {} is not supported", header.page_size)))
+        }
+        if header.page_count == 0 || header.freelist_head >= header.page_count || header.schema_root >= header.page_count {
+            return Err(error::Error::Corrupt(format!("Header has pages outside of the file : {:?}", header)))
+        }
+        Ok(header)
+    }
+
+    // header followed by zeros till the end of page.
+    pub fn write(&self) -> Vec<u8> {
+        let mut bytes = vec![0; self.page_size];
+        bytes[consts::MAGIC_OFFSET .. consts::MAGIC_OFFSET + consts::MAGIC_SIZE].copy_from_slice(consts::DB_MAGIC);
+        bytes[consts::FORMAT_VERSION_OFFSET .. consts::FORMAT_VERSION_OFFSET + consts::FORMAT_VERSION_SIZE]
+            .copy_from_slice(&consts::FORMAT_VERSION.to_be_bytes());
+        bytes[consts::DB_PAGE_SIZE_OFFSET .. consts::DB_PAGE_SIZE_OFFSET + consts::DB_PAGE_SIZE_SIZE]
+            .copy_from_slice(&(self.page_size as u32).to_be_bytes());
+        bytes[consts::PAGE_COUNT_OFFSET .. consts::PAGE_COUNT_OFFSET + consts::PAGE_COUNT_SIZE]
+            .copy_from_slice(&self.page_count.to_be_bytes());
+        bytes[consts::FREELIST_HEAD_OFFSET .. consts::FREELIST_HEAD_OFFSET + consts::FREELIST_HEAD_SIZE]
+            .copy_from_slice(&self.freelist_head.to_be_bytes());
+        bytes[consts::SCHEMA_ROOT_OFFSET .. consts::SCHEMA_ROOT_OFFSET + consts::SCHEMA_ROOT_SIZE]
+            .copy_from_slice(&self.schema_root.to_be_bytes());
+        bytes
+    }
+}
+
+// page sizes of new db files.
+pub fn is_valid_page_size(page_size: usize) -> bool {
+    page_size.is_power_of_two() && (consts::MIN_PAGE_SIZE ..= consts::MAX_PAGE_SIZE).contains(&page_size)
+}
+
+// page 0 of a file which was written before the header page : root of catalog B+tree.
+pub fn is_headerless_root(bytes: &[u8]) -> bool {
+    bytes.len() > consts::IS_ROOT_OFFSET
+        && (bytes[consts::PAGE_TYPE_OFFSET] == consts::LEAF_NODE_TYPE || bytes[consts::PAGE_TYPE_OFFSET] == consts::NONLEAF_NODE_TYPE)
+        && bytes[consts::IS_ROOT_OFFSET] == consts::IS_ROOT_TYPE
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut value_bytes = [0; 4];
+    value_bytes.copy_from_slice(&bytes[offset .. offset + 4]);
+    u32::from_be_bytes(value_bytes)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    let mut value_bytes = [0; 8];
+    value_bytes.copy_from_slice(&bytes[offset .. offset + 8]);
+    u64::from_be_bytes(value_bytes)
+}
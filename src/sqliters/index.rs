@@ -0,0 +1,171 @@
+use std::cmp;
+use sqliters::{ast, btree, consts, cursor, expr, key, pager, planner, row, schema, error};
+use sqliters::schema::ColumnType;
+
+// A secondary index is a B+tree over one column of a table.
+// Its cells only have a key : encoded column value : encoded primary key of the row.
+// Encoded values compare as bytes like the values do, so a range of values is a range of keys
+// and rows with equal values are kept apart by their primary key.
+// Text and blob of any length are keyed on their prefix, so rows found through such an index can have
+// other values with the same prefix ; callers check values of the rows they find.
+// A unique index backs a unique constraint ; callers check that a value is not present before adding it.
+// Null values are not indexed, so a unique column can have any number of nulls.
+#[derive(Debug, Clone)]
+pub struct Index {
+    name: String,
+    column: schema::Column,
+    column_pos: usize,
+    // size of primary keys of the table.
+    table_key_size: usize,
+    unique: bool,
+    root_page_num: u64
+}
+
+impl Index {
+    pub fn new(name: &str, schema: &schema::Schema, column_name: &str, unique: bool, root_page_num: u64) -> Result<Self, error::Error> {
+        let column_pos = schema.column_position(column_name)?;
+        Ok(Index {
+            name: name.to_string(),
+            column: schema.columns()[column_pos].clone(),
+            column_pos,
+            table_key_size: schema.key_size(),
+            unique,
+            root_page_num
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_unique(&self) -> bool {
+        self.unique
+    }
+
+    pub fn column(&self) -> &schema::Column {
+        &self.column
+    }
+
+    pub fn column_pos(&self) -> usize {
+        self.column_pos
+    }
+
+    #[cfg(test)]
+    pub fn root_page_num(&self) -> u64 {
+        self.root_page_num
+    }
+
+    pub fn add(&self, pager: &mut pager::Pager, schema: &schema::Schema, row: &row::Row) -> Result<(), error::Error> {
+        if row.values()[self.column_pos] == row::Value::Null {
+            return Ok(())
+        }
+        let key = self.entry_key(schema, row)?;
+        cursor::Cursor::find(pager, self.root_page_num, &key)?.insert(&key, vec![])
+    }
+
+    pub fn remove(&self, pager: &mut pager::Pager, schema: &schema::Schema, row: &row::Row) -> Result<(), error::Error> {
+        if row.values()[self.column_pos] == row::Value::Null {
+            return Ok(())
+        }
+        let key = self.entry_key(schema, row)?;
+        if !btree::delete_key(pager, self.root_page_num, &key)? {
+            return Err(error::Error::Other(format!("Index '{}' has no entry for row {}", self.name, row)))
+        }
+        Ok(())
+    }
+
+    // primary keys of rows with value ; or with the same prefix of a long value. Null is never found.
+    pub fn find(&self, pager: &mut pager::Pager, value: &row::Value) -> Result<Vec<Vec<u8>>, error::Error> {
+        if *value == row::Value::Null {
+            return Ok(vec![])
+        }
+        self.primary_keys(pager, &planner::ValueRange::single(expr::value_literal(value)))
+    }
+
+    // primary keys of rows whose value is in range, in order of encoded values.
+    pub fn primary_keys(&self, pager: &mut pager::Pager, range: &planner::ValueRange) -> Result<Vec<Vec<u8>>, error::Error> {
+        let size = value_size(self.column.column_type());
+        let mut low = match range.low() {
+            Some(v) => self.encode_bound(v)?,
+            None => vec![0; size]
+        };
+        let mut high = match range.high() {
+            Some(v) => self.encode_bound(v)?,
+            None => vec![0xff; size]
+        };
+        low.extend(vec![0; self.table_key_size]);
+        high.extend(vec![0xff; self.table_key_size]);
+
+        let mut keys = vec![];
+        if low > high {
+            return Ok(keys)
+        }
+
+        let mut cursor = cursor::Cursor::seek(pager, self.root_page_num, &low)?;
+        while !cursor.end_of_table() {
+            let key = cursor.cell_key()?;
+            if key > high {
+                break;
+            }
+            keys.push(key[size..].to_vec());
+            cursor.advance_cursor()?;
+        }
+        Ok(keys)
+    }
+
+    fn entry_key(&self, schema: &schema::Schema, row: &row::Row) -> Result<Vec<u8>, error::Error> {
+        let mut key = encode_value(&self.column, &row.values()[self.column_pos])?;
+        key.extend(row.key(schema)?);
+        Ok(key)
+    }
+
+    // integers out of range are clamped and longer text and blob are cut ; bounds may only widen the range.
+    // integers are valid bounds of any number column.
+    fn encode_bound(&self, literal: &ast::Literal) -> Result<Vec<u8>, error::Error> {
+        let column_type = self.column.column_type();
+        let size = value_size(column_type);
+        match (column_type, literal) {
+            (ColumnType::Integer, ast::Literal::Integer(v)) => Ok(key::encode_i32(cmp::max(i64::from(i32::MIN), cmp::min(i64::from(i32::MAX), *v)) as i32)),
+            (ColumnType::BigInt, ast::Literal::Integer(v)) | (ColumnType::Timestamp, ast::Literal::Timestamp(v)) => Ok(key::encode_i64(*v)),
+            (ColumnType::Real, ast::Literal::Integer(v)) => Ok(key::encode_real(*v as f64)),
+            (ColumnType::Real, ast::Literal::Real(v)) => Ok(key::encode_real(*v)),
+            (ColumnType::Boolean, ast::Literal::Boolean(v)) => Ok(vec![*v as u8]),
+            (_, ast::Literal::String(v)) if column_type.is_text() => Ok(key::encode_bytes(v.as_bytes(), size)),
+            (ColumnType::Blob, ast::Literal::Blob(v)) => Ok(key::encode_bytes(v, size)),
+            (column_type, literal) => Err(error::Error::TypeMismatch(format!("Literal {} does not match type {} of column '{}'", literal, column_type, self.column.name())))
+        }
+    }
+}
+
+// bytes of value in keys of an index on column.
+fn encode_value(column: &schema::Column, value: &row::Value) -> Result<Vec<u8>, error::Error> {
+    let size = value_size(column.column_type());
+    match (column.column_type(), value) {
+        (ColumnType::Integer, row::Value::Integer(v)) => Ok(key::encode_i32(*v)),
+        (ColumnType::BigInt, row::Value::BigInt(v)) | (ColumnType::Timestamp, row::Value::Timestamp(v)) => Ok(key::encode_i64(*v)),
+        (ColumnType::Real, row::Value::Real(v)) => Ok(key::encode_real(*v)),
+        (ColumnType::Boolean, row::Value::Boolean(v)) => Ok(vec![*v as u8]),
+        (ColumnType::Text(_), row::Value::Text(v)) | (ColumnType::VarText, row::Value::Text(v)) => Ok(key::encode_bytes(v.as_bytes(), size)),
+        (ColumnType::Blob, row::Value::Blob(v)) => Ok(key::encode_bytes(v, size)),
+        (column_type, value) => Err(error::Error::TypeMismatch(format!("Value {:?} does not match type {} of column '{}'", value, column_type, column.name())))
+    }
+}
+
+// keys of an index on column of type column_type in a table of schema.
+pub fn key_size(column_type: &ColumnType, schema: &schema::Schema) -> usize {
+    value_size(column_type) + schema.key_size()
+}
+
+// text of a maximum size is padded to that size in keys ; text and blob of any length keep only their prefix.
+fn value_size(column_type: &ColumnType) -> usize {
+    match *column_type {
+        ColumnType::Integer => consts::INTEGER_SIZE,
+        ColumnType::BigInt => consts::BIGINT_SIZE,
+        ColumnType::Real => consts::REAL_SIZE,
+        ColumnType::Boolean => consts::BOOLEAN_SIZE,
+        ColumnType::Timestamp => consts::TIMESTAMP_SIZE,
+        ColumnType::Text(size) => size,
+        ColumnType::VarText | ColumnType::Blob => consts::VARLEN_PREFIX_SIZE,
+        ColumnType::Null => 0
+    }
+}
@@ -0,0 +1,106 @@
+use std::cmp;
+use sqliters::{ast, consts, row};
+use sqliters::schema::ColumnType;
+
+// Keys of B+trees are compared as bytes, so values are encoded so that their bytes compare like the values :
+// numbers are big endian with the sign bit flipped and negative reals have all their bits flipped.
+// A primary key is the encoded values of its columns one after another. Each value has a fixed size,
+// so the first column orders the keys and next columns order keys with equal values before them.
+// Text of a maximum size is padded with zeroes to that size and followed by its length,
+// which keeps text ending in zeroes apart from shorter text.
+// Text and blob of any length only keep a prefix in a row, so they can not be part of a primary key.
+
+// bytes which a value of column_type takes in a primary key ; None when the type can not be part of one.
+pub fn size(column_type: &ColumnType) -> Option<usize> {
+    match *column_type {
+        ColumnType::Integer => Some(consts::INTEGER_SIZE),
+        ColumnType::BigInt => Some(consts::BIGINT_SIZE),
+        ColumnType::Real => Some(consts::REAL_SIZE),
+        ColumnType::Boolean => Some(consts::BOOLEAN_SIZE),
+        ColumnType::Timestamp => Some(consts::TIMESTAMP_SIZE),
+        ColumnType::Text(size) => Some(size + consts::TEXT_LENGTH_SIZE),
+        ColumnType::VarText | ColumnType::Blob | ColumnType::Null => None
+    }
+}
+
+// bytes of value of a primary key column.
+pub fn encode_value(column_type: &ColumnType, value: &row::Value) -> Result<Vec<u8>, String> {
+    match (column_type, value) {
+        (ColumnType::Integer, row::Value::Integer(v)) => Ok(encode_i32(*v)),
+        (ColumnType::BigInt, row::Value::BigInt(v)) | (ColumnType::Timestamp, row::Value::Timestamp(v)) => Ok(encode_i64(*v)),
+        (ColumnType::Real, row::Value::Real(v)) => Ok(encode_real(*v)),
+        (ColumnType::Boolean, row::Value::Boolean(v)) => Ok(vec![*v as u8]),
+        (ColumnType::Text(size), row::Value::Text(v)) if v.len() <= *size => Ok(encode_text(v.as_bytes(), *size)),
+        (column_type, value) => Err(format!("Value {:?} can not be a primary key of type {}", value, column_type))
+    }
+}
+
+// bytes of a bound on values of a primary key column ; None when literal is not of column type.
+// Integers out of range are clamped and longer text is cut ; bounds may only widen the range.
+pub fn encode_bound(column_type: &ColumnType, literal: &ast::Literal) -> Option<Vec<u8>> {
+    match (column_type, literal) {
+        (ColumnType::Integer, ast::Literal::Integer(v)) => Some(encode_i32(cmp::max(i64::from(i32::MIN), cmp::min(i64::from(i32::MAX), *v)) as i32)),
+        (ColumnType::BigInt, ast::Literal::Integer(v)) | (ColumnType::Timestamp, ast::Literal::Timestamp(v)) => Some(encode_i64(*v)),
+        (ColumnType::Real, ast::Literal::Integer(v)) => Some(encode_real(*v as f64)),
+        (ColumnType::Real, ast::Literal::Real(v)) => Some(encode_real(*v)),
+        (ColumnType::Boolean, ast::Literal::Boolean(v)) => Some(vec![*v as u8]),
+        (ColumnType::Text(size), ast::Literal::String(v)) => Some(encode_text(&v.as_bytes()[.. cmp::min(*size, v.len())], *size)),
+        _ => None
+    }
+}
+
+pub fn encode_i32(v: i32) -> Vec<u8> {
+    ((v as u32) ^ (1 << 31)).to_be_bytes().to_vec()
+}
+
+pub fn encode_i64(v: i64) -> Vec<u8> {
+    ((v as u64) ^ (1 << 63)).to_be_bytes().to_vec()
+}
+
+// -0.0 is keyed as 0.0 ; negative reals have all bits flipped so that larger magnitudes come first.
+pub fn encode_real(v: f64) -> Vec<u8> {
+    let bits = (v + 0.0).to_bits();
+    let bits = if bits >> 63 == 0 { bits ^ (1 << 63) } else { !bits };
+    bits.to_be_bytes().to_vec()
+}
+
+// bytes padded with zeroes to size ; longer bytes are cut.
+pub fn encode_bytes(bytes: &[u8], size: usize) -> Vec<u8> {
+    let mut encoded = bytes[.. cmp::min(size, bytes.len())].to_vec();
+    encoded.resize(size, 0);
+    encoded
+}
+
+// text of at most size bytes padded to size and followed by its length.
+fn encode_text(bytes: &[u8], size: usize) -> Vec<u8> {
+    let mut encoded = encode_bytes(bytes, size);
+    encoded.extend((bytes.len() as u16).to_be_bytes());
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_compare_like_values() {
+        let text = |v: &str| row::Value::Text(v.to_string());
+        let cases = [
+            (ColumnType::Integer, vec![row::Value::Integer(i32::MIN), row::Value::Integer(-1), row::Value::Integer(0), row::Value::Integer(i32::MAX)]),
+            (ColumnType::BigInt, vec![row::Value::BigInt(i64::MIN), row::Value::BigInt(-5), row::Value::BigInt(3), row::Value::BigInt(i64::MAX)]),
+            (ColumnType::Real, vec![row::Value::Real(-2.5), row::Value::Real(-0.5), row::Value::Real(0.0), row::Value::Real(1e10)]),
+            (ColumnType::Boolean, vec![row::Value::Boolean(false), row::Value::Boolean(true)]),
+            (ColumnType::Text(4), vec![text(""), text("\0"), text("a"), text("a\0"), text("ab"), text("b")])
+        ];
+
+        for (column_type, values) in cases.iter() {
+            let keys: Vec<Vec<u8>> = values.iter().map(|v| encode_value(column_type, v).expect("value should encode")).collect();
+            assert!(keys.iter().all(|k| Some(k.len()) == size(column_type)), "keys of {} should have a fixed size : {:?}", column_type, keys);
+            assert!(keys.windows(2).all(|w| w[0] < w[1]), "keys of {} should be in order of values : {:?}", column_type, keys);
+        }
+
+        assert!(size(&ColumnType::VarText).is_none() && size(&ColumnType::Blob).is_none(), "text and blob of any length are not keys");
+        assert!(encode_value(&ColumnType::Text(2), &text("abc")).is_err(), "text should fit in the column");
+        assert!(encode_bound(&ColumnType::Text(2), &ast::Literal::String("abc".to_string())) == Some(encode_text(b"ab", 2)), "long bound is cut");
+    }
+}
@@ -0,0 +1,419 @@
+use std::fmt;
+use sqliters::ast;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Keyword {
+    Create,
+    Table,
+    Index,
+    Primary,
+    Key,
+    Autoincrement,
+    Unique,
+    Insert,
+    Into,
+    Values,
+    Select,
+    From,
+    Delete,
+    Update,
+    Set,
+    Replace,
+    Ignore,
+    On,
+    Conflict,
+    Do,
+    Nothing,
+    As,
+    Order,
+    Group,
+    By,
+    Asc,
+    Desc,
+    Limit,
+    Offset,
+    Where,
+    And,
+    Or,
+    Not,
+    Between,
+    True,
+    False,
+    Is,
+    Null
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Keyword(Keyword),
+    Identifier(String),
+    Integer(i64),
+    Real(f64),
+    String(String),
+    Blob(Vec<u8>),
+    LeftParen,
+    RightParen,
+    Comma,
+    Dot,
+    Semicolon,
+    Star,
+    Plus,
+    Minus,
+    Slash,
+    Equals,
+    NotEquals,
+    Less,
+    LessEquals,
+    Greater,
+    GreaterEquals,
+    // parameter of a prepared statement ; numbered from 1 in order of appearance.
+    Question,
+    Eof
+}
+
+// line and column start from 1 ; they point to first character of token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    kind: TokenKind,
+    line: usize,
+    column: usize
+}
+
+impl Keyword {
+    fn from_word(word: &str) -> Option<Keyword> {
+        let keyword = match word.to_lowercase().as_str() {
+            "create" => Keyword::Create,
+            "table" => Keyword::Table,
+            "index" => Keyword::Index,
+            "primary" => Keyword::Primary,
+            "key" => Keyword::Key,
+            "autoincrement" => Keyword::Autoincrement,
+            "unique" => Keyword::Unique,
+            "insert" => Keyword::Insert,
+            "into" => Keyword::Into,
+            "values" => Keyword::Values,
+            "select" => Keyword::Select,
+            "from" => Keyword::From,
+            "delete" => Keyword::Delete,
+            "update" => Keyword::Update,
+            "set" => Keyword::Set,
+            "replace" => Keyword::Replace,
+            "ignore" => Keyword::Ignore,
+            "on" => Keyword::On,
+            "conflict" => Keyword::Conflict,
+            "do" => Keyword::Do,
+            "nothing" => Keyword::Nothing,
+            "as" => Keyword::As,
+            "order" => Keyword::Order,
+            "group" => Keyword::Group,
+            "by" => Keyword::By,
+            "asc" => Keyword::Asc,
+            "desc" => Keyword::Desc,
+            "limit" => Keyword::Limit,
+            "offset" => Keyword::Offset,
+            "where" => Keyword::Where,
+            "and" => Keyword::And,
+            "or" => Keyword::Or,
+            "not" => Keyword::Not,
+            "between" => Keyword::Between,
+            "true" => Keyword::True,
+            "false" => Keyword::False,
+            "is" => Keyword::Is,
+            "null" => Keyword::Null,
+            _ => return None
+        };
+        Some(keyword)
+    }
+}
+
+impl fmt::Display for Keyword {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let word = match *self {
+            Keyword::Create => "CREATE",
+            Keyword::Table => "TABLE",
+            Keyword::Index => "INDEX",
+            Keyword::Primary => "PRIMARY",
+            Keyword::Key => "KEY",
+            Keyword::Autoincrement => "AUTOINCREMENT",
+            Keyword::Unique => "UNIQUE",
+            Keyword::Insert => "INSERT",
+            Keyword::Into => "INTO",
+            Keyword::Values => "VALUES",
+            Keyword::Select => "SELECT",
+            Keyword::From => "FROM",
+            Keyword::Delete => "DELETE",
+            Keyword::Update => "UPDATE",
+            Keyword::Set => "SET",
+            Keyword::Replace => "REPLACE",
+            Keyword::Ignore => "IGNORE",
+            Keyword::On => "ON",
+            Keyword::Conflict => "CONFLICT",
+            Keyword::Do => "DO",
+            Keyword::Nothing => "NOTHING",
+            Keyword::As => "AS",
+            Keyword::Order => "ORDER",
+            Keyword::Group => "GROUP",
+            Keyword::By => "BY",
+            Keyword::Asc => "ASC",
+            Keyword::Desc => "DESC",
+            Keyword::Limit => "LIMIT",
+            Keyword::Offset => "OFFSET",
+            Keyword::Where => "WHERE",
+            Keyword::And => "AND",
+            Keyword::Or => "OR",
+            Keyword::Not => "NOT",
+            Keyword::Between => "BETWEEN",
+            Keyword::True => "TRUE",
+            Keyword::False => "FALSE",
+            Keyword::Is => "IS",
+            Keyword::Null => "NULL"
+        };
+        fmt.write_str(word)
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TokenKind::Keyword(keyword) => write!(fmt, "keyword {}", keyword),
+            TokenKind::Identifier(ref name) => write!(fmt, "identifier '{}'", name),
+            TokenKind::Integer(value) => write!(fmt, "integer {}", value),
+            TokenKind::Real(value) => write!(fmt, "real {:?}", value),
+            TokenKind::String(ref value) => write!(fmt, "string '{}'", value),
+            TokenKind::Blob(ref value) => write!(fmt, "blob {}", ast::Literal::Blob(value.clone())),
+            TokenKind::LeftParen => fmt.write_str("'('"),
+            TokenKind::RightParen => fmt.write_str("')'"),
+            TokenKind::Comma => fmt.write_str("','"),
+            TokenKind::Dot => fmt.write_str("'.'"),
+            TokenKind::Semicolon => fmt.write_str("';'"),
+            TokenKind::Star => fmt.write_str("'*'"),
+            TokenKind::Plus => fmt.write_str("'+'"),
+            TokenKind::Minus => fmt.write_str("'-'"),
+            TokenKind::Slash => fmt.write_str("'/'"),
+            TokenKind::Equals => fmt.write_str("'='"),
+            TokenKind::NotEquals => fmt.write_str("'!='"),
+            TokenKind::Less => fmt.write_str("'<'"),
+            TokenKind::LessEquals => fmt.write_str("'<='"),
+            TokenKind::Greater => fmt.write_str("'>'"),
+            TokenKind::GreaterEquals => fmt.write_str("'>='"),
+            TokenKind::Question => fmt.write_str("'?'"),
+            TokenKind::Eof => fmt.write_str("end of input")
+        }
+    }
+}
+
+impl Token {
+    pub fn kind(&self) -> &TokenKind {
+        &self.kind
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+}
+
+struct Lexer<'a> {
+    chars: ::std::iter::Peekable<::std::str::Chars<'a>>,
+    line: usize,
+    column: usize
+}
+
+// splits sql into tokens ; last token is always Eof.
+pub fn tokenize(sql: &str) -> Result<Vec<Token>, String> {
+    let mut lexer = Lexer {
+        chars: sql.chars().peekable(),
+        line: 1,
+        column: 1
+    };
+
+    let mut tokens = vec![];
+    loop {
+        let token = lexer.next_token()?;
+        let is_eof = token.kind == TokenKind::Eof;
+        tokens.push(token);
+        if is_eof {
+            return Ok(tokens)
+        }
+    }
+}
+
+pub fn syntax_error(line: usize, column: usize, msg: &str) -> String {
+    format!("Syntax error at line {}, column {} : {}", line, column, msg)
+}
+
+// bytes of hex digits ; two digits per byte.
+fn decode_hex(digits: &str) -> Option<Vec<u8>> {
+    if !digits.len().is_multiple_of(2) || !digits.is_ascii() {
+        return None
+    }
+    (0 .. digits.len()).step_by(2)
+        .map(|pos| u8::from_str_radix(&digits[pos .. pos + 2], 16).ok())
+        .collect()
+}
+
+impl<'a> Lexer<'a> {
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        match c {
+            Some('\n') => {
+                self.line += 1;
+                self.column = 1;
+            },
+            Some(_) => self.column += 1,
+            None => {}
+        }
+        c
+    }
+
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            match self.chars.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                },
+                Some('-') => {
+                    // -- comment till end of line
+                    let mut lookahead = self.chars.clone();
+                    lookahead.next();
+                    if lookahead.peek() != Some(&'-') {
+                        return
+                    }
+                    while let Some(c) = self.bump() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                },
+                _ => return
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Token, String> {
+        self.skip_whitespace_and_comments();
+
+        let (line, column) = (self.line, self.column);
+        let c = match self.bump() {
+            Some(c) => c,
+            None => return Ok(Token { kind: TokenKind::Eof, line, column })
+        };
+
+        let kind = match c {
+            '(' => TokenKind::LeftParen,
+            ')' => TokenKind::RightParen,
+            ',' => TokenKind::Comma,
+            '.' => TokenKind::Dot,
+            ';' => TokenKind::Semicolon,
+            '*' => TokenKind::Star,
+            '+' => TokenKind::Plus,
+            '-' => TokenKind::Minus,
+            '/' => TokenKind::Slash,
+            '=' => TokenKind::Equals,
+            '?' => TokenKind::Question,
+            '!' => {
+                if self.bump() != Some('=') {
+                    return Err(syntax_error(line, column, "expected '=' after '!'"))
+                }
+                TokenKind::NotEquals
+            },
+            '<' => match self.chars.peek() {
+                Some('=') => { self.bump(); TokenKind::LessEquals },
+                Some('>') => { self.bump(); TokenKind::NotEquals },
+                _ => TokenKind::Less
+            },
+            '>' => match self.chars.peek() {
+                Some('=') => { self.bump(); TokenKind::GreaterEquals },
+                _ => TokenKind::Greater
+            },
+            '\'' => TokenKind::String(self.quoted('\'', line, column)?),
+            '"' => TokenKind::Identifier(self.quoted('"', line, column)?),
+            c if c.is_ascii_digit() => {
+                let mut digits = self.take_while(c, |c| c.is_ascii_digit());
+                // digits [. digits] [e [+|-] digits] is a real.
+                let mut is_real = false;
+                if self.chars.peek() == Some(&'.') && self.nth_is(1, |c| c.is_ascii_digit()) {
+                    let dot = self.bump().expect("dot is peeked");
+                    digits.push_str(&self.take_while(dot, |c| c.is_ascii_digit()));
+                    is_real = true;
+                }
+                if matches!(self.chars.peek(), Some('e') | Some('E'))
+                    && (self.nth_is(1, |c| c.is_ascii_digit()) || (self.nth_is(1, |c| c == '+' || c == '-') && self.nth_is(2, |c| c.is_ascii_digit()))) {
+                    let e = self.bump().expect("e is peeked");
+                    let sign = self.bump().expect("sign or digit is peeked");
+                    digits.push(e);
+                    digits.push_str(&self.take_while(sign, |c| c.is_ascii_digit()));
+                    is_real = true;
+                }
+                if let Some(&next) = self.chars.peek() {
+                    if next.is_alphabetic() || next == '_' {
+                        return Err(syntax_error(line, column, &format!("invalid number '{}{}'", digits, next)))
+                    }
+                }
+                if is_real {
+                    let value = digits.parse::<f64>().ok().filter(|v| v.is_finite())
+                        .ok_or_else(|| syntax_error(line, column, &format!("real '{}' is out of range", digits)))?;
+                    TokenKind::Real(value)
+                } else {
+                    let value = digits.parse::<i64>()
+                        .map_err(|_e| syntax_error(line, column, &format!("integer '{}' is too large", digits)))?;
+                    TokenKind::Integer(value)
+                }
+            },
+            'x' | 'X' if self.chars.peek() == Some(&'\'') => {
+                self.bump();
+                let digits = self.quoted('\'', line, column)?;
+                TokenKind::Blob(decode_hex(&digits).ok_or_else(|| syntax_error(line, column, &format!("invalid blob x'{}'", digits)))?)
+            },
+            c if c.is_alphabetic() || c == '_' => {
+                let word = self.take_while(c, |c| c.is_alphanumeric() || c == '_');
+                match Keyword::from_word(&word) {
+                    Some(keyword) => TokenKind::Keyword(keyword),
+                    None => TokenKind::Identifier(word)
+                }
+            },
+            c => return Err(syntax_error(line, column, &format!("unexpected character '{}'", c)))
+        };
+
+        Ok(Token { kind, line, column })
+    }
+
+    // true when character at position n satisfies pred ; next character is at 0.
+    fn nth_is<F: Fn(char) -> bool>(&self, n: usize, pred: F) -> bool {
+        self.chars.clone().nth(n).is_some_and(pred)
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, first: char, pred: F) -> String {
+        let mut word = first.to_string();
+        while let Some(&c) = self.chars.peek() {
+            if !pred(c) {
+                break;
+            }
+            word.push(c);
+            self.bump();
+        }
+        word
+    }
+
+    // quote is escaped by writing it twice : 'it''s'
+    fn quoted(&mut self, quote: char, line: usize, column: usize) -> Result<String, String> {
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some(c) if c == quote => {
+                    if self.chars.peek() == Some(&quote) {
+                        self.bump();
+                        value.push(quote);
+                    } else {
+                        return Ok(value)
+                    }
+                },
+                Some(c) => value.push(c),
+                None => return Err(syntax_error(line, column, "unterminated quoted string"))
+            }
+        }
+    }
+}
@@ -1,19 +1,29 @@
 use std::process;
-use sqliters::{table};
+use sqliters::{database, error};
 
-pub fn process_meta_command(table: &mut table::Table, command: &str) -> Result<(), String>
+pub fn process_meta_command(db: &mut database::Database, command: &str) -> Result<(), error::Error>
 {
     match command {
         ".exit" => {
-            table.close_db()?;
+            db.close_db()?;
             process::exit(0)
         },
         ".btree" => {
-            table.print();
+            db.print();
+            Ok(())
+        },
+        ".last_insert_rowid" => {
+            println!("{}", db.last_insert_rowid());
+            Ok(())
+        },
+        ".tables" => {
+            for table in db.tables() {
+                println!("{} {}", table.name(), table.schema());
+            }
             Ok(())
         },
         _ => {
-            Err(format!("Unknown command '{}'", command))
+            Err(error::Error::Other(format!("Unknown command '{}'", command)))
         }
     }
 }
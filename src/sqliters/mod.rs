@@ -1,10 +1,36 @@
 pub mod sqmain;
 mod sqlcommands;
-mod metacommands;
 mod statement;
+mod connection;
+mod error;
+mod metacommands;
+mod lexer;
+mod parser;
+mod ast;
+mod expr;
+mod planner;
+mod sorter;
+mod aggregate;
 mod table;
+mod index;
+mod key;
+mod database;
+mod btree;
 mod pager;
+mod header;
+mod replacement;
 mod cursor;
 mod page;
 mod consts;
 mod context;
+mod schema;
+mod row;
+mod timestamp;
+
+// Api of the library : a program opens a connection, executes statements and reads rows of queries.
+pub use self::connection::{Connection, Rows};
+pub use self::statement::PreparedStatement;
+pub use self::row::{Row, Value, FromValue};
+pub use self::error::{Error, ErrorCode};
+pub use self::replacement::{ReplacementPolicy, Lru, Clock, TwoQueue};
+pub use self::database::Config;
@@ -1,10 +1,14 @@
-use sqliters::{consts, page};
+use sqliters::{consts, page, error};
 use std::mem::transmute;
 
 #[derive(Debug, Clone)]
 pub enum NodeType {
     Leaf,
-    Internal
+    Internal,
+    // page removed from B+tree ; it is reused by a new page. Free pages make the freelist of the header page.
+    Free,
+    // part of a value which does not fit in its cell.
+    Overflow
 }
 
 #[derive(Debug, Clone)]
@@ -13,155 +17,365 @@ pub struct Page {
     node_type: NodeType,
     data: Vec<u8>,
     num_cells: u64,
-    next_sibling_num: u64
+    next_sibling_num: u64,
+    cell_size: usize,
+    key_size: usize,
+    // start of cell content of a leaf ; it is found from cell pointers when a page is read.
+    content_start: usize,
+    // page has changes which are not written to db file.
+    dirty: bool
 }
 
 impl Page {
     pub fn new(data: Vec<u8>) -> Self {
         let num_cells = get_num_cells(&data);
-        let is_leaf = is_leaf_node(&data);
-        let next_sibling_num = if is_leaf { leaf_node_next_sibling_num(&data) } else { 0 };
-        let node_type = if is_leaf { NodeType::Leaf } else { NodeType::Internal };
+        let node_type = get_node_type(&data);
+        let has_sibling = matches!(node_type, NodeType::Leaf | NodeType::Free | NodeType::Overflow);
+        let next_sibling_num = if has_sibling { leaf_node_next_sibling_num(&data) } else { 0 };
+        let cell_size = get_cell_size(&data);
+        let key_size = get_key_size(&data);
+        let content_start = if matches!(node_type, NodeType::Leaf) {
+            (0 .. num_cells).map(|pos| cell_pointer(&data, pos).0).min().unwrap_or(data.len())
+        } else {
+            data.len()
+        };
         Page {
-            is_root: !is_leaf,
+            is_root: is_root_node(&data),
             node_type: node_type,
             data: data,
             num_cells: num_cells,
             next_sibling_num: next_sibling_num,
+            cell_size,
+            key_size,
+            content_start,
+            dirty: false
         }
     }
 
-    pub fn empty() -> Self {
-        Page {
-            is_root: false,
-            node_type: NodeType::Leaf,
-            data: vec![],
-            num_cells: 0,
-            next_sibling_num: 0,
-        }
-    }
-
-    pub fn new_leaf(is_root: bool, page_size: usize) -> Self {
+    // leaf cells are key_size bytes of key followed by value ; cell_size is the size of the largest cell.
+    pub fn new_leaf(is_root: bool, page_size: usize, key_size: usize, cell_size: usize) -> Self {
         Page {
             is_root: is_root,
             node_type: NodeType::Leaf,
             data: new_leaf_node(is_root, page_size),
             num_cells: 0,
             next_sibling_num: 0,
+            cell_size,
+            key_size,
+            content_start: page_size,
+            dirty: true
         }
     }
 
-    pub fn new_root(page_size: usize, left_page_num: u64, right_page_num: u64, left: &Page) -> Self {
-        let max_key = left.max_key();
+    // internal node with no cells ; all keys go to right_page_num.
+    pub fn new_internal(is_root: bool, page_size: usize, key_size: usize, right_page_num: u64) -> Self {
         let mut bytes = vec![0; page_size];
-
-        let right_start_offset = consts::INTERNAL_NODE_RIGHT_PAGE_NUM_OFFSET;
-        let right_end_offset = right_start_offset + consts::INTERNAL_NODE_PAGE_NUM_SIZE;
-        let right_page_bytes: [u8; consts::INTERNAL_NODE_PAGE_NUM_SIZE] = unsafe { transmute(right_page_num.to_be()) };
-        bytes[right_start_offset .. right_end_offset]
-            .copy_from_slice(&right_page_bytes);
-
-        let left_page_bytes: [u8; consts::INTERNAL_NODE_PAGE_NUM_SIZE] = unsafe { transmute(left_page_num.to_be()) };
-        let left_page_num_start_offset = consts::INTERNAL_NODE_CELL_START_OFFSET + consts::INTERNAL_NODE_LEFT_PAGE_NUM_OFFSET;
-        let left_page_num_end_offset = left_page_num_start_offset + consts::INTERNAL_NODE_PAGE_NUM_SIZE;
-        bytes[left_page_num_start_offset .. left_page_num_end_offset]
-            .copy_from_slice(&left_page_bytes);
-
-        let key_start_offset = consts::INTERNAL_NODE_CELL_START_OFFSET + consts::INTERNAL_NODE_KEY_OFFSET;
-        let key_end_offset = key_start_offset + consts::INTERNAL_NODE_KEY_SIZE;
-        let key_bytes: [u8; consts::KEY_SIZE] = unsafe {transmute(max_key.to_be())};
-        bytes[key_start_offset .. key_end_offset]
-            .copy_from_slice(&key_bytes);
+        set_internal_node_right_page_num(&mut bytes, right_page_num);
 
         Page {
-            is_root: true,
+            is_root,
             node_type: NodeType::Internal,
             data: bytes,
-            num_cells: 1,
+            num_cells: 0,
             next_sibling_num: 0, // 0 for all internal nodes.
+            cell_size: consts::INTERNAL_NODE_PAGE_NUM_SIZE + key_size,
+            key_size,
+            content_start: page_size,
+            dirty: true
+        }
+    }
+
+    // next_free_page_num is the next page of the freelist ; 0 ends it.
+    pub fn new_free(page_size: usize, next_free_page_num: u64) -> Self {
+        Page {
+            is_root: false,
+            node_type: NodeType::Free,
+            data: vec![0; page_size],
+            num_cells: 0,
+            next_sibling_num: next_free_page_num,
+            cell_size: 0,
+            key_size: 0,
+            content_start: page_size,
+            dirty: true
+        }
+    }
+
+    // payload is a part of a value and next_page_num is the page with the following part ; 0 ends the chain.
+    pub fn new_overflow(page_size: usize, payload: &[u8], next_page_num: u64) -> Self {
+        let mut data = vec![0; page_size];
+        data[consts::OVERFLOW_PAYLOAD_OFFSET .. consts::OVERFLOW_PAYLOAD_OFFSET + payload.len()].copy_from_slice(payload);
+        Page {
+            is_root: false,
+            node_type: NodeType::Overflow,
+            data,
+            num_cells: payload.len() as u64,
+            next_sibling_num: next_page_num,
+            cell_size: 0,
+            key_size: 0,
+            content_start: page_size,
+            dirty: true
+        }
+    }
+
+    pub fn overflow_payload(&self) -> Result<&[u8], error::Error> {
+        if !self.is_overflow() {
+            return Err(error::Error::Other(String::from("overflow_payload should only be called on overflow page")))
         }
+        Ok(&self.data[consts::OVERFLOW_PAYLOAD_OFFSET .. consts::OVERFLOW_PAYLOAD_OFFSET + self.num_cells as usize])
     }
 
     pub fn set_non_root(&mut self) {
+        self.dirty = true;
         self.is_root = false;
     }
 
-    pub fn max_key(&self) -> i32 {
-        self.get_key_at(self.num_cells() - 1)
+    pub fn set_root(&mut self) {
+        self.dirty = true;
+        self.is_root = true;
     }
 
-    pub fn is_root(&self) -> bool {
-        self.is_root
+    pub fn max_key(&self) -> Vec<u8> {
+        self.get_key_at(self.num_cells() - 1).to_vec()
     }
 
     pub fn is_leaf(&self) -> bool {
-        match self.node_type {
-            NodeType::Leaf => true,
-            _ => false
-        }
+        matches!(self.node_type, NodeType::Leaf)
+    }
+
+    pub fn is_free(&self) -> bool {
+        matches!(self.node_type, NodeType::Free)
+    }
+
+    pub fn is_overflow(&self) -> bool {
+        matches!(self.node_type, NodeType::Overflow)
     }
 
     pub fn num_cells(&self) -> u64 {
         self.num_cells
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.data.len() == 0
+    pub fn cell_size(&self) -> usize {
+        self.cell_size
+    }
+
+    pub fn key_size(&self) -> usize {
+        self.key_size
+    }
+
+    // number of cells that fit in this page ; a leaf can fit more cells when they are smaller than cell_size.
+    pub fn max_cells(&self) -> u64 {
+        match self.node_type {
+            NodeType::Leaf => leaf_max_cells(self.page_size(), self.cell_size),
+            NodeType::Internal => internal_max_cells(self.page_size(), self.key_size),
+            NodeType::Free | NodeType::Overflow => 0
+        }
+    }
+
+    // internal nodes with less cells than this borrow from or merge with a sibling.
+    pub fn min_cells(&self) -> u64 {
+        self.max_cells() / 2
+    }
+
+    // non root pages which are under full borrow from or merge with a sibling.
+    // A leaf is under full when its cells use less than about half of the page ; then it can always
+    // take a cell from its sibling or merge with it.
+    pub fn is_under_full(&self) -> bool {
+        match self.node_type {
+            NodeType::Leaf => self.used_space() < self.min_used_space(),
+            NodeType::Internal => self.num_cells < self.min_cells(),
+            NodeType::Free | NodeType::Overflow => false
+        }
+    }
+
+    // true when page is not under full after giving away cell at cell_pos.
+    pub fn can_lend(&self, cell_pos: u64) -> bool {
+        match self.node_type {
+            NodeType::Leaf => self.used_space() - self.cell_pointer(cell_pos).1 - consts::CELL_POINTER_SIZE >= self.min_used_space(),
+            NodeType::Internal => self.num_cells > self.min_cells(),
+            NodeType::Free | NodeType::Overflow => false
+        }
+    }
+
+    // true when a leaf can take a cell of cell_size ; space of removed cells is reclaimed by defragmenting.
+    pub fn has_room(&self, cell_size: usize) -> bool {
+        self.used_space() + cell_size + consts::CELL_POINTER_SIZE <= self.page_size() - consts::CELL_POINTER_ARRAY_OFFSET
+    }
+
+    // bytes of a leaf taken by cells and their pointers.
+    pub fn used_space(&self) -> usize {
+        (0 .. self.num_cells).map(|pos| self.cell_pointer(pos).1 + consts::CELL_POINTER_SIZE).sum()
+    }
+
+    fn min_used_space(&self) -> usize {
+        (self.page_size() - consts::CELL_POINTER_ARRAY_OFFSET - self.cell_size - consts::CELL_POINTER_SIZE) / 2
     }
 
     pub fn get_data(&mut self) -> &mut Vec<u8> {
         &mut self.data
     }
 
-    pub fn get_key_at(&self, key_pos: u64) -> i32 {
-        match self.node_type {
-            NodeType::Leaf => leaf_get_key_at(&self.data, key_pos),
-            NodeType::Internal => {
-                // handle when key_pos == num_cells - 1
-                // return key after header
-                internal_node_get_key_at(&self.data, key_pos)
-            }
+    // dirty pages are written back before they leave the page cache.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn mark_clean(&mut self) {
+        self.dirty = false;
+    }
+
+    pub fn get_key_at(&self, key_pos: u64) -> &[u8] {
+        let key_offset = match self.node_type {
+            NodeType::Leaf => self.cell_pointer(key_pos).0 + consts::KEY_OFFSET,
+            NodeType::Internal => consts::INTERNAL_NODE_CELL_START_OFFSET + consts::INTERNAL_NODE_KEY_OFFSET + key_pos as usize * self.cell_size,
+            NodeType::Free | NodeType::Overflow => panic!("get_key_at should not be called on free or overflow page")
+        };
+        &self.data[key_offset .. key_offset + self.key_size]
+    }
+
+    // bytes of cell at cell_pos of a leaf.
+    pub fn leaf_cell(&self, cell_pos: u64) -> &[u8] {
+        let (offset, size) = self.cell_pointer(cell_pos);
+        &self.data[offset .. offset + size]
+    }
+
+    // (offset, size) of cell at cell_pos of a leaf.
+    fn cell_pointer(&self, cell_pos: u64) -> (usize, usize) {
+        cell_pointer(&self.data, cell_pos)
+    }
+
+    fn set_cell_pointer(&mut self, cell_pos: u64, offset: usize, size: usize) {
+        let pointer_offset = consts::CELL_POINTER_ARRAY_OFFSET + cell_pos as usize * consts::CELL_POINTER_SIZE;
+        self.data[pointer_offset .. pointer_offset + consts::CELL_POINTER_OFFSET_SIZE].copy_from_slice(&(offset as u16).to_be_bytes());
+        self.data[pointer_offset + consts::CELL_POINTER_OFFSET_SIZE .. pointer_offset + consts::CELL_POINTER_SIZE]
+            .copy_from_slice(&(size as u16).to_be_bytes());
+    }
+
+    // adds cell in front of content and its pointer at cell_pos ; pointers after it move one place right.
+    fn leaf_add_cell(&mut self, cell_pos: u64, cell: &[u8]) -> Result<(), error::Error> {
+        if !self.has_room(cell.len()) {
+            return Err(error::Error::Other(format!("Leaf has no room for a cell of size {} : {} bytes are used", cell.len(), self.used_space())))
+        }
+        let pointers_end = consts::CELL_POINTER_ARRAY_OFFSET + self.num_cells as usize * consts::CELL_POINTER_SIZE;
+        if pointers_end + consts::CELL_POINTER_SIZE + cell.len() > self.content_start {
+            self.defragment();
+        }
+
+        let pointer_offset = consts::CELL_POINTER_ARRAY_OFFSET + cell_pos as usize * consts::CELL_POINTER_SIZE;
+        self.data.copy_within(pointer_offset .. pointers_end, pointer_offset + consts::CELL_POINTER_SIZE);
+        self.write_cell(cell_pos, cell);
+        Ok(())
+    }
+
+    fn write_cell(&mut self, cell_pos: u64, cell: &[u8]) {
+        self.content_start -= cell.len();
+        let offset = self.content_start;
+        self.data[offset .. offset + cell.len()].copy_from_slice(cell);
+        self.set_cell_pointer(cell_pos, offset, cell.len());
+    }
+
+    // removes pointer of cell at cell_pos ; its content is left as a hole till the page is defragmented.
+    fn leaf_remove_cell(&mut self, cell_pos: u64) {
+        let (offset, size) = self.cell_pointer(cell_pos);
+        let pointer_offset = consts::CELL_POINTER_ARRAY_OFFSET + cell_pos as usize * consts::CELL_POINTER_SIZE;
+        let pointers_end = consts::CELL_POINTER_ARRAY_OFFSET + self.num_cells as usize * consts::CELL_POINTER_SIZE;
+        self.data.copy_within(pointer_offset + consts::CELL_POINTER_SIZE .. pointers_end, pointer_offset);
+        if offset == self.content_start {
+            self.content_start += size;
+        }
+    }
+
+    // moves cells to the end of page so that all free space is between pointers and content.
+    fn defragment(&mut self) {
+        let cells: Vec<Vec<u8>> = (0 .. self.num_cells).map(|pos| self.get_cell(pos)).collect();
+        self.content_start = self.page_size();
+        for (pos, cell) in cells.iter().enumerate() {
+            self.write_cell(pos as u64, cell);
         }
     }
 
     pub fn increment_cell_count(&mut self) {
+        self.dirty = true;
         self.num_cells += 1
     }
 
-    pub fn add_data(&mut self, cell_pos: u64, data: &Vec<u8>) -> Result<(), String> {
+    pub fn add_data(&mut self, cell_pos: u64, data: &Vec<u8>) -> Result<(), error::Error> {
+        self.dirty = true;
         if self.num_cells < cell_pos {
-            return Err(format!("cell_pos {} is higher than number of cells {} already present; You should split before : page: {}",
-                cell_pos, self.num_cells, self.print()))
+            return Err(error::Error::Other(format!("cell_pos {} is higher than number of cells {} already present; You should split before : page: {}",
+                cell_pos, self.num_cells, self.print())))
         }
 
         match self.node_type {
-            NodeType::Leaf => {
-                leaf_shift_data(&mut self.data, cell_pos, self.num_cells);
-                leaf_copy_at_cell_pos(&mut self.data, cell_pos, data);
-            },
+            NodeType::Leaf => self.leaf_add_cell(cell_pos, data)?,
             NodeType::Internal => {
-                internal_node_shift_data(&mut self.data, cell_pos, self.num_cells);
-                internal_copy_at_cell_pos(&mut self.data, cell_pos, data)
-            }
+                internal_node_shift_data(&mut self.data, cell_pos, self.num_cells, self.cell_size);
+                internal_copy_at_cell_pos(&mut self.data, cell_pos, self.cell_size, data)
+            },
+            NodeType::Free | NodeType::Overflow => return Err(error::Error::Other(String::from("add_data should not be called on free or overflow page")))
         }
 
         self.increment_cell_count();
         Ok(())
     }
 
-    pub fn update_data(&mut self, cell_pos: u64, data: &Vec<u8>) -> Result<(), String> {
+    pub fn remove_cell(&mut self, cell_pos: u64) -> Result<(), error::Error> {
+        self.dirty = true;
+        if cell_pos >= self.num_cells {
+            return Err(error::Error::Other(format!("cell_pos {} is out of {} cells", cell_pos, self.num_cells)))
+        }
+
+        match self.node_type {
+            NodeType::Leaf => self.leaf_remove_cell(cell_pos),
+            NodeType::Internal => {
+                // move cells after cell_pos one cell left.
+                let cell_offset = consts::INTERNAL_NODE_CELL_START_OFFSET + cell_pos as usize * self.cell_size;
+                let end_offset = consts::INTERNAL_NODE_CELL_START_OFFSET + self.num_cells as usize * self.cell_size;
+                self.data.copy_within(cell_offset + self.cell_size .. end_offset, cell_offset);
+            },
+            NodeType::Free | NodeType::Overflow => return Err(error::Error::Other(String::from("remove_cell should not be called on free or overflow page")))
+        }
+
+        self.num_cells -= 1;
+        if self.num_cells == 0 {
+            self.content_start = self.page_size();
+        }
+        Ok(())
+    }
+
+    // a leaf cell of another size is moved within the page ; it fails when the page has no room for it.
+    pub fn update_data(&mut self, cell_pos: u64, data: &Vec<u8>) -> Result<(), error::Error> {
+        self.dirty = true;
         match self.node_type {
             NodeType::Leaf => {
-                leaf_copy_at_cell_pos(&mut self.data, cell_pos, data);
+                let (offset, size) = self.cell_pointer(cell_pos);
+                if size == data.len() {
+                    self.data[offset .. offset + size].copy_from_slice(data);
+                } else {
+                    if self.used_space() - size + data.len() > self.page_size() - consts::CELL_POINTER_ARRAY_OFFSET {
+                        return Err(error::Error::Other(format!("Leaf has no room for a cell of size {} : {} bytes are used", data.len(), self.used_space())))
+                    }
+                    self.remove_cell(cell_pos)?;
+                    self.add_data(cell_pos, data)?;
+                }
             },
             NodeType::Internal => {
-                internal_copy_at_cell_pos(&mut self.data, cell_pos, data);
-            }
+                internal_copy_at_cell_pos(&mut self.data, cell_pos, self.cell_size, data);
+            },
+            NodeType::Free | NodeType::Overflow => return Err(error::Error::Other(String::from("update_data should not be called on free or overflow page")))
         }
         Ok(())
     }
 
     pub fn print(&self) -> bool {
+        if self.is_free() {
+            println!("free");
+            return true
+        }
+        if self.is_overflow() {
+            println!("overflow: {} bytes, next_page_num: {}", self.num_cells, self.next_sibling_num);
+            return true
+        }
+
         print!("leaf: {}, root: {}, num_cells: {}, keys: ", self.is_leaf(), self.is_root, self.num_cells);
         match self.node_type {
             NodeType::Internal => print!("right_page_num: {}, ", self.get_page_num(self.num_cells())),
@@ -169,7 +383,7 @@ impl Page {
         }
 
         for i in 0 .. self.num_cells {
-            print!("{}, ", self.get_key_at(i));
+            print!("{:?}, ", self.get_key_at(i));
             match self.node_type {
                 NodeType::Internal => {
                     let cell = self.get_cell(i);
@@ -193,48 +407,109 @@ impl Page {
         set_node_type(&mut self.data, &self.node_type);
         set_cell_count(&mut self.data, self.num_cells as usize);
         set_next_sibling_num(&mut self.data, self.next_sibling_num);
+        set_cell_size(&mut self.data, self.cell_size);
+        set_key_size(&mut self.data, self.key_size);
+    }
+
+    // splits a leaf which has no room for cell with cell added at cell_pos ; upper cells move to a new right page.
+    // Both pages get about the same number of bytes ; when any two cells fit in a page both halves fit.
+    // returns the new page and the separator key : max key which remains in this page.
+    pub fn split_with(&mut self, cell_pos: u64, cell: &[u8]) -> Result<(Page, Vec<u8>), error::Error> {
+        self.dirty = true;
+        if !self.is_leaf() {
+            return Err(error::Error::Other(String::from("split_with should only be called on leaf node")))
+        }
+
+        let mut cells: Vec<Vec<u8>> = (0 .. self.num_cells).map(|pos| self.get_cell(pos)).collect();
+        cells.insert(cell_pos as usize, cell.to_vec());
+        let total: usize = cells.iter().map(|c| c.len() + consts::CELL_POINTER_SIZE).sum();
+        let (mut mid, mut left, mut best) = (1, 0, usize::MAX);
+        for (pos, c) in cells.iter().enumerate().take(cells.len() - 1) {
+            left += c.len() + consts::CELL_POINTER_SIZE;
+            let larger_half = left.max(total - left);
+            if larger_half < best {
+                best = larger_half;
+                mid = pos + 1;
+            }
+        }
+
+        let mut new_page = Page::new_leaf(false, self.page_size(), self.key_size, self.cell_size);
+        new_page.next_sibling_num = self.next_sibling_num; // this gets my next_sibling. // caller should set mine.
+        for c in cells[mid ..].iter() {
+            let end_pos = new_page.num_cells;
+            new_page.add_data(end_pos, c)?;
+        }
+
+        self.num_cells = 0;
+        self.content_start = self.page_size();
+        for c in cells[.. mid].iter() {
+            let end_pos = self.num_cells;
+            self.add_data(end_pos, c)?;
+        }
+        Ok((new_page, self.max_key()))
     }
 
-    pub fn split(&mut self) -> Page {
+    // moves upper half of cells of an internal node to a new right page.
+    // returns the new page and the separator key which moves up to parent.
+    pub fn split(&mut self) -> (Page, Vec<u8>) {
+        self.dirty = true;
         match self.node_type {
-            NodeType::Leaf => {
-                let new_my_num_cells = self.num_cells / 2;
-                let mut new_page = Page {
-                    is_root: false,
-                    node_type: NodeType::Leaf,
-                    data: new_leaf_node(false, self.page_size()),
-                    num_cells: self.num_cells - new_my_num_cells,
-                    next_sibling_num: self.next_sibling_num // this gets my next_sibling. // caller should set mine.
-                };
-
-                let to_move_byte_size = new_page.num_cells as usize * consts::CELL_SIZE;
-                let my_start_offset = consts::PAGE_HEADER_SIZE + (new_my_num_cells as usize * consts::CELL_SIZE);
-                let my_end_offset = my_start_offset + to_move_byte_size;
-
-                new_page.data[consts::PAGE_HEADER_SIZE .. consts::PAGE_HEADER_SIZE + to_move_byte_size]
-                    .copy_from_slice(&self.data[my_start_offset .. my_end_offset ]);
-
-                self.num_cells = new_my_num_cells;
-                new_page
-            },
+            NodeType::Leaf => panic!("leaves are split with the cell which does not fit : use split_with"),
             NodeType::Internal => {
-                panic!("split is not implemented for internal nodes")
-            }
+                // HEADER : RIGHT : [0 .. mid) : mid : (mid .. num_cells)
+                // mid's left page becomes my right page and mid's key moves up to parent.
+                let mid = self.num_cells / 2;
+                let mid_cell = self.get_cell(mid);
+                let mut new_page = Page::new_internal(false, self.page_size(), self.key_size, internal_node_right_page_num(&self.data));
+                for cell_pos in mid + 1 .. self.num_cells {
+                    let cell = self.get_cell(cell_pos);
+                    internal_copy_at_cell_pos(&mut new_page.data, cell_pos - mid - 1, self.cell_size, &cell);
+                }
+                new_page.num_cells = self.num_cells - mid - 1;
+
+                set_internal_node_right_page_num(&mut self.data, internal_node_left_page_num(&mid_cell));
+                self.num_cells = mid;
+                (new_page, internal_node_key(&mid_cell).to_vec())
+            },
+            NodeType::Free | NodeType::Overflow => panic!("split should not be called on free or overflow page")
+        }
+    }
+
+    // child at child_pos was split into left_page_num (keys <= separator_key) and right_page_num.
+    pub fn insert_child(&mut self, child_pos: u64, left_page_num: u64, separator_key: &[u8], right_page_num: u64) -> Result<(), error::Error> {
+        self.dirty = true;
+        if self.is_leaf() {
+            return Err(error::Error::Other(String::from("insert_child should not be called on leaf node")))
+        }
+
+        if child_pos == self.num_cells {
+            self.update_right_page_num(right_page_num);
+        } else {
+            let cell = internal_node_cell(self.get_key_at(child_pos), right_page_num);
+            self.update_data(child_pos, &cell)?;
         }
+        self.add_data(child_pos, &internal_node_cell(separator_key, left_page_num))
+    }
+
+    // true when an internal node has no space for another child.
+    pub fn is_full(&self) -> bool {
+        self.num_cells >= self.max_cells()
     }
 
-    pub fn find_key_pos(&self, key: i32) -> u64 {
+    // position of first key >= key.
+    pub fn find_key_pos(&self, key: &[u8]) -> u64 {
         match self.node_type {
             NodeType::Leaf => self.leaf_find_key(key),
-            NodeType::Internal => self.internal_node_find_key(key)
+            NodeType::Internal => self.internal_node_find_key(key),
+            NodeType::Free | NodeType::Overflow => panic!("find_key_pos should not be called on free or overflow page")
         }
     }
 
-    fn internal_node_find_key(&self, key: i32) -> u64 {
+    fn internal_node_find_key(&self, key: &[u8]) -> u64 {
         self.leaf_find_key(key)
     }
 
-    fn leaf_find_key(&self, key: i32) -> u64 {
+    fn leaf_find_key(&self, key: &[u8]) -> u64 {
         let num_keys = self.num_cells();
 
         if num_keys == 0 {
@@ -272,14 +547,18 @@ impl Page {
         }
 
         match self.node_type {
-            NodeType::Leaf => panic!("get_cell not implemented for leaf"),
-            NodeType::Internal => internal_node_cell_at(&self.data, key_pos)
+            NodeType::Leaf => {
+                let (offset, size) = self.cell_pointer(key_pos);
+                self.data[offset .. offset + size].to_vec()
+            },
+            NodeType::Internal => internal_node_cell_at(&self.data, self.cell_size, key_pos),
+            NodeType::Free | NodeType::Overflow => panic!("get_cell should not be called on free or overflow page")
         }
     }
 
     pub fn get_page_num(&self, cell_pos: u64) -> u64 {
         match self.node_type {
-            NodeType::Leaf => panic!("get_page_num should not be called on leaf node"),
+            NodeType::Leaf | NodeType::Free | NodeType::Overflow => panic!("get_page_num should not be called on leaf node, free or overflow page"),
             NodeType::Internal => {
                 if cell_pos == self.num_cells() {
                     page::internal_node_right_page_num(&self.data)
@@ -291,33 +570,43 @@ impl Page {
         }
     }
 
+    // next leaf of a leaf node ; next page of the chain of an overflow page or of the freelist.
     pub fn next_sibling_num(&self) -> u64 {
         match self.node_type {
             NodeType::Internal => panic!("next_sibling_num should not be called for Internal node."),
-            NodeType::Leaf => self.next_sibling_num
+            NodeType::Leaf | NodeType::Free | NodeType::Overflow => self.next_sibling_num
         }
     }
 
     pub fn set_next_sibling_num(&mut self, v: u64) {
+        self.dirty = true;
         self.next_sibling_num = v;
     }
 
     pub fn update_right_page_num(&mut self, right_page_num: u64) {
+        self.dirty = true;
         page::set_internal_node_right_page_num(&mut self.data, right_page_num)
     }
 }
 
-fn is_leaf_node(page: &Vec<u8>) -> bool {
+fn get_node_type(page: &Vec<u8>) -> NodeType {
     let mut node_type_bytes: [u8; consts::PAGE_TYPE_SIZE] = Default::default();
     node_type_bytes.copy_from_slice(&page[consts::PAGE_TYPE_OFFSET..consts::IS_ROOT_OFFSET]);
     let node_type = unsafe { transmute::<[u8;consts::PAGE_TYPE_SIZE], u8>(node_type_bytes) }.to_be();
-    node_type == consts::LEAF_NODE_TYPE
+    match node_type {
+        consts::LEAF_NODE_TYPE => NodeType::Leaf,
+        consts::FREE_PAGE_TYPE => NodeType::Free,
+        consts::OVERFLOW_PAGE_TYPE => NodeType::Overflow,
+        _ => NodeType::Internal
+    }
 }
 
 fn set_node_type(page: &mut Vec<u8>, node_type: &NodeType) {
     let node_type_value = match node_type {
         NodeType::Leaf => consts::LEAF_NODE_TYPE,
-        NodeType::Internal => consts::NONLEAF_NODE_TYPE
+        NodeType::Internal => consts::NONLEAF_NODE_TYPE,
+        NodeType::Free => consts::FREE_PAGE_TYPE,
+        NodeType::Overflow => consts::OVERFLOW_PAGE_TYPE
     };
     let node_type_bytes: [u8; consts::PAGE_TYPE_SIZE] = unsafe { transmute(node_type_value.to_be()) };
     page[consts::PAGE_TYPE_OFFSET..consts::IS_ROOT_OFFSET]
@@ -338,22 +627,9 @@ fn get_num_cells(page: &Vec<u8>) -> u64 {
     unsafe { transmute::<[u8;consts::NUM_ENTRIES_SIZE], u64>(id_bytes) }.to_be()
 }
 
-fn leaf_copy_at_cell_pos(page: &mut Vec<u8>, cell_pos: u64, data: &Vec<u8>) {
-    let cell_offset = consts::PAGE_HEADER_SIZE + (cell_pos as usize * consts::CELL_SIZE);
-    let cell = &mut page[cell_offset .. cell_offset + consts::CELL_SIZE];
-
-    for pos in 0 .. consts::KEY_SIZE {
-        cell[pos] = data[pos];
-    }
-
-    for pos in 0..data.len() {
-        cell[pos + consts::VALUE_OFFSET] = data[pos];
-    }
-}
-
-fn internal_copy_at_cell_pos(page: &mut Vec<u8>, cell_pos: u64, data: &Vec<u8>) {
-    let cell_offset = consts::INTERNAL_NODE_CELL_START_OFFSET + (cell_pos as usize * consts::INTERNAL_NODE_CELL_SIZE);
-    page[cell_offset .. cell_offset + consts::INTERNAL_NODE_CELL_SIZE]
+fn internal_copy_at_cell_pos(page: &mut Vec<u8>, cell_pos: u64, cell_size: usize, data: &Vec<u8>) {
+    let cell_offset = consts::INTERNAL_NODE_CELL_START_OFFSET + (cell_pos as usize * cell_size);
+    page[cell_offset .. cell_offset + cell_size]
         .copy_from_slice(data);
 }
 
@@ -366,16 +642,10 @@ fn shift_data(page: &mut Vec<u8>, copy_start_offset: usize, copy_end_offset: usi
     }
 }
 
-fn leaf_shift_data(page: &mut Vec<u8>, cell_pos: u64, num_cells: u64) {
-    let copy_start_offset = consts::PAGE_HEADER_SIZE + (cell_pos as usize * consts::CELL_SIZE);
-    let copy_end_offset = consts::PAGE_HEADER_SIZE + (num_cells as usize * consts::CELL_SIZE);
-    shift_data(page, copy_start_offset, copy_end_offset, consts::CELL_SIZE)
-}
-
-fn internal_node_shift_data(page: &mut Vec<u8>, cell_pos: u64, num_cells: u64) {
-    let copy_start_offset = consts::INTERNAL_NODE_CELL_START_OFFSET + (cell_pos as usize * consts::INTERNAL_NODE_CELL_SIZE);
-    let copy_end_offset = consts::INTERNAL_NODE_CELL_START_OFFSET + (num_cells as usize * consts::INTERNAL_NODE_CELL_SIZE);
-    shift_data(page, copy_start_offset, copy_end_offset, consts::INTERNAL_NODE_CELL_SIZE)
+fn internal_node_shift_data(page: &mut Vec<u8>, cell_pos: u64, num_cells: u64, cell_size: usize) {
+    let copy_start_offset = consts::INTERNAL_NODE_CELL_START_OFFSET + (cell_pos as usize * cell_size);
+    let copy_end_offset = consts::INTERNAL_NODE_CELL_START_OFFSET + (num_cells as usize * cell_size);
+    shift_data(page, copy_start_offset, copy_end_offset, cell_size)
 }
 
 fn set_cell_count(page: &mut Vec<u8>, count: usize) {
@@ -394,6 +664,38 @@ fn set_next_sibling_num(page: &mut Vec<u8>, next_sibling_num: u64) {
     next_sibling_num_ref.copy_from_slice(&next_sibling_num_bytes);
 }
 
+// (offset, size) of cell at cell_pos from cell pointers of a leaf.
+fn cell_pointer(page: &[u8], cell_pos: u64) -> (usize, usize) {
+    let pointer_offset = consts::CELL_POINTER_ARRAY_OFFSET + cell_pos as usize * consts::CELL_POINTER_SIZE;
+    let mut offset_bytes: [u8; consts::CELL_POINTER_OFFSET_SIZE] = Default::default();
+    offset_bytes.copy_from_slice(&page[pointer_offset .. pointer_offset + consts::CELL_POINTER_OFFSET_SIZE]);
+    let mut size_bytes: [u8; consts::CELL_POINTER_SIZE_SIZE] = Default::default();
+    size_bytes.copy_from_slice(&page[pointer_offset + consts::CELL_POINTER_OFFSET_SIZE .. pointer_offset + consts::CELL_POINTER_SIZE]);
+    (u16::from_be_bytes(offset_bytes) as usize, u16::from_be_bytes(size_bytes) as usize)
+}
+
+fn get_cell_size(page: &[u8]) -> usize {
+    let mut cell_size_bytes: [u8; consts::CELL_SIZE_SIZE] = Default::default();
+    cell_size_bytes.copy_from_slice(&page[consts::CELL_SIZE_OFFSET .. consts::CELL_SIZE_OFFSET + consts::CELL_SIZE_SIZE]);
+    u64::from_be_bytes(cell_size_bytes) as usize
+}
+
+fn set_cell_size(page: &mut [u8], cell_size: usize) {
+    page[consts::CELL_SIZE_OFFSET .. consts::CELL_SIZE_OFFSET + consts::CELL_SIZE_SIZE]
+        .copy_from_slice(&(cell_size as u64).to_be_bytes());
+}
+
+fn get_key_size(page: &[u8]) -> usize {
+    let mut key_size_bytes: [u8; consts::KEY_SIZE_SIZE] = Default::default();
+    key_size_bytes.copy_from_slice(&page[consts::KEY_SIZE_OFFSET .. consts::KEY_SIZE_OFFSET + consts::KEY_SIZE_SIZE]);
+    u16::from_be_bytes(key_size_bytes) as usize
+}
+
+fn set_key_size(page: &mut [u8], key_size: usize) {
+    page[consts::KEY_SIZE_OFFSET .. consts::KEY_SIZE_OFFSET + consts::KEY_SIZE_SIZE]
+        .copy_from_slice(&(key_size as u16).to_be_bytes());
+}
+
 fn is_root_node(page: &Vec<u8>) -> bool {
     let mut is_root_bytes: [u8; consts::IS_ROOT_SIZE] = Default::default();
     is_root_bytes.copy_from_slice(&page[consts::IS_ROOT_OFFSET..consts::NUM_ENTRIES_OFFSET]);
@@ -421,37 +723,9 @@ fn new_leaf_node(is_root: bool, page_size: usize) -> Vec<u8> {
     bytes
 }
 
-fn get_key_at(page: &Vec<u8>, key_start_offset: usize, cell_size: usize, key_pos: u64) -> i32 {
-    let mut id_bytes: [u8; consts::KEY_SIZE] = Default::default();
-    let key_offset = key_start_offset + (key_pos as usize * cell_size);
-
-    id_bytes.copy_from_slice(&page[key_offset .. key_offset + consts::KEY_SIZE]);
-    // key is i32 ; same as id
-    unsafe { transmute::<[u8;4], i32>(id_bytes) }.to_be()
-}
-
-fn leaf_get_key_at(page: &Vec<u8>, key_pos: u64) -> i32 {
-    let key_start_offset = consts::PAGE_HEADER_SIZE + consts::KEY_OFFSET;
-    get_key_at(page, key_start_offset, consts::CELL_SIZE, key_pos)
-}
-
-fn internal_node_get_key_at(page: &Vec<u8>, key_pos: u64) -> i32 {
-    let key_start_offset = consts::INTERNAL_NODE_CELL_START_OFFSET + consts::INTERNAL_NODE_KEY_OFFSET;
-    get_key_at(page, key_start_offset, consts::INTERNAL_NODE_CELL_SIZE, key_pos)
-}
-
-fn internal_node_cell_at(page: &Vec<u8>, key_pos: u64) -> Vec<u8> {
-    let mut cell_bytes: [u8; consts::INTERNAL_NODE_CELL_SIZE] = Default::default();
-    let cell_offset = consts::INTERNAL_NODE_CELL_START_OFFSET + (key_pos as usize * consts::INTERNAL_NODE_CELL_SIZE);
-    cell_bytes.copy_from_slice(&page[cell_offset .. cell_offset + consts::INTERNAL_NODE_CELL_SIZE]);
-    cell_bytes.to_vec()
-}
-
-pub fn deserialize_key(buf: &[u8]) -> i32 {
-    let mut id_bytes: [u8; consts::KEY_SIZE] = Default::default();
-    id_bytes.copy_from_slice(buf);
-    // key is i32 ; same as id
-    unsafe { transmute::<[u8;4], i32>(id_bytes) }.to_be()
+fn internal_node_cell_at(page: &Vec<u8>, cell_size: usize, key_pos: u64) -> Vec<u8> {
+    let cell_offset = consts::INTERNAL_NODE_CELL_START_OFFSET + (key_pos as usize * cell_size);
+    page[cell_offset .. cell_offset + cell_size].to_vec()
 }
 
 pub fn internal_node_left_page_num(buf: &Vec<u8>) -> u64 {
@@ -469,6 +743,10 @@ pub fn internal_node_right_page_num(page: &Vec<u8>) -> u64 {
     unsafe { transmute::<[u8;8], u64>(page_num_bytes) }.to_be()
 }
 
+pub fn internal_node_key(buf: &[u8]) -> &[u8] {
+    &buf[consts::INTERNAL_NODE_KEY_OFFSET ..]
+}
+
 pub fn set_internal_node_right_page_num(page: &mut Vec<u8>, right_page_num: u64) {
     let mut page_num_bytes: [u8; consts::INTERNAL_NODE_PAGE_NUM_SIZE] = Default::default();
     page_num_bytes.copy_from_slice(&unsafe { transmute::<u64, [u8;consts::INTERNAL_NODE_PAGE_NUM_SIZE]>(right_page_num.to_be()) } );
@@ -479,16 +757,42 @@ pub fn set_internal_node_right_page_num(page: &mut Vec<u8>, right_page_num: u64)
 
 fn leaf_node_next_sibling_num(page: &Vec<u8>) -> u64 {
     let mut next_page_num_bytes: [u8; consts::NEXT_LEAF_NODE_NUM_SIZE] = Default::default();
-    next_page_num_bytes.copy_from_slice(&page[consts::NEXT_LEAF_NODE_OFFSET .. consts::PAGE_HEADER_SIZE]);
+    next_page_num_bytes.copy_from_slice(&page[consts::NEXT_LEAF_NODE_OFFSET .. consts::CELL_SIZE_OFFSET]);
     unsafe { transmute::<[u8;consts::NEXT_LEAF_NODE_NUM_SIZE], u64>(next_page_num_bytes) }.to_be()
 }
 
-pub fn internal_node_cell(max_key : i32, next_page_num : u64) -> Vec<u8> {
-    let mut cell : [u8; consts::INTERNAL_NODE_CELL_SIZE] = Default::default();
-    cell[consts::INTERNAL_NODE_LEFT_PAGE_NUM_OFFSET .. consts::INTERNAL_NODE_KEY_OFFSET]
-        .copy_from_slice(&unsafe { transmute::<u64, [u8;consts::INTERNAL_NODE_PAGE_NUM_SIZE]>(next_page_num.to_be()) });
-    cell[consts::INTERNAL_NODE_KEY_OFFSET .. consts::INTERNAL_NODE_KEY_OFFSET + consts::INTERNAL_NODE_KEY_SIZE]
-        .copy_from_slice(&unsafe {transmute::<i32, [u8;consts::INTERNAL_NODE_KEY_SIZE]>(max_key.to_be())});
-    cell.to_vec()
+pub fn internal_node_cell(max_key : &[u8], next_page_num : u64) -> Vec<u8> {
+    let mut cell = Vec::with_capacity(consts::INTERNAL_NODE_PAGE_NUM_SIZE + max_key.len());
+    cell.extend_from_slice(&next_page_num.to_be_bytes());
+    cell.extend_from_slice(max_key);
+    cell
 }
 
+
+// number of value bytes that fit in an overflow page.
+pub fn overflow_capacity(page_size: usize) -> usize {
+    page_size - consts::OVERFLOW_PAYLOAD_OFFSET
+}
+
+// largest cell of a leaf which is kept whole ; like the largest local payload of sqlite, atleast 4 such cells fit in a page.
+pub fn max_local_cell_size(page_size: usize) -> usize {
+    (page_size - consts::CELL_POINTER_ARRAY_OFFSET) / 4 - consts::CELL_POINTER_SIZE
+}
+
+// number of leaf cells of cell_size that fit in a page of page_size with their pointers.
+pub fn leaf_max_cells(page_size: usize, cell_size: usize) -> u64 {
+    ((page_size - consts::CELL_POINTER_ARRAY_OFFSET) / (cell_size + consts::CELL_POINTER_SIZE)) as u64
+}
+
+// number of internal cells with keys of key_size that fit in a page of page_size.
+pub fn internal_max_cells(page_size: usize, key_size: usize) -> u64 {
+    ((page_size - consts::INTERNAL_NODE_CELL_START_OFFSET) / (consts::INTERNAL_NODE_PAGE_NUM_SIZE + key_size)) as u64
+}
+
+// leaf cell : KEY : VALUE
+pub fn leaf_node_cell(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut cell = Vec::with_capacity(key.len() + value.len());
+    cell.extend_from_slice(key);
+    cell.extend_from_slice(value);
+    cell
+}
@@ -1,159 +1,296 @@
-use std::{iter, fs};
+use std::fs;
+use std::collections::HashMap;
 use std::io::{self, Seek, Read, Write};
 use std::fs::OpenOptions;
-use sqliters::page;
+use sqliters::{page, error, replacement, header, consts};
 
+// Pages of the db file are read into a cache of at most cache_size pages. When the cache is full,
+// replacement policy chooses the page which is evicted ; a dirty page is written back before it leaves.
+// So the db file can have any number of pages. Page 0 is the header page ; it is not cached and
+// is written with the dirty pages.
 #[derive(Debug)]
 pub struct Pager {
-    pages: Vec<page::Page>,
+    // resident pages by page_num.
+    pages: HashMap<u64, page::Page>,
+    cache_size: usize,
+    policy: Box<dyn replacement::ReplacementPolicy>,
     db_file: fs::File,
     db_filepath: String,
     filesize: u64,
     page_size: usize,
-    max_pages: usize,
-    num_pages: u64
+    num_pages: u64,
+    // first of the pages freed by deletes ; they are reused before adding pages at the end of db.
+    freelist_head: u64,
+    schema_root: u64
 }
 
 impl Pager {
-    pub fn new(page_size: usize, max_pages: usize, db_filepath: &str) -> Result<Self, String> {
-        // read the db file and initialize the page from it.
+    // page_size is the size of pages of a new db file ; an existing file has the page size of its header.
+    pub fn new(page_size: usize, cache_size: usize, policy: Box<dyn replacement::ReplacementPolicy>, db_filepath: &str) -> Result<Self, error::Error> {
+        // checked before the file is created, so that a bad page size leaves no empty file behind.
+        if !header::is_valid_page_size(page_size) {
+            return Err(error::Error::Other(format!("Page size {} should be a power of two from {} to {}",
+                page_size, consts::MIN_PAGE_SIZE, consts::MAX_PAGE_SIZE)))
+        }
         let file = open_or_create_db_file(db_filepath)?;
         let filesize = get_filesize(db_filepath)?;
         let mut pager = Pager {
-            pages: iter::repeat(page::Page::empty()).take(max_pages).collect(),
+            pages: HashMap::new(),
+            // a page is in cache while it is used.
+            cache_size: cache_size.max(1),
+            policy,
             db_file: file,
             db_filepath: String::from(db_filepath),
             filesize: filesize,
             page_size: page_size,
-            max_pages: max_pages,
-            num_pages: 0
+            num_pages: 0,
+            freelist_head: 0,
+            schema_root: 0
         };
 
-        pager.num_pages = pager.num_db_pages();
+        if filesize == 0 {
+            pager.num_pages = 1;
+            pager.write_header()?;
+            return Ok(pager)
+        }
+
+        let mut header_bytes = vec![0; (filesize as usize).min(consts::DB_HEADER_SIZE)];
+        pager.read_bytes(0, &mut header_bytes)?;
+        if header::Header::has_magic(&header_bytes) {
+            let header = header::Header::read(&header_bytes)?;
+            if filesize < header.page_count * header.page_size as u64 {
+                return Err(error::Error::Corrupt(format!("Db file has {} bytes : header has {} pages of {} bytes",
+                    filesize, header.page_count, header.page_size)))
+            }
+            pager.page_size = header.page_size;
+            pager.num_pages = header.page_count;
+            pager.freelist_head = header.freelist_head;
+            pager.schema_root = header.schema_root;
+        } else if filesize % consts::HEADERLESS_PAGE_SIZE as u64 == 0 && header::is_headerless_root(&header_bytes) {
+            pager.migrate_headerless()?;
+        } else {
+            return Err(error::Error::NotADatabase(format!("'{}' does not start with the magic of sqliters", db_filepath)))
+        }
 
         Ok(pager)
     }
 
-    pub fn get_page(&mut self, page_num: usize) -> Result<&mut page::Page, String> {
-        if page_num >= self.max_pages {
-            return Err(format!("{} greater than max pages {}.", page_num, self.max_pages));
+    pub fn get_page(&mut self, page_num: usize) -> Result<&mut page::Page, error::Error> {
+        let page_num = page_num as u64;
+        if page_num == consts::HEADER_PAGE_NUM || page_num >= self.num_pages {
+            return Err(error::Error::Corrupt(format!("Page {} is not a page of a B+tree : num_pages {}", page_num, self.num_pages)));
+        }
+
+        if self.pages.contains_key(&page_num) {
+            self.policy.access(page_num);
+        } else {
+            // pages which are not in cache were written to db file when they were evicted.
+            let page = self.read_page_from_file(page_num)?;
+            self.cache_page(page_num, page)?;
+        }
+
+        Ok(self.pages.get_mut(&page_num).expect("page was cached"))
+    }
+
+    // adds page to a free slot or at the end of db and returns its page_num.
+    pub fn new_page(&mut self, page: page::Page) -> Result<u64, error::Error> {
+        let page_num = if self.freelist_head != 0 {
+            let page_num = self.freelist_head;
+            let free_page = self.get_page(page_num as usize)?;
+            if !free_page.is_free() {
+                return Err(error::Error::Corrupt(format!("Page {} of freelist is not a free page", page_num)))
+            }
+            self.freelist_head = free_page.next_sibling_num();
+            page_num
+        } else {
+            self.get_unused_page_num()
+        };
+        self.cache_page(page_num, page)?;
+        Ok(page_num)
+    }
+
+    pub fn free_page(&mut self, page_num: u64) -> Result<(), error::Error> {
+        if self.get_page(page_num as usize)?.is_free() {
+            return Err(error::Error::Corrupt(format!("Page {} can not be freed : it is already free", page_num)))
         }
 
-        if self.pages[page_num].is_empty() {
-            println!("Num db pages: {}", self.num_db_pages());
-            if self.num_db_pages() > page_num as u64 {
-                // page is present in db file
-                self.read_page_from_file(page_num)?;
-            } else {
-                self.pages[page_num] = page::Page::new_leaf(true, self.page_size);
-                self.num_pages += 1; // one more page added.
+        let page = page::Page::new_free(self.page_size, self.freelist_head);
+        self.cache_page(page_num, page)?;
+        self.freelist_head = page_num;
+        Ok(())
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    // root page of the catalog ; 0 till it is set for a new db file.
+    pub fn schema_root(&self) -> u64 {
+        self.schema_root
+    }
+
+    pub fn set_schema_root(&mut self, schema_root: u64) {
+        self.schema_root = schema_root;
+    }
+
+    // writes bytes to a new chain of overflow pages and returns its first page_num.
+    // Chain is written from its end so that each page knows its next page ; pages are freed again
+    // when the pager runs out of pages.
+    pub fn write_overflow(&mut self, bytes: &[u8]) -> Result<u64, error::Error> {
+        let capacity = page::overflow_capacity(self.page_size);
+        let mut written = vec![];
+        let mut next_page_num = 0;
+        for chunk in bytes.chunks(capacity).rev() {
+            match self.new_page(page::Page::new_overflow(self.page_size, chunk, next_page_num)) {
+                Ok(page_num) => {
+                    written.push(page_num);
+                    next_page_num = page_num;
+                },
+                Err(e) => {
+                    for page_num in written {
+                        self.free_page(page_num)?;
+                    }
+                    return Err(e)
+                }
             }
         }
+        Ok(next_page_num)
+    }
 
-        return Ok(&mut self.pages[page_num])
+    // len bytes from the chain of overflow pages starting at first_page_num.
+    pub fn read_overflow(&mut self, first_page_num: u64, len: usize) -> Result<Vec<u8>, error::Error> {
+        let mut bytes = Vec::with_capacity(len);
+        let mut page_num = first_page_num;
+        while bytes.len() < len {
+            if page_num == 0 {
+                return Err(error::Error::Corrupt(format!("Overflow chain at page {} ends after {} of {} bytes", first_page_num, bytes.len(), len)))
+            }
+            let page = self.get_page(page_num as usize)?;
+            bytes.extend_from_slice(page.overflow_payload()?);
+            page_num = page.next_sibling_num();
+        }
+        bytes.truncate(len);
+        Ok(bytes)
     }
 
-    pub fn read_page_from_file(&mut self, page_num: usize) -> Result<(), String> {
+    pub fn free_overflow(&mut self, first_page_num: u64) -> Result<(), error::Error> {
+        let mut page_num = first_page_num;
+        while page_num != 0 {
+            let next_page_num = {
+                let page = self.get_page(page_num as usize)?;
+                if !page.is_overflow() {
+                    return Err(error::Error::Corrupt(format!("Page {} of overflow chain at page {} is not an overflow page", page_num, first_page_num)))
+                }
+                page.next_sibling_num()
+            };
+            self.free_page(page_num)?;
+            page_num = next_page_num;
+        }
+        Ok(())
+    }
+
+    fn read_page_from_file(&mut self, page_num: u64) -> Result<page::Page, error::Error> {
         // pages are written in order 0,1,2..N
         let mut page_buffer = vec![0; self.page_size];
-        let page_offset = (page_num * self.page_size) as u64;
-        let offset = self.db_file
-            .seek(io::SeekFrom::Start(page_offset))
-            .map_err(|e| format!("Error in seek to offset {} : error {}", page_offset, e))?;
+        self.read_bytes(page_num * self.page_size as u64, &mut page_buffer)?;
+        Ok(page::Page::new(page_buffer))
+    }
 
-        if offset != page_offset {
-            return Err(format!("Failed to seek to offset {} : offset reached : {}", page_offset, offset))
+    fn read_bytes(&mut self, offset: u64, buffer: &mut [u8]) -> Result<(), error::Error> {
+        let offset_reached = self.db_file
+            .seek(io::SeekFrom::Start(offset))
+            .map_err(|e| error::Error::io(format!("Error in seek to offset {}", offset), e))?;
+
+        if offset_reached != offset {
+            return Err(error::Error::Corrupt(format!("Failed to seek to offset {} : offset reached : {}", offset, offset_reached)))
         }
 
         let bytes_read = self.db_file
-            .read(page_buffer.as_mut())
-            .map_err(|e| format!("Error in read to offset {} : error {}", page_offset, e))?;
+            .read(buffer)
+            .map_err(|e| error::Error::io(format!("Error in read to offset {}", offset), e))?;
 
-        if bytes_read != page_buffer.len() {
-            format!("Could not read full page_buffer : bytes_read {} : page_buffer_len {}", bytes_read, page_buffer.len());
+        if bytes_read != buffer.len() {
+            return Err(error::Error::Corrupt(format!("Could not read full page_buffer : bytes_read {} : page_buffer_len {}", bytes_read, buffer.len())))
         }
-
-        self.pages[page_num] = page::Page::new(page_buffer);
         Ok(())
     }
 
-    pub fn delete_db_file(&mut self) -> Result<(), String> {
-        fs::remove_file(self.db_filepath.as_str()).map_err(|e| format!("Unable to delete db_file : error {}", e.to_string()))
+    fn write_header(&mut self) -> Result<(), error::Error> {
+        let header = header::Header {
+            page_size: self.page_size,
+            page_count: self.num_pages,
+            freelist_head: self.freelist_head,
+            schema_root: self.schema_root
+        };
+        self.db_file.seek(io::SeekFrom::Start(0))
+            .map_err(|e| error::Error::io(String::from("Failed to seek in db file"), e))?;
+        self.db_file.write_all(&header.write())
+            .map_err(|e| error::Error::io(String::from("Failed to write header page"), e))?;
+        self.filesize = self.filesize.max(self.page_size as u64);
+        Ok(())
     }
 
-    pub fn flush_page(&mut self, page_pos: usize) -> Result<(), String> {
-        if page_pos >= self.pages.len() {
-            return Err(format!("Page pos {} is greater than total number of pages.", self.pages.len()))
+    // files written before the header page have the catalog root at page 0. The root moves
+    // to a new page at the end, so that no page num changes, and page 0 becomes the header page.
+    // Free pages were found by their page type ; they are linked into the freelist.
+    fn migrate_headerless(&mut self) -> Result<(), error::Error> {
+        self.page_size = consts::HEADERLESS_PAGE_SIZE;
+        let old_num_pages = self.filesize / self.page_size as u64;
+        self.num_pages = old_num_pages + 1;
+
+        let mut root = self.read_page_from_file(0)?;
+        write_page(&mut self.db_file, self.page_size, old_num_pages, &mut root)?;
+        self.schema_root = old_num_pages;
+
+        for page_num in 1 .. old_num_pages {
+            let mut page = self.read_page_from_file(page_num)?;
+            if page.is_free() {
+                page.set_next_sibling_num(self.freelist_head);
+                write_page(&mut self.db_file, self.page_size, page_num, &mut page)?;
+                self.freelist_head = page_num;
+            }
         }
 
-        let page = &mut self.pages[page_pos];
-
-        if !page.is_empty() { // never bought in memory and never written ; so flush is no-op
-            if page.page_size() < self.page_size {
-                return Err(format!("Unexpected : Page size {} is smaller than the size to flush {}", page.page_size(), self.page_size))
-            }
-            self.db_file.seek(io::SeekFrom::Start((page_pos * self.page_size) as u64))
-                    .map_err(|e| format!("Failed to seek in db file {}", e))?;
+        self.filesize = self.num_pages * self.page_size as u64;
+        self.write_header()
+    }
 
-            page.flush();
+    pub fn delete_db_file(&mut self) -> Result<(), error::Error> {
+        fs::remove_file(self.db_filepath.as_str()).map_err(|e| error::Error::io(String::from("Unable to delete db_file"), e))
+    }
 
-            self.db_file.write(page.get_data())
-                .map_err(|e| format!("Failed to write file {}", e))?;
+    // writes all dirty pages of cache to db file ; they stay in cache.
+    pub fn flush_pages(&mut self) -> Result<(), error::Error> {
+        let mut page_nums: Vec<u64> = self.pages.iter()
+            .filter(|&(_, page)| page.is_dirty())
+            .map(|(&page_num, _)| page_num)
+            .collect();
+        page_nums.sort();
+        for page_num in page_nums {
+            let page = self.pages.get_mut(&page_num).expect("page was cached");
+            write_page(&mut self.db_file, self.page_size, page_num, page)?;
+            self.filesize = self.filesize.max((page_num + 1) * self.page_size as u64);
         }
+        self.write_header()
+    }
 
-        Ok(())
+    // number of pages in cache.
+    pub fn cached_pages(&self) -> usize {
+        self.pages.len()
     }
 
-    pub fn num_db_pages(&self) -> u64 {
-        return self.filesize / (self.page_size as u64);
+    #[cfg(test)]
+    pub fn dirty_pages(&self) -> usize {
+        self.pages.values().filter(|page| page.is_dirty()).count()
     }
 
     pub fn num_pages(&self) -> u64 {
         return self.num_pages;
     }
 
-    pub fn close_db(&mut self) -> Result<(), String> {
+    pub fn close_db(&mut self) -> Result<(), error::Error> {
         self.db_file.flush()
-            .map_err(|e| format!("Failed to flush the db_file to disk : error : {}", e))
-    }
-
-    pub fn split_page(&mut self, page_num: usize) -> Result<(), String> {
-        if self.pages[page_num].is_empty() {
-            return Err(format!("Can't split an empty page : {}", page_num));
-        }
-
-        if self.num_pages >= self.max_pages as u64 {
-            return Err(format!("Already added max number of pages: {} : Adding {}", self.max_pages, page_num));
-        }
-
-        let max_key = self.pages[page_num].max_key();
-        let new_sibling_page = self.pages[page_num].split();
-        let next_page_num = self.get_unused_page_num();
-        self.pages[page_num].set_next_sibling_num(next_page_num);
-
-        self.pages[next_page_num as usize] = new_sibling_page;
-
-        if self.pages[page_num].is_root() {
-            let left_leaf_page_num = self.get_unused_page_num();
-            self.pages[left_leaf_page_num as usize] = self.pages[page_num].clone();
-            self.pages[left_leaf_page_num as usize].set_non_root();
-            // keep root at page_num
-            self.pages[page_num] = page::Page::new_root(self.page_size, left_leaf_page_num, next_page_num, &self.pages[left_leaf_page_num as usize]);
-        } else  {
-            // we know 0 is the parent of all leafs
-            // assume root node has space.
-            let cell_pos = self.pages[0].find_key_pos(max_key);
-            let num_cells = self.pages[0].num_cells();
-            let page_num_max_key = self.pages[page_num].max_key();
-            self.pages[0].update_data(cell_pos, &page::internal_node_cell(max_key, next_page_num))?;
-            self.pages[0].add_data(cell_pos, &page::internal_node_cell(page_num_max_key, page_num as u64))?;
-            if cell_pos == num_cells {
-                // new right most leaf added ; update it.
-                self.pages[0].update_right_page_num(next_page_num);
-            }
-        }
-
-        Ok(())
+            .map_err(|e| error::Error::io(String::from("Failed to flush the db_file to disk"), e))
     }
 
     fn get_unused_page_num(&mut self) -> u64 {
@@ -162,31 +299,36 @@ impl Pager {
         old_num_pages
     }
 
+    // page is put in cache in place of its old content ; a page chosen by policy is evicted when cache is full.
+    fn cache_page(&mut self, page_num: u64, page: page::Page) -> Result<(), error::Error> {
+        if self.pages.contains_key(&page_num) {
+            self.policy.access(page_num);
+            self.pages.insert(page_num, page);
+            return Ok(())
+        }
+
+        if self.pages.len() >= self.cache_size {
+            let evicted_num = self.policy.evict().expect("full cache has a page to evict");
+            let mut evicted = self.pages.remove(&evicted_num)
+                .ok_or_else(|| error::Error::Other(format!("Replacement policy evicted page {} which is not cached", evicted_num)))?;
+            if evicted.is_dirty() {
+                write_page(&mut self.db_file, self.page_size, evicted_num, &mut evicted)?;
+                self.filesize = self.filesize.max((evicted_num + 1) * self.page_size as u64);
+            }
+        }
+        self.pages.insert(page_num, page);
+        self.policy.insert(page_num);
+        Ok(())
+    }
+
+    // pages which are not in cache are not printed.
     pub fn print(&self) {
         for i in 0 .. self.num_pages {
-            print!("page_num: {} => ", i);
-            self.pages[i as usize].print();
-        }
-    }
-
-    pub fn find_key_pos(&mut self, key: i32) -> Result<(u64, u64), String> {
-        let mut page_num = 0;
-        loop {
-            let page = self.get_page(page_num)?;
-            if page.is_leaf() {
-                return Ok((page_num as u64, page.find_key_pos(key)))
-            } else {
-                let pos = page.find_key_pos(key);
-                let new_page_num = page.get_page_num(pos) as usize;
-                if new_page_num == page_num {
-                    break;
-                }
-                page_num = new_page_num;
+            if let Some(page) = self.pages.get(&i) {
+                print!("page_num: {} => ", i);
+                page.print();
             }
         }
-
-        self.print();
-        panic!("Can't have same page_num {}", page_num);
     }
 }
 
@@ -196,7 +338,23 @@ impl Drop for Pager {
     }
 }
 
-fn open_or_create_db_file(db_filepath: &str) -> Result<fs::File, String> {
+// writes page at page_num of db file.
+fn write_page(db_file: &mut fs::File, page_size: usize, page_num: u64, page: &mut page::Page) -> Result<(), error::Error> {
+    if page.page_size() < page_size {
+        return Err(error::Error::Other(format!("Unexpected : Page size {} is smaller than the size to flush {}", page.page_size(), page_size)))
+    }
+    db_file.seek(io::SeekFrom::Start(page_num * page_size as u64))
+        .map_err(|e| error::Error::io(String::from("Failed to seek in db file"), e))?;
+
+    page.flush();
+
+    db_file.write_all(page.get_data())
+        .map_err(|e| error::Error::io(String::from("Failed to write file"), e))?;
+    page.mark_clean();
+    Ok(())
+}
+
+fn open_or_create_db_file(db_filepath: &str) -> Result<fs::File, error::Error> {
     let file = OpenOptions::new()
         .read(true)
         .write(true)
@@ -204,12 +362,12 @@ fn open_or_create_db_file(db_filepath: &str) -> Result<fs::File, String> {
         .open(db_filepath);
 
     match file {
-        Err(e) => Err(format!("Unable to open/create db file {} : error {}", db_filepath, e)),
+        Err(e) => Err(error::Error::io(format!("Unable to open/create db file {}", db_filepath), e)),
         Ok(file) => Ok(file)
     }
 }
 
-fn get_filesize(db_filepath: &str) -> Result<u64, String> {
+fn get_filesize(db_filepath: &str) -> Result<u64, error::Error> {
     Ok(fs::metadata(db_filepath)
-        .map_err(|e| format!("Unable to get metadata of file {} : error {}", db_filepath, e))?.len())
+        .map_err(|e| error::Error::io(format!("Unable to get metadata of file {}", db_filepath), e))?.len())
 }
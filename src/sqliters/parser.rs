@@ -0,0 +1,991 @@
+use sqliters::{ast, lexer, schema, timestamp};
+use sqliters::lexer::{Keyword, Token, TokenKind};
+
+// Recursive descent parser over tokens from lexer.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    // parameters seen so far ; next ? is numbered one more.
+    parameter_count: usize
+}
+
+// parses one statement ; a trailing ';' is allowed.
+pub fn parse(sql: &str) -> Result<ast::Statement, String> {
+    let mut parser = Parser {
+        tokens: lexer::tokenize(sql)?,
+        pos: 0,
+        parameter_count: 0
+    };
+
+    let statement = parser.parse_statement()?;
+    parser.consume_if(&TokenKind::Semicolon);
+    parser.expect(&TokenKind::Eof)?;
+    Ok(statement)
+}
+
+impl Parser {
+    fn parse_statement(&mut self) -> Result<ast::Statement, String> {
+        match *self.peek().kind() {
+            TokenKind::Keyword(Keyword::Create) => self.parse_create(),
+            TokenKind::Keyword(Keyword::Insert) => self.parse_insert().map(ast::Statement::Insert),
+            TokenKind::Keyword(Keyword::Select) => self.parse_select().map(ast::Statement::Select),
+            TokenKind::Keyword(Keyword::Delete) => self.parse_delete().map(ast::Statement::Delete),
+            TokenKind::Keyword(Keyword::Update) => self.parse_update().map(ast::Statement::Update),
+            _ => Err(self.error_at_current("expected CREATE, INSERT, SELECT, UPDATE or DELETE"))
+        }
+    }
+
+    fn parse_create(&mut self) -> Result<ast::Statement, String> {
+        self.expect_keyword(Keyword::Create)?;
+        if self.consume_keyword_if(Keyword::Table) {
+            self.parse_create_table().map(ast::Statement::CreateTable)
+        } else if self.consume_keyword_if(Keyword::Unique) {
+            self.expect_keyword(Keyword::Index)?;
+            self.parse_create_index(true).map(ast::Statement::CreateIndex)
+        } else if self.consume_keyword_if(Keyword::Index) {
+            self.parse_create_index(false).map(ast::Statement::CreateIndex)
+        } else {
+            Err(self.error_at_current("expected TABLE, INDEX or UNIQUE INDEX"))
+        }
+    }
+
+    // CREATE TABLE name ( column_def [, column_def]* [, PRIMARY KEY ( column [, column]* )] )
+    fn parse_create_table(&mut self) -> Result<ast::CreateTableStatement, String> {
+        let table_name = self.expect_identifier()?;
+
+        self.expect(&TokenKind::LeftParen)?;
+        let mut columns = vec![self.parse_column_def()?];
+        let mut primary_key = vec![];
+        while self.consume_if(&TokenKind::Comma) {
+            // the table constraint comes after all columns.
+            if self.consume_keyword_if(Keyword::Primary) {
+                self.expect_keyword(Keyword::Key)?;
+                primary_key = self.parse_column_names()?;
+                break;
+            }
+            columns.push(self.parse_column_def()?);
+        }
+        self.expect(&TokenKind::RightParen)?;
+
+        Ok(ast::CreateTableStatement {
+            table_name,
+            columns,
+            primary_key
+        })
+    }
+
+    // CREATE [UNIQUE] INDEX name ON table ( column )
+    fn parse_create_index(&mut self, unique: bool) -> Result<ast::CreateIndexStatement, String> {
+        let index_name = self.expect_identifier()?;
+        self.expect_keyword(Keyword::On)?;
+        let table_name = self.expect_identifier()?;
+        self.expect(&TokenKind::LeftParen)?;
+        let column = self.expect_identifier()?;
+        self.expect(&TokenKind::RightParen)?;
+
+        Ok(ast::CreateIndexStatement {
+            unique,
+            index_name,
+            table_name,
+            column
+        })
+    }
+
+    // name type [( size )] [PRIMARY KEY [AUTOINCREMENT] | UNIQUE | NOT NULL]*
+    fn parse_column_def(&mut self) -> Result<ast::ColumnDef, String> {
+        let name = self.expect_identifier()?;
+
+        let type_token = self.peek().clone();
+        let type_name = self.expect_identifier()?;
+        let size = if self.consume_if(&TokenKind::LeftParen) {
+            let size = self.expect_integer()?;
+            self.expect(&TokenKind::RightParen)?;
+            Some(size as usize)
+        } else {
+            None
+        };
+        let column_type = schema::ColumnType::new(&type_name, size)
+            .map_err(|e| error_at(&type_token, &e.to_string()))?;
+
+        let (mut primary_key, mut autoincrement, mut unique, mut not_null) = (false, false, false, false);
+        loop {
+            if self.consume_keyword_if(Keyword::Primary) {
+                self.expect_keyword(Keyword::Key)?;
+                primary_key = true;
+                autoincrement = self.consume_keyword_if(Keyword::Autoincrement);
+            } else if self.consume_keyword_if(Keyword::Unique) {
+                unique = true;
+            } else if self.consume_keyword_if(Keyword::Not) {
+                self.expect_keyword(Keyword::Null)?;
+                not_null = true;
+            } else {
+                break;
+            }
+        }
+
+        Ok(ast::ColumnDef {
+            name,
+            column_type,
+            primary_key,
+            autoincrement,
+            unique,
+            not_null
+        })
+    }
+
+    // INSERT [OR REPLACE | OR IGNORE] INTO name [( column [, column]* )] VALUES ( literal [, literal]* ) [, ( ... )]*
+    //   [ON CONFLICT [( column )] DO UPDATE SET column = expr [, column = expr]* | DO NOTHING]
+    fn parse_insert(&mut self) -> Result<ast::InsertStatement, String> {
+        self.expect_keyword(Keyword::Insert)?;
+        let or_action = if self.consume_keyword_if(Keyword::Or) {
+            if self.consume_keyword_if(Keyword::Replace) {
+                Some(ast::OnConflict::Replace)
+            } else if self.consume_keyword_if(Keyword::Ignore) {
+                Some(ast::OnConflict::Ignore)
+            } else {
+                return Err(self.error_at_current("expected REPLACE or IGNORE"))
+            }
+        } else {
+            None
+        };
+        self.expect_keyword(Keyword::Into)?;
+        let table_name = self.expect_identifier()?;
+
+        let columns = if *self.peek().kind() == TokenKind::LeftParen {
+            Some(self.parse_column_names()?)
+        } else {
+            None
+        };
+
+        self.expect_keyword(Keyword::Values)?;
+        let mut rows = vec![self.parse_values_row()?];
+        while self.consume_if(&TokenKind::Comma) {
+            rows.push(self.parse_values_row()?);
+        }
+
+        let (on_conflict, conflict_target) = match or_action {
+            Some(action) => (action, None),
+            None => self.parse_on_conflict()?
+        };
+
+        Ok(ast::InsertStatement {
+            table_name,
+            columns,
+            rows,
+            on_conflict,
+            conflict_target
+        })
+    }
+
+    fn parse_on_conflict(&mut self) -> Result<(ast::OnConflict, Option<String>), String> {
+        if !self.consume_keyword_if(Keyword::On) {
+            return Ok((ast::OnConflict::Abort, None))
+        }
+
+        self.expect_keyword(Keyword::Conflict)?;
+        let target = if self.consume_if(&TokenKind::LeftParen) {
+            let column = self.expect_identifier()?;
+            self.expect(&TokenKind::RightParen)?;
+            Some(column)
+        } else {
+            None
+        };
+
+        self.expect_keyword(Keyword::Do)?;
+        if self.consume_keyword_if(Keyword::Nothing) {
+            return Ok((ast::OnConflict::Ignore, target))
+        }
+        self.expect_keyword(Keyword::Update)?;
+        self.expect_keyword(Keyword::Set)?;
+        Ok((ast::OnConflict::Update(self.parse_assignments()?), target))
+    }
+
+    // ( column [, column]* )
+    fn parse_column_names(&mut self) -> Result<Vec<String>, String> {
+        self.expect(&TokenKind::LeftParen)?;
+        let mut columns = vec![self.expect_identifier()?];
+        while self.consume_if(&TokenKind::Comma) {
+            columns.push(self.expect_identifier()?);
+        }
+        self.expect(&TokenKind::RightParen)?;
+        Ok(columns)
+    }
+
+    fn parse_values_row(&mut self) -> Result<Vec<ast::Literal>, String> {
+        self.expect(&TokenKind::LeftParen)?;
+        let mut values = vec![self.parse_literal()?];
+        while self.consume_if(&TokenKind::Comma) {
+            values.push(self.parse_literal()?);
+        }
+        self.expect(&TokenKind::RightParen)?;
+        Ok(values)
+    }
+
+    // [-] number | string | blob | TRUE | FALSE | TIMESTAMP string
+    fn parse_literal(&mut self) -> Result<ast::Literal, String> {
+        let negative = self.consume_if(&TokenKind::Minus);
+        let token = self.advance();
+        match (token.kind().clone(), negative) {
+            (TokenKind::Integer(value), true) => Ok(ast::Literal::Integer(-value)),
+            (TokenKind::Integer(value), false) => Ok(ast::Literal::Integer(value)),
+            (TokenKind::Real(value), true) => Ok(ast::Literal::Real(-value)),
+            (TokenKind::Real(value), false) => Ok(ast::Literal::Real(value)),
+            (TokenKind::String(value), false) => Ok(ast::Literal::String(value)),
+            (TokenKind::Blob(value), false) => Ok(ast::Literal::Blob(value)),
+            (TokenKind::Keyword(Keyword::True), false) => Ok(ast::Literal::Boolean(true)),
+            (TokenKind::Keyword(Keyword::False), false) => Ok(ast::Literal::Boolean(false)),
+            (TokenKind::Keyword(Keyword::Null), false) => Ok(ast::Literal::Null),
+            (TokenKind::Question, false) => {
+                self.parameter_count += 1;
+                Ok(ast::Literal::Parameter(self.parameter_count))
+            },
+            (TokenKind::Identifier(ref name), false) if name.eq_ignore_ascii_case("timestamp") => {
+                let text_token = self.advance();
+                match *text_token.kind() {
+                    TokenKind::String(ref text) => timestamp::parse(text).map(ast::Literal::Timestamp).map_err(|e| error_at(&text_token, &e)),
+                    ref kind => Err(error_at(&text_token, &format!("expected string after TIMESTAMP, found {}", kind)))
+                }
+            },
+            (_, true) => Err(error_at(&token, &format!("expected number after '-', found {}", token.kind()))),
+            (_, false) => Err(error_at(&token, &format!("expected a value, found {}", token.kind())))
+        }
+    }
+
+    // SELECT select_item [, select_item]* FROM name [WHERE expr]
+    //   [ORDER BY order_by_item [, order_by_item]*] [LIMIT integer [OFFSET integer]]
+    fn parse_select(&mut self) -> Result<ast::SelectStatement, String> {
+        self.expect_keyword(Keyword::Select)?;
+        let mut projection = vec![self.parse_select_item()?];
+        while self.consume_if(&TokenKind::Comma) {
+            projection.push(self.parse_select_item()?);
+        }
+        self.expect_keyword(Keyword::From)?;
+        let table_name = self.expect_identifier()?;
+        let where_clause = self.parse_where()?;
+
+        let mut group_by = vec![];
+        if self.consume_keyword_if(Keyword::Group) {
+            self.expect_keyword(Keyword::By)?;
+            group_by.push(self.parse_expr()?);
+            while self.consume_if(&TokenKind::Comma) {
+                group_by.push(self.parse_expr()?);
+            }
+        }
+
+        let mut order_by = vec![];
+        if self.consume_keyword_if(Keyword::Order) {
+            self.expect_keyword(Keyword::By)?;
+            order_by.push(self.parse_order_by_item()?);
+            while self.consume_if(&TokenKind::Comma) {
+                order_by.push(self.parse_order_by_item()?);
+            }
+        }
+
+        let (mut limit, mut offset) = (None, None);
+        if self.consume_keyword_if(Keyword::Limit) {
+            limit = Some(self.expect_integer()? as u64);
+            if self.consume_keyword_if(Keyword::Offset) {
+                offset = Some(self.expect_integer()? as u64);
+            }
+        }
+
+        Ok(ast::SelectStatement {
+            projection,
+            table_name,
+            where_clause,
+            group_by,
+            order_by,
+            limit,
+            offset
+        })
+    }
+
+    // expr [ASC | DESC]
+    fn parse_order_by_item(&mut self) -> Result<ast::OrderByItem, String> {
+        let expr = self.parse_expr()?;
+        let descending = if self.consume_keyword_if(Keyword::Desc) {
+            true
+        } else {
+            self.consume_keyword_if(Keyword::Asc);
+            false
+        };
+        Ok(ast::OrderByItem {
+            expr,
+            descending
+        })
+    }
+
+    // * | expr [[AS] name]
+    fn parse_select_item(&mut self) -> Result<ast::SelectItem, String> {
+        if self.consume_if(&TokenKind::Star) {
+            return Ok(ast::SelectItem::Wildcard)
+        }
+
+        let expr = self.parse_expr()?;
+        let alias = if self.consume_keyword_if(Keyword::As) {
+            Some(self.expect_identifier()?)
+        } else if let TokenKind::Identifier(_) = *self.peek().kind() {
+            Some(self.expect_identifier()?)
+        } else {
+            None
+        };
+
+        Ok(ast::SelectItem::Expr {
+            expr,
+            alias
+        })
+    }
+
+    // DELETE FROM name [WHERE expr]
+    fn parse_delete(&mut self) -> Result<ast::DeleteStatement, String> {
+        self.expect_keyword(Keyword::Delete)?;
+        self.expect_keyword(Keyword::From)?;
+        let table_name = self.expect_identifier()?;
+        let where_clause = self.parse_where()?;
+
+        Ok(ast::DeleteStatement {
+            table_name,
+            where_clause
+        })
+    }
+
+    // UPDATE name SET column = expr [, column = expr]* [WHERE expr]
+    fn parse_update(&mut self) -> Result<ast::UpdateStatement, String> {
+        self.expect_keyword(Keyword::Update)?;
+        let table_name = self.expect_identifier()?;
+        self.expect_keyword(Keyword::Set)?;
+        let assignments = self.parse_assignments()?;
+        let where_clause = self.parse_where()?;
+
+        Ok(ast::UpdateStatement {
+            table_name,
+            assignments,
+            where_clause
+        })
+    }
+
+    fn parse_assignments(&mut self) -> Result<Vec<ast::Assignment>, String> {
+        let mut assignments = vec![self.parse_assignment()?];
+        while self.consume_if(&TokenKind::Comma) {
+            assignments.push(self.parse_assignment()?);
+        }
+        Ok(assignments)
+    }
+
+    fn parse_assignment(&mut self) -> Result<ast::Assignment, String> {
+        let column = self.expect_identifier()?;
+        self.expect(&TokenKind::Equals)?;
+        let value = self.parse_expr()?;
+        Ok(ast::Assignment {
+            column,
+            value
+        })
+    }
+
+    fn parse_where(&mut self) -> Result<Option<ast::Expr>, String> {
+        if self.consume_keyword_if(Keyword::Where) {
+            Ok(Some(self.parse_expr()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Precedence from lowest : OR, AND, NOT, comparison / BETWEEN / IS NULL, + -, * /
+    fn parse_expr(&mut self) -> Result<ast::Expr, String> {
+        let mut expr = self.parse_and()?;
+        while self.consume_keyword_if(Keyword::Or) {
+            let right = self.parse_and()?;
+            expr = ast::Expr::Binary(ast::BinaryOperator::Or, Box::new(expr), Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<ast::Expr, String> {
+        let mut expr = self.parse_not()?;
+        while self.consume_keyword_if(Keyword::And) {
+            let right = self.parse_not()?;
+            expr = ast::Expr::Binary(ast::BinaryOperator::And, Box::new(expr), Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<ast::Expr, String> {
+        if self.consume_keyword_if(Keyword::Not) {
+            return Ok(ast::Expr::Not(Box::new(self.parse_not()?)))
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<ast::Expr, String> {
+        let left = self.parse_additive()?;
+
+        let operator = match *self.peek().kind() {
+            TokenKind::Equals => ast::BinaryOperator::Equals,
+            TokenKind::NotEquals => ast::BinaryOperator::NotEquals,
+            TokenKind::Less => ast::BinaryOperator::Less,
+            TokenKind::LessEquals => ast::BinaryOperator::LessEquals,
+            TokenKind::Greater => ast::BinaryOperator::Greater,
+            TokenKind::GreaterEquals => ast::BinaryOperator::GreaterEquals,
+            TokenKind::Keyword(Keyword::Between) | TokenKind::Keyword(Keyword::Not) => return self.parse_between(left),
+            TokenKind::Keyword(Keyword::Is) => return self.parse_is_null(left),
+            _ => return Ok(left)
+        };
+        self.advance();
+
+        let right = self.parse_additive()?;
+        Ok(ast::Expr::Binary(operator, Box::new(left), Box::new(right)))
+    }
+
+    // expr [NOT] BETWEEN low AND high
+    fn parse_between(&mut self, expr: ast::Expr) -> Result<ast::Expr, String> {
+        let negated = self.consume_keyword_if(Keyword::Not);
+        self.expect_keyword(Keyword::Between)?;
+        let low = self.parse_additive()?;
+        self.expect_keyword(Keyword::And)?;
+        let high = self.parse_additive()?;
+
+        Ok(ast::Expr::Between {
+            expr: Box::new(expr),
+            low: Box::new(low),
+            high: Box::new(high),
+            negated
+        })
+    }
+
+    // expr IS [NOT] NULL
+    fn parse_is_null(&mut self, expr: ast::Expr) -> Result<ast::Expr, String> {
+        self.expect_keyword(Keyword::Is)?;
+        let negated = self.consume_keyword_if(Keyword::Not);
+        self.expect_keyword(Keyword::Null)?;
+        Ok(ast::Expr::IsNull {
+            expr: Box::new(expr),
+            negated
+        })
+    }
+
+    fn parse_additive(&mut self) -> Result<ast::Expr, String> {
+        let mut expr = self.parse_multiplicative()?;
+        loop {
+            let operator = match *self.peek().kind() {
+                TokenKind::Plus => ast::BinaryOperator::Plus,
+                TokenKind::Minus => ast::BinaryOperator::Minus,
+                _ => return Ok(expr)
+            };
+            self.advance();
+            let right = self.parse_multiplicative()?;
+            expr = ast::Expr::Binary(operator, Box::new(expr), Box::new(right));
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<ast::Expr, String> {
+        let mut expr = self.parse_primary()?;
+        loop {
+            let operator = match *self.peek().kind() {
+                TokenKind::Star => ast::BinaryOperator::Multiply,
+                TokenKind::Slash => ast::BinaryOperator::Divide,
+                _ => return Ok(expr)
+            };
+            self.advance();
+            let right = self.parse_primary()?;
+            expr = ast::Expr::Binary(operator, Box::new(expr), Box::new(right));
+        }
+    }
+
+    // literal | column | excluded.column | function ( [*] expr ) | ( expr )
+    fn parse_primary(&mut self) -> Result<ast::Expr, String> {
+        match self.peek().kind().clone() {
+            TokenKind::Identifier(ref name) if name.eq_ignore_ascii_case("timestamp") && matches!(self.peek_next().kind(), TokenKind::String(_)) => {
+                Ok(ast::Expr::Literal(self.parse_literal()?))
+            },
+            TokenKind::Identifier(name) => {
+                let token = self.advance();
+                if self.consume_if(&TokenKind::LeftParen) {
+                    return self.parse_aggregate(&token, &name)
+                }
+                if !self.consume_if(&TokenKind::Dot) {
+                    return Ok(ast::Expr::Column(name))
+                }
+                if !name.eq_ignore_ascii_case("excluded") {
+                    return Err(error_at(&token, &format!("unknown table '{}' : only excluded can qualify a column", name)))
+                }
+                Ok(ast::Expr::Excluded(self.expect_identifier()?))
+            },
+            TokenKind::LeftParen => {
+                self.advance();
+                let expr = self.parse_expr()?;
+                self.expect(&TokenKind::RightParen)?;
+                Ok(expr)
+            },
+            TokenKind::Integer(_) | TokenKind::Real(_) | TokenKind::String(_) | TokenKind::Blob(_) | TokenKind::Minus | TokenKind::Question
+                | TokenKind::Keyword(Keyword::True) | TokenKind::Keyword(Keyword::False) | TokenKind::Keyword(Keyword::Null) => {
+                Ok(ast::Expr::Literal(self.parse_literal()?))
+            },
+            _ => Err(self.error_at_current("expected an expression"))
+        }
+    }
+
+    // arguments of function name after '(' ; only count takes *.
+    fn parse_aggregate(&mut self, token: &Token, name: &str) -> Result<ast::Expr, String> {
+        let function = ast::AggregateFunction::from_name(name)
+            .ok_or_else(|| error_at(token, &format!("unknown function '{}'", name)))?;
+        let arg = if function == ast::AggregateFunction::Count && self.consume_if(&TokenKind::Star) {
+            None
+        } else {
+            Some(Box::new(self.parse_expr()?))
+        };
+        self.expect(&TokenKind::RightParen)?;
+        Ok(ast::Expr::Aggregate {
+            function,
+            arg
+        })
+    }
+
+    fn peek(&self) -> &Token {
+        // tokens always end with Eof which is never consumed.
+        &self.tokens[self.pos]
+    }
+
+    fn peek_next(&self) -> &Token {
+        &self.tokens[(self.pos + 1).min(self.tokens.len() - 1)]
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if *token.kind() != TokenKind::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn consume_if(&mut self, kind: &TokenKind) -> bool {
+        if self.peek().kind() == kind {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn consume_keyword_if(&mut self, keyword: Keyword) -> bool {
+        self.consume_if(&TokenKind::Keyword(keyword))
+    }
+
+    fn expect(&mut self, kind: &TokenKind) -> Result<Token, String> {
+        if self.peek().kind() == kind {
+            Ok(self.advance())
+        } else {
+            Err(self.error_at_current(&format!("expected {}", kind)))
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: Keyword) -> Result<Token, String> {
+        self.expect(&TokenKind::Keyword(keyword))
+    }
+
+    fn expect_identifier(&mut self) -> Result<String, String> {
+        match self.peek().kind().clone() {
+            TokenKind::Identifier(name) => {
+                self.advance();
+                Ok(name)
+            },
+            _ => Err(self.error_at_current("expected identifier"))
+        }
+    }
+
+    fn expect_integer(&mut self) -> Result<i64, String> {
+        match *self.peek().kind() {
+            TokenKind::Integer(value) => {
+                self.advance();
+                Ok(value)
+            },
+            _ => Err(self.error_at_current("expected integer"))
+        }
+    }
+
+    fn error_at_current(&self, msg: &str) -> String {
+        let token = self.peek();
+        error_at(token, &format!("{}, found {}", msg, token.kind()))
+    }
+}
+
+fn error_at(token: &Token, msg: &str) -> String {
+    lexer::syntax_error(token.line(), token.column(), msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqliters::ast::{Statement, Literal};
+
+    #[test]
+    fn test_parse_create_table() {
+        let statement = parse("CREATE TABLE users (id INTEGER PRIMARY KEY, username varchar (32), email Text(255) unique NOT null, bio text, avatar BLOB);")
+            .expect("create table should parse");
+        let expected = Statement::CreateTable(ast::CreateTableStatement {
+            table_name: String::from("users"),
+            columns: vec![
+                ast::ColumnDef { name: String::from("id"), column_type: schema::ColumnType::Integer, primary_key: true, autoincrement: false, unique: false, not_null: false },
+                ast::ColumnDef { name: String::from("username"), column_type: schema::ColumnType::Text(32), primary_key: false, autoincrement: false, unique: false, not_null: false },
+                ast::ColumnDef { name: String::from("email"), column_type: schema::ColumnType::Text(255), primary_key: false, autoincrement: false, unique: true, not_null: true },
+                ast::ColumnDef { name: String::from("bio"), column_type: schema::ColumnType::VarText, primary_key: false, autoincrement: false, unique: false, not_null: false },
+                ast::ColumnDef { name: String::from("avatar"), column_type: schema::ColumnType::Blob, primary_key: false, autoincrement: false, unique: false, not_null: false }
+            ],
+            primary_key: vec![]
+        });
+        assert!(statement == expected, "{:?}", statement);
+    }
+
+    #[test]
+    fn test_parse_table_primary_key() {
+        let statement = parse("create table visits (city text(16), day timestamp, count bigint, PRIMARY KEY (city, Day))")
+            .expect("create table should parse");
+        let create = match statement {
+            Statement::CreateTable(create) => create,
+            _ => panic!("should be create table statement : {:?}", statement)
+        };
+        assert!(create.columns.len() == 3 && create.primary_key == ["city", "Day"], "{:?}", create);
+        let schema = create.schema().expect("schema should be valid");
+        assert!(schema.key_columns() == [0, 1], "key columns are matched ignoring case : {:?}", schema.key_columns());
+        assert!(schema.to_string() == "(\"city\" text(16), \"day\" timestamp, \"count\" bigint, primary key (\"city\", \"day\"))", "{}", schema);
+
+        assert!(parse("create table t (a int, primary key (a), b int)").is_err(), "primary key comes after columns");
+        assert!(parse("create table t (a int, primary key ())").is_err(), "primary key needs columns");
+        let schema_of = |sql: &str| match parse(sql) {
+            Ok(Statement::CreateTable(create)) => create.schema(),
+            other => panic!("create table should parse : {:?}", other)
+        };
+        assert!(schema_of("create table t (a int primary key, b int, primary key (b))").is_err(), "only one primary key");
+        assert!(schema_of("create table t (a int, primary key (c))").is_err(), "no such column");
+        assert!(schema_of("create table t (a int, primary key (a, A))").is_err(), "key column is repeated");
+        assert!(schema_of("create table t (a text, primary key (a))").is_err(), "text of any length is not a key");
+
+        let schema = schema_of("create table t (id bigint primary key autoincrement, a int)").expect("schema should be valid");
+        assert!(schema.auto_key() == Some(0) && schema.to_string() == "(\"id\" bigint primary key autoincrement, \"a\" integer)", "{}", schema);
+        let schema = schema_of("create table t (a int, b text)").expect("schema should be valid");
+        assert!(schema.auto_key() == Some(2) && schema.columns()[2].is_hidden(), "table without primary key has a rowid : {:?}", schema);
+        assert!(schema.to_string() == "(\"a\" integer, \"b\" text)", "rowid is not in sql : {}", schema);
+        assert!(schema_of("create table t (a text(4) primary key autoincrement)").is_err(), "autoincrement key is an integer");
+        assert!(parse("create table t (a int autoincrement)").is_err(), "autoincrement comes after primary key");
+    }
+
+    #[test]
+    fn test_parse_column_types() {
+        let statement = parse("create table m (id int primary key, total BIGINT, price double, paid bool, at timestamp)")
+            .expect("create table should parse");
+        let types: Vec<schema::ColumnType> = match statement {
+            Statement::CreateTable(create) => create.columns.into_iter().map(|c| c.column_type).collect(),
+            _ => panic!("should be create table statement : {:?}", statement)
+        };
+        assert!(types == [schema::ColumnType::Integer, schema::ColumnType::BigInt, schema::ColumnType::Real,
+                          schema::ColumnType::Boolean, schema::ColumnType::Timestamp], "{:?}", types);
+        assert!(parse("create table m (id integer primary key, r real(8))").is_err(), "real does not take a size");
+
+        let statement = parse("insert into m values (1, 3000000000, -1.5e2, true, timestamp '2024-02-29 12:00:00'), (2, 0, 0.5, FALSE, 0)");
+        let expected_first = vec![Literal::Integer(1), Literal::Integer(3000000000), Literal::Real(-150.0), Literal::Boolean(true),
+                                  Literal::Timestamp(1709208000)];
+        match statement {
+            Ok(Statement::Insert(insert)) => assert!(insert.rows[0] == expected_first, "{:?}", insert.rows[0]),
+            other => panic!("insert should parse : {:?}", other)
+        }
+        assert!(parse("insert into m values (1, timestamp '2024-02-30')").is_err(), "bad timestamp");
+        assert!(parse("insert into m values (1, 1e999)").is_err(), "real out of range");
+    }
+
+    #[test]
+    fn test_parse_create_index() {
+        let statement = parse("create INDEX users_email ON users (Email)").expect("create index should parse");
+        let expected = Statement::CreateIndex(ast::CreateIndexStatement {
+            unique: false,
+            index_name: String::from("users_email"),
+            table_name: String::from("users"),
+            column: String::from("Email")
+        });
+        assert!(statement == expected, "{:?}", statement);
+
+        match parse("create unique index users_email on users (email)") {
+            Ok(Statement::CreateIndex(create)) => assert!(create.unique, "index should be unique"),
+            other => panic!("create unique index should parse : {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_parse_insert() {
+        let statement = parse("insert INTO users (id, username) values (-1, 'ashish negi, (it''s) SELECT'), (2, 'b'), (3, X'00fF')")
+            .expect("insert should parse");
+        let expected = Statement::Insert(ast::InsertStatement {
+            table_name: String::from("users"),
+            columns: Some(vec![String::from("id"), String::from("username")]),
+            rows: vec![
+                vec![Literal::Integer(-1), Literal::String(String::from("ashish negi, (it's) SELECT"))],
+                vec![Literal::Integer(2), Literal::String(String::from("b"))],
+                vec![Literal::Integer(3), Literal::Blob(vec![0, 255])]
+            ],
+            on_conflict: ast::OnConflict::Abort,
+            conflict_target: None
+        });
+        assert!(statement == expected, "{:?}", statement);
+    }
+
+    #[test]
+    fn test_parse_insert_on_conflict() {
+        use sqliters::ast::{Expr, BinaryOperator, OnConflict};
+
+        let conflict_of = |sql: &str| match parse(sql) {
+            Ok(Statement::Insert(insert)) => (insert.on_conflict, insert.conflict_target),
+            other => panic!("'{}' should parse as insert : {:?}", sql, other)
+        };
+
+        assert!(conflict_of("insert or replace into t values (1)") == (OnConflict::Replace, None));
+        assert!(conflict_of("INSERT OR IGNORE INTO t values (1)") == (OnConflict::Ignore, None));
+        assert!(conflict_of("insert into t values (1) on conflict do nothing") == (OnConflict::Ignore, None));
+
+        let (on_conflict, target) = conflict_of("insert into t values (1, 'a') on conflict (id) do update set name = excluded.name, n = n + 1");
+        let expected = OnConflict::Update(vec![
+            ast::Assignment { column: String::from("name"), value: Expr::Excluded(String::from("name")) },
+            ast::Assignment {
+                column: String::from("n"),
+                value: Expr::Binary(BinaryOperator::Plus, Box::new(Expr::Column(String::from("n"))), Box::new(Expr::Literal(Literal::Integer(1))))
+            }
+        ]);
+        assert!(on_conflict == expected, "{:?}", on_conflict);
+        assert!(target == Some(String::from("id")), "{:?}", target);
+
+        assert!(parse("insert or update into t values (1)").is_err(), "or update is not supported");
+        assert!(parse("insert or replace into t values (1) on conflict do nothing").is_err(), "only one conflict action");
+        assert!(parse("insert into t values (1) on conflict do update").is_err(), "missing set");
+        assert!(parse("insert into t values (1) on conflict do update set n = t.n").is_err(), "only excluded can qualify column");
+    }
+
+    #[test]
+    fn test_parse_select() {
+        let statement = parse("-- all users\nSelect *\n  FROM \"Users\"").expect("select should parse");
+        let expected = ast::SelectStatement {
+            projection: vec![ast::SelectItem::Wildcard],
+            table_name: String::from("Users"),
+            where_clause: None,
+            group_by: vec![],
+            order_by: vec![],
+            limit: None,
+            offset: None
+        };
+        assert!(statement == Statement::Select(expected), "{:?}", statement);
+    }
+
+    #[test]
+    fn test_parse_projection() {
+        use sqliters::ast::{Expr, BinaryOperator, SelectItem};
+
+        let statement = parse("select username, id * 2 as double_id, 'x' y, * from t").expect("select should parse");
+        let projection = match statement {
+            Statement::Select(select) => select.projection,
+            other => panic!("should be select statement : {:?}", other)
+        };
+        let expected = vec![
+            SelectItem::Expr { expr: Expr::Column(String::from("username")), alias: None },
+            SelectItem::Expr {
+                expr: Expr::Binary(BinaryOperator::Multiply, Box::new(Expr::Column(String::from("id"))), Box::new(Expr::Literal(Literal::Integer(2)))),
+                alias: Some(String::from("double_id"))
+            },
+            SelectItem::Expr { expr: Expr::Literal(Literal::String(String::from("x"))), alias: Some(String::from("y")) },
+            SelectItem::Wildcard
+        ];
+        assert!(projection == expected, "{:?}", projection);
+
+        assert!(parse("select from t").is_err(), "empty projection");
+        assert!(parse("select id as from t").is_err(), "missing alias");
+        assert!(parse("select id, from t").is_err(), "trailing comma");
+
+        // names of expressions are printed back as sql.
+        let expr = match parse("select (a + 1) * -2 >= 'it''s' and not b between 1 and 2 from t") {
+            Ok(Statement::Select(ref select)) => match select.projection[0] {
+                SelectItem::Expr { ref expr, .. } => expr.clone(),
+                ref other => panic!("should be expression : {:?}", other)
+            },
+            other => panic!("should be select statement : {:?}", other)
+        };
+        assert!(expr.to_string() == "(((a + 1) * -2) >= 'it''s') AND (NOT (b BETWEEN 1 AND 2))", "{}", expr);
+    }
+
+    #[test]
+    fn test_parse_order_by_limit() {
+        use sqliters::ast::{Expr, OrderByItem};
+
+        let select = match parse("select * from t where id > 1 order by name desc, id asc, age limit 10 offset 5") {
+            Ok(Statement::Select(select)) => select,
+            other => panic!("should be select statement : {:?}", other)
+        };
+        let item = |name: &str, descending: bool| OrderByItem { expr: Expr::Column(name.to_string()), descending };
+        assert!(select.order_by == vec![item("name", true), item("id", false), item("age", false)], "{:?}", select.order_by);
+        assert!(select.limit == Some(10) && select.offset == Some(5), "{:?} {:?}", select.limit, select.offset);
+
+        match parse("select * from t limit 3") {
+            Ok(Statement::Select(select)) => assert!(select.order_by.is_empty() && select.limit == Some(3) && select.offset.is_none(), "{:?}", select),
+            other => panic!("should be select statement : {:?}", other)
+        }
+
+        assert!(parse("select * from t order id").is_err(), "missing BY");
+        assert!(parse("select * from t order by").is_err(), "missing order by expression");
+        assert!(parse("select * from t limit -1").is_err(), "negative limit");
+        assert!(parse("select * from t offset 1").is_err(), "offset without limit");
+        assert!(parse("select * from t limit 1 order by id").is_err(), "order by after limit");
+    }
+
+    #[test]
+    fn test_parse_aggregates() {
+        use sqliters::ast::{Expr, AggregateFunction};
+
+        let select = match parse("select name, COUNT(*), max(id + 1) from t group by name, id / 2") {
+            Ok(Statement::Select(select)) => select,
+            other => panic!("should be select statement : {:?}", other)
+        };
+        let names: Vec<String> = select.projection.iter().map(|item| match *item {
+            ast::SelectItem::Expr { ref expr, .. } => expr.to_string(),
+            ast::SelectItem::Wildcard => String::from("*")
+        }).collect();
+        assert!(names == ["name", "count(*)", "max(id + 1)"], "{:?}", names);
+        assert!(select.group_by.len() == 2 && select.group_by[0] == Expr::Column(String::from("name")), "{:?}", select.group_by);
+        match select.projection[1] {
+            ast::SelectItem::Expr { expr: Expr::Aggregate { function: AggregateFunction::Count, arg: None }, .. } => {},
+            ref other => panic!("should be count(*) : {:?}", other)
+        }
+
+        assert!(parse("select count( from t").is_err(), "missing argument");
+        assert!(parse("select min(*) from t").is_err(), "only count takes *");
+        assert!(parse("select upper(name) from t").is_err(), "unknown function");
+        assert!(parse("select * from t group name").is_err(), "missing BY");
+    }
+
+    #[test]
+    fn test_parse_where() {
+        use sqliters::ast::{Expr, BinaryOperator};
+
+        let column = |name: &str| Box::new(Expr::Column(name.to_string()));
+        let integer = |v: i64| Box::new(Expr::Literal(Literal::Integer(v)));
+
+        let statement = parse("select * from t where id between 1 and 5 and not (name = 'a' or 3 < id)")
+            .expect("select should parse");
+        let name_is_a = Expr::Binary(BinaryOperator::Equals, column("name"), Box::new(Expr::Literal(Literal::String(String::from("a")))));
+        let id_above_3 = Expr::Binary(BinaryOperator::Less, integer(3), column("id"));
+        let expected = Expr::Binary(BinaryOperator::And,
+            Box::new(Expr::Between { expr: column("id"), low: integer(1), high: integer(5), negated: false }),
+            Box::new(Expr::Not(Box::new(Expr::Binary(BinaryOperator::Or, Box::new(name_is_a), Box::new(id_above_3))))));
+        match statement {
+            Statement::Select(select) => assert!(select.where_clause == Some(expected), "{:?}", select.where_clause),
+            _ => panic!("should be select statement : {:?}", statement)
+        }
+
+        let statement = parse("select * from t where name is null or age IS NOT NULL and age != NULL").expect("select should parse");
+        let expected = Expr::Binary(BinaryOperator::Or,
+            Box::new(Expr::IsNull { expr: column("name"), negated: false }),
+            Box::new(Expr::Binary(BinaryOperator::And,
+                Box::new(Expr::IsNull { expr: column("age"), negated: true }),
+                Box::new(Expr::Binary(BinaryOperator::NotEquals, column("age"), Box::new(Expr::Literal(Literal::Null)))))));
+        match statement {
+            Statement::Select(select) => assert!(select.where_clause == Some(expected), "{:?}", select.where_clause),
+            _ => panic!("should be select statement : {:?}", statement)
+        }
+
+        assert!(parse("select * from t where id not between -1 and 1").is_ok(), "not between should parse");
+        assert!(parse("select * from t where id is 1").is_err(), "is needs null");
+        assert!(parse("select * from t where id = ").is_err(), "missing right side of =");
+        assert!(parse("select * from t where id between 1").is_err(), "missing and of between");
+    }
+
+    #[test]
+    fn test_parse_delete() {
+        use sqliters::ast::{Expr, BinaryOperator};
+
+        let statement = parse("DELETE FROM t where id > 3;").expect("delete should parse");
+        let expected = Statement::Delete(ast::DeleteStatement {
+            table_name: String::from("t"),
+            where_clause: Some(Expr::Binary(BinaryOperator::Greater,
+                Box::new(Expr::Column(String::from("id"))), Box::new(Expr::Literal(Literal::Integer(3)))))
+        });
+        assert!(statement == expected, "{:?}", statement);
+
+        let statement = parse("delete from t").expect("delete without where should parse");
+        assert!(statement == Statement::Delete(ast::DeleteStatement { table_name: String::from("t"), where_clause: None }), "{:?}", statement);
+    }
+
+    #[test]
+    fn test_parse_update() {
+        use sqliters::ast::{Expr, BinaryOperator};
+
+        let column = |name: &str| Box::new(Expr::Column(name.to_string()));
+        let integer = |v: i64| Box::new(Expr::Literal(Literal::Integer(v)));
+
+        let statement = parse("UPDATE t SET id = id + 2 * 3, name = 'b' where id - 1 > 3")
+            .expect("update should parse");
+        let expected = Statement::Update(ast::UpdateStatement {
+            table_name: String::from("t"),
+            assignments: vec![
+                ast::Assignment {
+                    column: String::from("id"),
+                    value: Expr::Binary(BinaryOperator::Plus, column("id"), Box::new(Expr::Binary(BinaryOperator::Multiply, integer(2), integer(3))))
+                },
+                ast::Assignment { column: String::from("name"), value: Expr::Literal(Literal::String(String::from("b"))) }
+            ],
+            where_clause: Some(Expr::Binary(BinaryOperator::Greater,
+                Box::new(Expr::Binary(BinaryOperator::Minus, column("id"), integer(1))), integer(3)))
+        });
+        assert!(statement == expected, "{:?}", statement);
+
+        assert!(parse("update t set").is_err(), "missing assignment");
+        assert!(parse("update t set id 1").is_err(), "missing =");
+        assert!(parse("update t set id = 1,").is_err(), "trailing comma");
+    }
+
+    #[test]
+    fn test_parse_parameters() {
+        use sqliters::ast::{Expr, BinaryOperator};
+
+        let statement = parse("insert into t values (?, 'a', ?), (?, -1, null)").expect("insert should parse");
+        match statement {
+            Statement::Insert(insert) => assert!(insert.rows == vec![
+                vec![Literal::Parameter(1), Literal::String(String::from("a")), Literal::Parameter(2)],
+                vec![Literal::Parameter(3), Literal::Integer(-1), Literal::Null]
+            ], "{:?}", insert.rows),
+            _ => panic!("should be insert statement : {:?}", statement)
+        }
+
+        let statement = parse("update t set name = ? where id > ?").expect("update should parse");
+        let expected = Statement::Update(ast::UpdateStatement {
+            table_name: String::from("t"),
+            assignments: vec![ast::Assignment { column: String::from("name"), value: Expr::Literal(Literal::Parameter(1)) }],
+            where_clause: Some(Expr::Binary(BinaryOperator::Greater,
+                Box::new(Expr::Column(String::from("id"))), Box::new(Expr::Literal(Literal::Parameter(2)))))
+        });
+        assert!(statement == expected, "{:?}", statement);
+        assert!(Expr::Literal(Literal::Parameter(2)).to_string() == "?", "parameter is shown as ?");
+
+        assert!(parse("select * from t limit ?").is_err(), "limit is a number");
+        assert!(parse("insert into t values (-?)").is_err(), "parameter can not be negated");
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let cases = [
+            ("select * users", "line 1, column 10"),
+            ("select *\nfrom", "line 2, column 5"),
+            ("insert into t values (1, 'abc)", "line 1, column 26"),
+            ("insert into t values (1 2)", "line 1, column 25"),
+            ("create table t (id integer(4))", "line 1, column 20"),
+            ("create table t (id blob(4))", "line 1, column 20"),
+            ("insert into t values (x'abc')", "line 1, column 23"),
+            ("create view v", "line 1, column 8"),
+            ("create unique table t (id integer)", "line 1, column 15"),
+            ("create index i on t (a, b)", "line 1, column 23"),
+            ("select * from t t2", "line 1, column 17"),
+            ("drop table t", "line 1, column 1"),
+            ("delete t", "line 1, column 8"),
+            ("select # from t", "line 1, column 8")
+        ];
+
+        for &(sql, position) in cases.iter() {
+            match parse(sql) {
+                Ok(statement) => panic!("'{}' should not parse : {:?}", sql, statement),
+                Err(msg) => assert!(msg.contains(position), "'{}' : error '{}' should be at {}", sql, msg, position)
+            }
+        }
+    }
+}
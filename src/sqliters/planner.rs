@@ -0,0 +1,313 @@
+use std::cmp::Ordering;
+use sqliters::{ast, key, schema};
+use sqliters::ast::{Expr, BinaryOperator};
+
+// Inclusive range of encoded primary keys a query needs to look at.
+// Values of the first key column order the keys, so conditions on that column narrow the range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyRange {
+    start: Vec<u8>,
+    end: Vec<u8>
+}
+
+impl KeyRange {
+    // all keys of key_size bytes.
+    pub fn full(key_size: usize) -> Self {
+        KeyRange {
+            start: vec![0; key_size],
+            end: vec![0xff; key_size]
+        }
+    }
+
+    fn empty(key_size: usize) -> Self {
+        KeyRange {
+            start: vec![0xff; key_size],
+            end: vec![0; key_size]
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start > self.end
+    }
+
+    pub fn start(&self) -> &[u8] {
+        &self.start
+    }
+
+    pub fn end(&self) -> &[u8] {
+        &self.end
+    }
+}
+
+// Inclusive range of values of a column which an index can look up ; None is unbounded.
+// Strict comparisons on text keep their bound, so rows in range still need to be checked against where clause.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueRange {
+    low: Option<ast::Literal>,
+    high: Option<ast::Literal>
+}
+
+impl ValueRange {
+    pub fn single(value: ast::Literal) -> Self {
+        ValueRange {
+            low: Some(value.clone()),
+            high: Some(value)
+        }
+    }
+
+    pub fn low(&self) -> Option<&ast::Literal> {
+        self.low.as_ref()
+    }
+
+    pub fn high(&self) -> Option<&ast::Literal> {
+        self.high.as_ref()
+    }
+
+    fn intersect(&mut self, low: Option<ast::Literal>, high: Option<ast::Literal>) {
+        if let Some(low) = low {
+            if self.low.as_ref().is_none_or(|current| compare_literals(&low, current) == Ordering::Greater) {
+                self.low = Some(low);
+            }
+        }
+        if let Some(high) = high {
+            if self.high.as_ref().is_none_or(|current| compare_literals(&high, current) == Ordering::Less) {
+                self.high = Some(high);
+            }
+        }
+    }
+}
+
+// Narrows the key range using conditions on the first key column which are AND-ed at top level of where clause.
+// Rows in range still need to be checked against the whole where clause.
+pub fn key_range(where_clause: Option<&Expr>, schema: &schema::Schema) -> KeyRange {
+    let key_size = schema.key_size();
+    let mut range = KeyRange::full(key_size);
+    let column_pos = schema.key_columns()[0];
+    let column_type = schema.columns()[column_pos].column_type();
+    let values = match column_range(where_clause, schema, column_pos) {
+        Some(values) => values,
+        None => return range
+    };
+
+    // integer bounds are clamped to i32 ; a low bound above all integer keys or a high bound below them leaves none.
+    let beyond = |bound: Option<&ast::Literal>, outside: &dyn Fn(i64) -> bool| match (column_type, bound) {
+        (schema::ColumnType::Integer, Some(&ast::Literal::Integer(v))) => outside(v),
+        _ => false
+    };
+    if beyond(values.low(), &|v| v > i64::from(i32::MAX)) || beyond(values.high(), &|v| v < i64::from(i32::MIN)) {
+        return KeyRange::empty(key_size)
+    }
+
+    // other key columns keep their lowest bytes in start and highest bytes in end.
+    if let Some(low) = values.low().and_then(|v| key::encode_bound(column_type, v)) {
+        range.start.splice(.. low.len(), low);
+    }
+    if let Some(high) = values.high().and_then(|v| key::encode_bound(column_type, v)) {
+        range.end.splice(.. high.len(), high);
+    }
+    range
+}
+
+// Range of values of column at column_pos from conditions AND-ed at top level of where clause ;
+// None when no condition compares the column with a literal of its type.
+pub fn column_range(where_clause: Option<&Expr>, schema: &schema::Schema, column_pos: usize) -> Option<ValueRange> {
+    let mut range = None;
+    if let Some(expr) = where_clause {
+        narrow_values(&mut range, expr, schema, column_pos);
+    }
+    range
+}
+
+fn narrow_values(range: &mut Option<ValueRange>, expr: &Expr, schema: &schema::Schema, column_pos: usize) {
+    let column_type = schema.columns()[column_pos].column_type();
+    match *expr {
+        Expr::Binary(BinaryOperator::And, ref left, ref right) => {
+            narrow_values(range, left, schema, column_pos);
+            narrow_values(range, right, schema, column_pos);
+        },
+        Expr::Binary(operator, ref left, ref right) => {
+            let is_left_column = is_column(left, schema, column_pos);
+            let is_right_column = is_column(right, schema, column_pos);
+            match (is_left_column, typed_literal(right, column_type), typed_literal(left, column_type), is_right_column) {
+                (true, Some(v), _, _) => narrow_value_comparison(range, operator, v, column_type),
+                (_, _, Some(v), true) => narrow_value_comparison(range, flip(operator), v, column_type),
+                _ => {}
+            }
+        },
+        Expr::Between { ref expr, ref low, ref high, negated: false } if is_column(expr, schema, column_pos) => {
+            if let (Some(low), Some(high)) = (typed_literal(low, column_type), typed_literal(high, column_type)) {
+                intersect_values(range, Some(low.clone()), Some(high.clone()));
+            }
+        },
+        _ => {}
+    }
+}
+
+fn narrow_value_comparison(range: &mut Option<ValueRange>, operator: BinaryOperator, v: &ast::Literal, column_type: &schema::ColumnType) {
+    // integers next to a strict bound are known ; other values are kept inclusive.
+    let step = |delta: i64| match (v, column_type) {
+        (ast::Literal::Integer(i), schema::ColumnType::Integer) | (ast::Literal::Integer(i), schema::ColumnType::BigInt) =>
+            ast::Literal::Integer(i.saturating_add(delta)),
+        (other, _) => other.clone()
+    };
+    match operator {
+        BinaryOperator::Equals => intersect_values(range, Some(v.clone()), Some(v.clone())),
+        BinaryOperator::Greater => intersect_values(range, Some(step(1)), None),
+        BinaryOperator::GreaterEquals => intersect_values(range, Some(v.clone()), None),
+        BinaryOperator::Less => intersect_values(range, None, Some(step(-1))),
+        BinaryOperator::LessEquals => intersect_values(range, None, Some(v.clone())),
+        _ => {}
+    }
+}
+
+fn intersect_values(range: &mut Option<ValueRange>, low: Option<ast::Literal>, high: Option<ast::Literal>) {
+    range.get_or_insert(ValueRange { low: None, high: None }).intersect(low, high);
+}
+
+// literals of a range are all of the column type ; integers and reals are both valid for real columns.
+fn compare_literals(a: &ast::Literal, b: &ast::Literal) -> Ordering {
+    match (a, b) {
+        (ast::Literal::Integer(a), ast::Literal::Integer(b)) => a.cmp(b),
+        (ast::Literal::Integer(a), ast::Literal::Real(b)) => (*a as f64).partial_cmp(b).unwrap_or(Ordering::Equal),
+        (ast::Literal::Real(a), ast::Literal::Integer(b)) => a.partial_cmp(&(*b as f64)).unwrap_or(Ordering::Equal),
+        (ast::Literal::Real(a), ast::Literal::Real(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+        (ast::Literal::Boolean(a), ast::Literal::Boolean(b)) => a.cmp(b),
+        (ast::Literal::Timestamp(a), ast::Literal::Timestamp(b)) => a.cmp(b),
+        (ast::Literal::String(a), ast::Literal::String(b)) => a.cmp(b),
+        (ast::Literal::Blob(a), ast::Literal::Blob(b)) => a.cmp(b),
+        _ => Ordering::Equal
+    }
+}
+
+fn typed_literal<'a>(expr: &'a Expr, column_type: &schema::ColumnType) -> Option<&'a ast::Literal> {
+    match (expr, column_type) {
+        (Expr::Literal(literal @ ast::Literal::Integer(_)), column_type) if column_type.is_numeric() => Some(literal),
+        (Expr::Literal(literal @ ast::Literal::Real(_)), schema::ColumnType::Real) => Some(literal),
+        (Expr::Literal(literal @ ast::Literal::Boolean(_)), schema::ColumnType::Boolean) => Some(literal),
+        (Expr::Literal(literal @ ast::Literal::Timestamp(_)), schema::ColumnType::Timestamp) => Some(literal),
+        (Expr::Literal(literal @ ast::Literal::String(_)), column_type) if column_type.is_text() => Some(literal),
+        (Expr::Literal(literal @ ast::Literal::Blob(_)), schema::ColumnType::Blob) => Some(literal),
+        _ => None
+    }
+}
+
+fn flip(operator: BinaryOperator) -> BinaryOperator {
+    match operator {
+        BinaryOperator::Less => BinaryOperator::Greater,
+        BinaryOperator::LessEquals => BinaryOperator::GreaterEquals,
+        BinaryOperator::Greater => BinaryOperator::Less,
+        BinaryOperator::GreaterEquals => BinaryOperator::LessEquals,
+        other => other
+    }
+}
+
+fn is_column(expr: &Expr, schema: &schema::Schema, column_pos: usize) -> bool {
+    match *expr {
+        Expr::Column(ref name) => schema.column_position(name).ok() == Some(column_pos),
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqliters::{parser, row};
+
+    fn range_of(where_clause: &str, schema: &schema::Schema) -> KeyRange {
+        let sql = format!("select * from t where {}", where_clause);
+        match parser::parse(&sql).expect("select should parse") {
+            ast::Statement::Select(select) => key_range(select.where_clause.as_ref(), schema),
+            other => panic!("should be select : {:?}", other)
+        }
+    }
+
+    fn range(start: Option<i32>, end: Option<i32>) -> KeyRange {
+        let full = KeyRange::full(4);
+        KeyRange {
+            start: start.map_or(full.start, key::encode_i32),
+            end: end.map_or(full.end, key::encode_i32)
+        }
+    }
+
+    #[test]
+    fn test_key_range() {
+        let schema = schema::Schema::default_users();
+        let full = KeyRange::full(schema.key_size());
+        let cases = [
+            ("id = 5", range(Some(5), Some(5))),
+            ("ID >= 5 and id < 10", range(Some(5), Some(9))),
+            ("10 > id and 5 < id", range(Some(6), Some(9))),
+            ("id between 3 and 7 and id <= 4", range(Some(3), Some(4))),
+            ("id > 5 and username = 'a'", range(Some(6), None)),
+            ("id = 5 and id = 6", range(Some(6), Some(5))),
+            ("id > 2147483647", KeyRange::empty(4)),
+            ("id < 10000000000", full.clone()),
+            ("id = 5 or id = 6", full.clone()),
+            ("not id = 5", full.clone()),
+            ("id not between 3 and 7", full.clone()),
+            ("username = 'a'", full.clone()),
+            ("email > 5", full.clone())
+        ];
+
+        for &(where_clause, ref expected) in cases.iter() {
+            let actual = range_of(where_clause, &schema);
+            assert!(actual == *expected, "'{}' : {:?} != {:?}", where_clause, actual, expected);
+        }
+
+        assert!(range_of("id = 5 and id = 6", &schema).is_empty(), "contradicting conditions give empty range");
+        assert!(range_of("id > 2147483647", &schema).is_empty(), "no integer key is above i32::MAX");
+        assert!(range_of("id < -2147483649", &schema).is_empty(), "no integer key is below i32::MIN");
+    }
+
+    #[test]
+    fn test_composite_key_range() {
+        let columns = vec![
+            schema::Column::new("city", schema::ColumnType::Text(4)),
+            schema::Column::new("day", schema::ColumnType::Integer)
+        ];
+        let schema = schema::Schema::new(columns, vec![0, 1]).expect("schema should be valid");
+        let city = |v: &str| key::encode_value(&schema::ColumnType::Text(4), &row::Value::Text(v.to_string())).expect("city should encode");
+        let concat = |a: Vec<u8>, b: Vec<u8>| a.into_iter().chain(b).collect::<Vec<u8>>();
+
+        let range = range_of("city = 'ab' and day > 3", &schema);
+        assert!(range.start() == &concat(city("ab"), vec![0; 4])[..] && range.end() == &concat(city("ab"), vec![0xff; 4])[..],
+            "rows of a city are together whatever their day : {:?}", range);
+        let range = range_of("city between 'a' and 'b'", &schema);
+        assert!(range.start() == &concat(city("a"), vec![0; 4])[..] && range.end() == &concat(city("b"), vec![0xff; 4])[..], "{:?}", range);
+        assert!(range_of("day = 5", &schema) == KeyRange::full(schema.key_size()), "second key column gives no range");
+        assert!(range_of("city > 'b' and city < 'a'", &schema).is_empty(), "contradicting conditions give empty range");
+    }
+
+    #[test]
+    fn test_column_range() {
+        use sqliters::ast::Literal;
+
+        let schema = schema::Schema::default_users();
+        let column_range_of = |where_clause: &str, column_pos: usize| {
+            let sql = format!("select * from t where {}", where_clause);
+            match parser::parse(&sql).expect("select should parse") {
+                ast::Statement::Select(select) => column_range(select.where_clause.as_ref(), &schema, column_pos),
+                other => panic!("should be select : {:?}", other)
+            }
+        };
+        let values = |low: Option<Literal>, high: Option<Literal>| Some(ValueRange { low, high });
+        let text = |v: &str| Some(Literal::String(v.to_string()));
+
+        let cases = [
+            ("username = 'a'", 1, values(text("a"), text("a"))),
+            ("'b' > username and username >= 'a'", 1, values(text("a"), text("b"))),
+            ("username between 'a' and 'm' and username < 'c'", 1, values(text("a"), text("c"))),
+            ("id > 5 and id <= 9", 0, values(Some(Literal::Integer(6)), Some(Literal::Integer(9)))),
+            ("username = 'a' and email = 'b'", 2, values(text("b"), text("b"))),
+            ("username = 5", 1, None),
+            ("username = 'a' or username = 'b'", 1, None),
+            ("username != 'a'", 1, None),
+            ("id = 5", 1, None)
+        ];
+
+        for &(where_clause, column_pos, ref expected) in cases.iter() {
+            let actual = column_range_of(where_clause, column_pos);
+            assert!(actual == *expected, "'{}' : {:?} != {:?}", where_clause, actual, expected);
+        }
+    }
+}
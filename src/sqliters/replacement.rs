@@ -0,0 +1,285 @@
+use std::fmt;
+use std::hash::Hash;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+// Chooses which page leaves the page cache of pager when it is full.
+// Pager tells the policy about every page which enters the cache and every use of a cached page ;
+// evict is only called when the cache is full, and the page it returns is no longer cached.
+pub trait ReplacementPolicy: fmt::Debug {
+    // page_num entered the cache.
+    fn insert(&mut self, page_num: u64);
+
+    // cached page_num was used again.
+    fn access(&mut self, page_num: u64);
+
+    // page which leaves the cache ; None when no page is cached.
+    fn evict(&mut self) -> Option<u64>;
+}
+
+// Least recently used page leaves first. Keys other than page nums are kept in the same order
+// by other caches, like the statement cache.
+#[derive(Debug, Default)]
+pub struct Lru<K = u64> {
+    // cached keys with the tick of their last use.
+    used: HashMap<K, u64>,
+    // cached keys by the tick of their last use ; the first one is least recently used.
+    by_tick: BTreeMap<u64, K>,
+    // incremented on every use.
+    tick: u64
+}
+
+// Pages are on a circle with a referenced bit which is set on use. The hand goes around clearing
+// bits and the first page which was not referenced since the hand last passed it leaves.
+#[derive(Debug, Default)]
+pub struct Clock {
+    // (page_num, referenced) ; slots of evicted pages are None till inserted pages take them.
+    frames: Vec<Option<(u64, bool)>>,
+    slots: HashMap<u64, usize>,
+    free_slots: Vec<usize>,
+    hand: usize
+}
+
+// Scan resistant 2Q. A page enters a fifo queue of pages seen once and leaves it without
+// being promoted, so a scan only replaces pages of that queue. Page nums which left it are
+// remembered for a while ; a page which comes back in that time is hot and goes to an lru queue.
+#[derive(Debug, Default)]
+pub struct TwoQueue {
+    // pages seen once, oldest first.
+    recent: VecDeque<u64>,
+    // (page num, ghost seq) of pages evicted from recent, oldest first ; their pages are not cached.
+    // An entry whose seq is not the one in ghost_seqs is left behind by a page which came back.
+    ghosts: VecDeque<(u64, u64)>,
+    ghost_seqs: HashMap<u64, u64>,
+    next_ghost_seq: u64,
+    // pages which were used again after leaving recent.
+    frequent: Lru
+}
+
+impl Lru {
+    pub fn new() -> Self {
+        Lru::default()
+    }
+}
+
+impl<K: Hash + Eq + Clone> Lru<K> {
+    fn len(&self) -> usize {
+        self.used.len()
+    }
+
+    // key is used ; a key which is not kept is added as the most recently used.
+    pub fn touch(&mut self, key: K) {
+        self.tick += 1;
+        if let Some(last_tick) = self.used.insert(key.clone(), self.tick) {
+            self.by_tick.remove(&last_tick);
+        }
+        self.by_tick.insert(self.tick, key);
+    }
+
+    // removes the least recently used key ; None when no key is kept.
+    pub fn evict_least_used(&mut self) -> Option<K> {
+        let tick = *self.by_tick.keys().next()?;
+        let least_used = self.by_tick.remove(&tick)?;
+        self.used.remove(&least_used);
+        Some(least_used)
+    }
+}
+
+impl ReplacementPolicy for Lru {
+    fn insert(&mut self, page_num: u64) {
+        self.touch(page_num)
+    }
+
+    fn access(&mut self, page_num: u64) {
+        self.touch(page_num)
+    }
+
+    fn evict(&mut self) -> Option<u64> {
+        self.evict_least_used()
+    }
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Clock::default()
+    }
+}
+
+impl ReplacementPolicy for Clock {
+    fn insert(&mut self, page_num: u64) {
+        let slot = match self.free_slots.pop() {
+            Some(slot) => {
+                self.frames[slot] = Some((page_num, true));
+                slot
+            },
+            None => {
+                self.frames.push(Some((page_num, true)));
+                self.frames.len() - 1
+            }
+        };
+        self.slots.insert(page_num, slot);
+    }
+
+    fn access(&mut self, page_num: u64) {
+        if let Some(&slot) = self.slots.get(&page_num) {
+            self.frames[slot] = Some((page_num, true));
+        }
+    }
+
+    fn evict(&mut self) -> Option<u64> {
+        if self.slots.is_empty() {
+            return None
+        }
+        // every bit is cleared in one round, so a page is found within two rounds.
+        loop {
+            let slot = self.hand;
+            self.hand = (self.hand + 1) % self.frames.len();
+            match self.frames[slot] {
+                Some((page_num, true)) => self.frames[slot] = Some((page_num, false)),
+                Some((page_num, false)) => {
+                    self.frames[slot] = None;
+                    self.slots.remove(&page_num);
+                    self.free_slots.push(slot);
+                    return Some(page_num)
+                },
+                None => {}
+            }
+        }
+    }
+}
+
+impl TwoQueue {
+    pub fn new() -> Self {
+        TwoQueue::default()
+    }
+}
+
+impl ReplacementPolicy for TwoQueue {
+    fn insert(&mut self, page_num: u64) {
+        match self.ghost_seqs.remove(&page_num) {
+            Some(_) => self.frequent.insert(page_num),
+            None => self.recent.push_back(page_num)
+        }
+    }
+
+    fn access(&mut self, page_num: u64) {
+        // uses while a page is in recent are often one access of a row ; they do not make it hot.
+        if self.frequent.used.contains_key(&page_num) {
+            self.frequent.access(page_num);
+        }
+    }
+
+    fn evict(&mut self) -> Option<u64> {
+        // cache is full when evict is called ; queues are sized by the number of cached pages.
+        let cached = self.recent.len() + self.frequent.len();
+        let max_recent = (cached / 4).max(1);
+        let max_ghosts = cached;
+
+        if self.recent.len() > max_recent || self.frequent.len() == 0 {
+            let page_num = self.recent.pop_front()?;
+            self.next_ghost_seq += 1;
+            self.ghosts.push_back((page_num, self.next_ghost_seq));
+            self.ghost_seqs.insert(page_num, self.next_ghost_seq);
+            while self.ghosts.len() > max_ghosts {
+                if let Some((ghost, seq)) = self.ghosts.pop_front() {
+                    if self.ghost_seqs.get(&ghost) == Some(&seq) {
+                        self.ghost_seqs.remove(&ghost);
+                    }
+                }
+            }
+            Some(page_num)
+        } else {
+            self.frequent.evict()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    // uses page_nums in a cache of capacity pages like pager does ; returns the number of misses of each page.
+    fn run(policy: &mut dyn ReplacementPolicy, capacity: usize, page_nums: &[u64]) -> HashMap<u64, usize> {
+        let mut cached = HashSet::new();
+        let mut misses = HashMap::new();
+        for &page_num in page_nums {
+            if cached.contains(&page_num) {
+                policy.access(page_num);
+                continue
+            }
+            *misses.entry(page_num).or_insert(0) += 1;
+            if cached.len() >= capacity {
+                let evicted = policy.evict().expect("full cache has a page to evict");
+                assert!(cached.remove(&evicted), "evicted page {} should be cached : {:?}", evicted, policy);
+            }
+            cached.insert(page_num);
+            policy.insert(page_num);
+        }
+        misses
+    }
+
+    // hot pages 1 and 2 are looked up while a scan reads 7 pages.
+    fn lookups_and_scan() -> Vec<u64> {
+        let mut page_nums = vec![];
+        for round in 0 .. 10 {
+            page_nums.extend_from_slice(&[1, 2, 1, 2]);
+            page_nums.extend(100 + round * 7 .. 100 + (round + 1) * 7);
+        }
+        page_nums
+    }
+
+    #[test]
+    fn test_lru() {
+        let mut lru = Lru::new();
+        let misses = run(&mut lru, 3, &[1, 2, 3, 1, 4, 1, 2]);
+        // 2 is least recently used when 4 comes ; 3 when 2 comes back.
+        assert!(misses[&1] == 1 && misses[&2] == 2 && misses[&3] == 1, "{:?}", misses);
+        assert!(lru.evict() == Some(4) && lru.evict() == Some(1) && lru.evict() == Some(2) && lru.evict().is_none());
+
+        let misses = run(&mut Lru::new(), 8, &lookups_and_scan());
+        assert!(misses[&1] == 10, "scans flush hot pages out of lru : {:?}", misses);
+
+        // a loop over one page more than fits misses every page.
+        let mut lru = Lru::new();
+        let page_nums: Vec<u64> = (0 .. 1000).map(|n| n % 101).collect();
+        let misses = run(&mut lru, 100, &page_nums);
+        assert!(misses.values().sum::<usize>() == 1000, "{:?}", misses);
+        assert!(lru.used.len() == 100 && lru.by_tick.len() == 100, "{:?}", lru);
+    }
+
+    #[test]
+    fn test_clock() {
+        let mut clock = Clock::new();
+        run(&mut clock, 3, &[1, 2, 3]);
+        // all pages are referenced ; the hand clears them and comes back to 1.
+        assert!(clock.evict() == Some(1));
+        clock.insert(4);
+        clock.access(2);
+        assert!(clock.evict() == Some(3), "2 was referenced after the hand passed : {:?}", clock);
+        clock.insert(5);
+        assert!(clock.evict() == Some(2) && clock.evict() == Some(4) && clock.evict() == Some(5) && clock.evict().is_none());
+
+        let misses = run(&mut Clock::new(), 3, &[1, 2, 3, 1, 4, 5, 1, 6, 1]);
+        assert!(misses[&1] == 2, "{:?}", misses);
+    }
+
+    #[test]
+    fn test_two_queue() {
+        let mut two_queue = TwoQueue::new();
+        let misses = run(&mut two_queue, 8, &lookups_and_scan());
+        // hot pages are missed the first time and once more after they leave recent ; scans do not evict them after that.
+        assert!(misses[&1] == 2 && misses[&2] == 2, "{:?}", misses);
+        assert!(misses.values().filter(|&&m| m == 1).count() == 70, "scanned pages are read once : {:?}", misses);
+        assert!(two_queue.frequent.used.len() == 2, "{:?}", two_queue);
+
+        // a page which comes back from ghosts leaves an old entry which is not a ghost.
+        let mut two_queue = TwoQueue::new();
+        run(&mut two_queue, 2, &[1, 2, 3, 1]);
+        assert!(two_queue.frequent.used.contains_key(&1) && !two_queue.ghost_seqs.contains_key(&1), "{:?}", two_queue);
+        assert!(two_queue.ghosts.iter().any(|&(g, _)| g == 1), "{:?}", two_queue);
+
+        // without hot pages it works like a fifo.
+        let misses = run(&mut TwoQueue::new(), 2, &[1, 2, 1, 3, 1]);
+        assert!(misses[&1] == 2 && misses[&3] == 1, "{:?}", misses);
+    }
+}
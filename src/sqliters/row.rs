@@ -0,0 +1,493 @@
+use std::fmt;
+use std::cmp;
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use sqliters::{consts, schema, ast, pager, expr, key, timestamp, error};
+use sqliters::schema::ColumnType;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i32),
+    BigInt(i64),
+    Real(f64), // never NaN or infinite
+    Boolean(bool),
+    Text(String),
+    Blob(Vec<u8>),
+    Timestamp(i64) // seconds since unix epoch
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    values: Vec<Value>
+}
+
+// Conversion of a value to a rust type when a row is read by a program.
+// Numbers only widen : a bigint is not read as i32. Null is only read as None of an Option.
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Result<Self, String>;
+}
+
+impl Value {
+    // converts a literal from sql to a value of given column type ; a timestamp column also takes a string.
+    pub fn from_literal(column: &schema::Column, literal: &ast::Literal) -> Result<Self, error::Error> {
+        let value = match (column.column_type(), literal) {
+            (ColumnType::Timestamp, ast::Literal::String(v)) => Value::Timestamp(timestamp::parse(v).map_err(error::Error::TypeMismatch)?),
+            (ColumnType::Text(size), ast::Literal::String(v)) if v.len() > *size => {
+                return Err(error::Error::TypeMismatch(format!("Value '{}' of column '{}' can be maximum of {} bytes", v, column.name(), size)))
+            },
+            (_, literal) => expr::literal_value(literal)?
+        };
+        value.cast(column)
+    }
+
+    // value as the type of column : integers widen to bigint and real, and a bigint in range of integer narrows.
+    // null stays null ; it is checked against not null columns when a row is written.
+    pub fn cast(self, column: &schema::Column) -> Result<Self, error::Error> {
+        match (column.column_type(), self) {
+            (_, Value::Null) => Ok(Value::Null),
+            (ColumnType::Integer, Value::BigInt(v)) => i32::try_from(v).map(Value::Integer)
+                .map_err(|_| error::Error::TypeMismatch(format!("Value {} of column '{}' is out of range of integer", v, column.name()))),
+            (ColumnType::BigInt, Value::Integer(v)) => Ok(Value::BigInt(i64::from(v))),
+            (ColumnType::Real, Value::Integer(v)) => Ok(Value::Real(f64::from(v))),
+            (ColumnType::Real, Value::BigInt(v)) => Ok(Value::Real(v as f64)),
+            (column_type, value) => {
+                if !value.has_type(column_type) {
+                    return Err(error::Error::TypeMismatch(format!("Value {:?} does not match type {} of column '{}'", value, column_type, column.name())))
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    // true when value can be stored in a column of column_type ; size of text is not checked.
+    fn has_type(&self, column_type: &ColumnType) -> bool {
+        matches!((column_type, self),
+            (ColumnType::Integer, Value::Integer(_)) | (ColumnType::BigInt, Value::BigInt(_)) | (ColumnType::Real, Value::Real(_))
+                | (ColumnType::Boolean, Value::Boolean(_)) | (ColumnType::Timestamp, Value::Timestamp(_))
+                | (ColumnType::Text(_), Value::Text(_)) | (ColumnType::VarText, Value::Text(_)) | (ColumnType::Blob, Value::Blob(_)))
+    }
+}
+
+// reals are never NaN, so every value equals itself.
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match *self {
+            Value::Null => {},
+            Value::Integer(v) => v.hash(state),
+            Value::BigInt(v) | Value::Timestamp(v) => v.hash(state),
+            // -0.0 equals 0.0 ; adding 0.0 turns it into 0.0.
+            Value::Real(v) => (v + 0.0).to_bits().hash(state),
+            Value::Boolean(v) => v.hash(state),
+            Value::Text(ref v) => v.hash(state),
+            Value::Blob(ref v) => v.hash(state)
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Null => fmt.write_str("NULL"),
+            Value::Integer(v) => write!(fmt, "{}", v),
+            Value::BigInt(v) => write!(fmt, "{}", v),
+            Value::Real(v) => write!(fmt, "{:?}", v),
+            Value::Boolean(v) => fmt.write_str(if v { "true" } else { "false" }),
+            Value::Text(ref v) => fmt.write_str(v),
+            Value::Blob(ref v) => write!(fmt, "{}", ast::Literal::Blob(v.clone())),
+            Value::Timestamp(v) => fmt.write_str(&timestamp::format(v))
+        }
+    }
+}
+
+impl Row {
+    pub fn new(values: Vec<Value>) -> Self {
+        Row {
+            values
+        }
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    pub fn get(&self, column_pos: usize) -> Option<&Value> {
+        self.values.get(column_pos)
+    }
+
+    // value of column at column_pos as type T.
+    pub fn get_as<T: FromValue>(&self, column_pos: usize) -> Result<T, String> {
+        let value = self.values.get(column_pos)
+            .ok_or_else(|| format!("Column {} is not present in row of {} columns", column_pos, self.values.len()))?;
+        T::from_value(value)
+    }
+
+    // encoded values of primary key columns ; rows of a table are kept in order of their keys.
+    pub fn key(&self, schema: &schema::Schema) -> Result<Vec<u8>, String> {
+        let mut key = vec![];
+        for &pos in schema.key_columns() {
+            let value = self.values.get(pos).ok_or_else(|| format!("Row {} has no value of primary key column {}", self, pos))?;
+            key.extend(key::encode_value(schema.columns()[pos].column_type(), value)?);
+        }
+        Ok(key)
+    }
+
+    // values of primary key columns in key order.
+    pub fn key_values(&self, schema: &schema::Schema) -> Vec<Value> {
+        schema.key_columns().iter().map(|&pos| self.values[pos].clone()).collect()
+    }
+}
+
+impl fmt::Display for Row {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("(")?;
+        for (pos, value) in self.values.iter().enumerate() {
+            if pos != 0 {
+                fmt.write_str(", ")?;
+            }
+            write!(fmt, "{}", value)?;
+        }
+        fmt.write_str(")")
+    }
+}
+
+fn type_error<T>(value: &Value, type_name: &str) -> Result<T, String> {
+    Err(format!("Value {:?} can not be read as {}", value, type_name))
+}
+
+impl FromValue for i32 {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match *value {
+            Value::Integer(v) => Ok(v),
+            _ => type_error(value, "i32")
+        }
+    }
+}
+
+// timestamps are read as seconds since unix epoch.
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match *value {
+            Value::Integer(v) => Ok(i64::from(v)),
+            Value::BigInt(v) | Value::Timestamp(v) => Ok(v),
+            _ => type_error(value, "i64")
+        }
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match *value {
+            Value::Integer(v) => Ok(f64::from(v)),
+            Value::BigInt(v) => Ok(v as f64),
+            Value::Real(v) => Ok(v),
+            _ => type_error(value, "f64")
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match *value {
+            Value::Boolean(v) => Ok(v),
+            _ => type_error(value, "bool")
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match *value {
+            Value::Text(ref v) => Ok(v.clone()),
+            _ => type_error(value, "String")
+        }
+    }
+}
+
+impl FromValue for Vec<u8> {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match *value {
+            Value::Blob(ref v) => Ok(v.clone()),
+            _ => type_error(value, "Vec<u8>")
+        }
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        Ok(value.clone())
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match *value {
+            Value::Null => Ok(None),
+            _ => T::from_value(value).map(Some)
+        }
+    }
+}
+
+// Row layout : null bitmap : columns are written back to back in schema order.
+// Bit i % 8 of byte i / 8 of the bitmap is set when column i is null ; null columns take no other bytes.
+// integer : 4 bytes ; bigint, real and timestamp : 8 bytes ; boolean : 1 byte ; text(n) : 2 bytes length : upto n bytes ;
+// text and blob : 4 bytes length : first page num of overflow chain : value, or its prefix of VARLEN_PREFIX_SIZE bytes.
+// Values are kept whole while the row fits in max_size bytes ; beyond that the longest values spill, and bytes
+// after their prefix are written to a new overflow chain, so a serialized row owns its chains.
+pub fn serialize_row(pager: &mut pager::Pager, schema: &schema::Schema, row: &Row, max_size: usize) -> Result<Vec<u8>, error::Error> {
+    validate_row(schema, row)?;
+    let spilled = spilled_values(schema, row, max_size);
+
+    let mut serialized = vec![0u8; schema.null_bitmap_size()];
+    serialized.reserve(schema.row_size());
+    let mut chains = vec![];
+    for (pos, (column, value)) in schema.columns().iter().zip(row.values.iter()).enumerate() {
+        let bytes = match (column.column_type(), value) {
+            (_, Value::Null) => {
+                serialized[pos / 8] |= 1 << (pos % 8);
+                continue;
+            },
+            (_, Value::Integer(v)) => {
+                serialized.extend_from_slice(&v.to_be_bytes());
+                continue;
+            },
+            (_, Value::BigInt(v)) | (_, Value::Timestamp(v)) => {
+                serialized.extend_from_slice(&v.to_be_bytes());
+                continue;
+            },
+            (_, Value::Real(v)) => {
+                serialized.extend_from_slice(&v.to_bits().to_be_bytes());
+                continue;
+            },
+            (_, Value::Boolean(v)) => {
+                serialized.push(*v as u8);
+                continue;
+            },
+            (ColumnType::Text(_), Value::Text(v)) => {
+                serialized.extend_from_slice(&(v.len() as u16).to_be_bytes());
+                serialized.extend_from_slice(v.as_bytes());
+                continue;
+            },
+            (_, Value::Text(v)) => v.as_bytes(),
+            (_, Value::Blob(v)) => &v[..]
+        };
+
+        let prefix_len = if spilled[pos] { consts::VARLEN_PREFIX_SIZE } else { bytes.len() };
+        let overflow_page_num = if spilled[pos] {
+            match pager.write_overflow(&bytes[prefix_len ..]) {
+                Ok(page_num) => page_num,
+                Err(e) => {
+                    for page_num in chains {
+                        pager.free_overflow(page_num)?;
+                    }
+                    return Err(e)
+                }
+            }
+        } else {
+            0
+        };
+        chains.push(overflow_page_num);
+
+        serialized.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        serialized.extend_from_slice(&overflow_page_num.to_be_bytes());
+        serialized.extend_from_slice(&bytes[.. prefix_len]);
+    }
+
+    Ok(serialized)
+}
+
+// true for values of row which spill to overflow pages, so that the serialized row is atmost max_size bytes.
+// max_size is atleast the row size of schema, in which every long value spills.
+fn spilled_values(schema: &schema::Schema, row: &Row, max_size: usize) -> Vec<bool> {
+    let mut spilled = vec![false; row.values.len()];
+    let mut long_values = vec![];
+    let mut size = schema.null_bitmap_size();
+    for (pos, (column, value)) in schema.columns().iter().zip(row.values.iter()).enumerate() {
+        let len = match (column.column_type(), value) {
+            (_, Value::Null) => continue,
+            (ColumnType::Text(_), Value::Text(v)) => {
+                size += consts::TEXT_LENGTH_SIZE + v.len();
+                continue
+            },
+            (_, Value::Text(v)) => v.len(),
+            (_, Value::Blob(v)) => v.len(),
+            (column_type, _) => {
+                size += column_type.size();
+                continue
+            }
+        };
+        size += consts::VARLEN_HEADER_SIZE + len;
+        if len > consts::VARLEN_PREFIX_SIZE {
+            long_values.push((len, pos));
+        }
+    }
+
+    long_values.sort_by(|a, b| b.cmp(a));
+    for (len, pos) in long_values {
+        if size <= max_size {
+            break
+        }
+        spilled[pos] = true;
+        size -= len - consts::VARLEN_PREFIX_SIZE;
+    }
+    spilled
+}
+
+// checks that row can be serialized in schema without writing anything.
+pub fn validate_row(schema: &schema::Schema, row: &Row) -> Result<(), error::Error> {
+    let columns = schema.columns();
+    if row.values.len() != columns.len() {
+        return Err(error::Error::Other(format!("Row has {} values but schema has {} columns", row.values.len(), columns.len())))
+    }
+
+    for (column, value) in columns.iter().zip(row.values.iter()) {
+        if *value == Value::Null {
+            if !column.is_nullable() {
+                return Err(error::Error::Other(format!("NOT NULL constraint failed : column '{}' can not be NULL", column.name())))
+            }
+            continue;
+        }
+        if !value.has_type(column.column_type()) {
+            return Err(error::Error::TypeMismatch(format!("Value {:?} does not match type {} of column '{}'", value, column.column_type(), column.name())))
+        }
+        let len = match (column.column_type(), value) {
+            (ColumnType::Text(size), Value::Text(v)) => {
+                if v.len() > *size {
+                    return Err(error::Error::TypeMismatch(format!("Value '{}' of column '{}' can be maximum of {} bytes", v, column.name(), size)))
+                }
+                continue;
+            },
+            (_, Value::Text(v)) => v.len(),
+            (_, Value::Blob(v)) => v.len(),
+            _ => continue
+        };
+        if len > u32::MAX as usize {
+            return Err(error::Error::Other(format!("Value of column '{}' of {} bytes is too long", column.name(), len)))
+        }
+    }
+    Ok(())
+}
+
+pub fn deserialize_row(pager: &mut pager::Pager, schema: &schema::Schema, serialized: &[u8]) -> Result<Row, error::Error> {
+    let mut values = Vec::with_capacity(schema.columns().len());
+    let bitmap = null_bitmap(schema, serialized)?;
+    let mut offset = bitmap.len();
+    for (pos, column) in schema.columns().iter().enumerate() {
+        if is_null(bitmap, pos) {
+            values.push(Value::Null);
+            continue;
+        }
+        let size = value_size(column, &serialized[offset ..])?;
+        let bytes = &serialized[offset .. offset + size];
+        let text = |bytes: Vec<u8>| String::from_utf8(bytes)
+            .map_err(|e| error::Error::Corrupt(format!("Unable to read column '{}' as utf8 : error {}", column.name(), e)));
+        match *column.column_type() {
+            ColumnType::Integer => {
+                let mut int_bytes: [u8; consts::INTEGER_SIZE] = Default::default();
+                int_bytes.copy_from_slice(bytes);
+                values.push(Value::Integer(i32::from_be_bytes(int_bytes)));
+            },
+            ColumnType::BigInt => values.push(Value::BigInt(i64::from_be_bytes(eight_bytes(bytes)))),
+            ColumnType::Real => values.push(Value::Real(f64::from_bits(u64::from_be_bytes(eight_bytes(bytes))))),
+            ColumnType::Boolean => values.push(Value::Boolean(bytes[0] != 0)),
+            ColumnType::Timestamp => values.push(Value::Timestamp(i64::from_be_bytes(eight_bytes(bytes)))),
+            ColumnType::Text(_) => values.push(Value::Text(text(bytes[consts::TEXT_LENGTH_SIZE ..].to_vec())?)),
+            ColumnType::VarText => values.push(Value::Text(text(read_varlen(pager, bytes)?)?)),
+            ColumnType::Blob => values.push(Value::Blob(read_varlen(pager, bytes)?)),
+            ColumnType::Null => values.push(Value::Null)
+        }
+        offset += size;
+    }
+
+    if offset != serialized.len() {
+        return Err(error::Error::Corrupt(format!("serialized size {} is not {}", serialized.len(), offset)))
+    }
+    Ok(Row::new(values))
+}
+
+fn null_bitmap<'a>(schema: &schema::Schema, serialized: &'a [u8]) -> Result<&'a [u8], error::Error> {
+    serialized.get(.. schema.null_bitmap_size())
+        .ok_or_else(|| error::Error::Corrupt(format!("Serialized row of {} bytes is shorter than its null bitmap", serialized.len())))
+}
+
+fn is_null(bitmap: &[u8], pos: usize) -> bool {
+    bitmap[pos / 8] & (1 << (pos % 8)) != 0
+}
+
+fn eight_bytes(bytes: &[u8]) -> [u8; 8] {
+    let mut eight: [u8; 8] = Default::default();
+    eight.copy_from_slice(bytes);
+    eight
+}
+
+// frees overflow chains of a serialized row which is removed or overwritten.
+pub fn free_overflow(pager: &mut pager::Pager, schema: &schema::Schema, serialized: &[u8]) -> Result<(), error::Error> {
+    let bitmap = null_bitmap(schema, serialized)?;
+    let mut offset = bitmap.len();
+    for (pos, column) in schema.columns().iter().enumerate() {
+        if is_null(bitmap, pos) {
+            continue;
+        }
+        let size = value_size(column, &serialized[offset ..])?;
+        if let ColumnType::VarText | ColumnType::Blob = *column.column_type() {
+            let (_, overflow_page_num, _) = varlen_parts(&serialized[offset .. offset + size]);
+            pager.free_overflow(overflow_page_num)?;
+        }
+        offset += size;
+    }
+    Ok(())
+}
+
+// bytes taken by the value of column at the start of serialized.
+fn value_size(column: &schema::Column, serialized: &[u8]) -> Result<usize, error::Error> {
+    let size = match *column.column_type() {
+        ColumnType::Text(max_size) if serialized.len() >= consts::TEXT_LENGTH_SIZE => {
+            let mut len_bytes: [u8; consts::TEXT_LENGTH_SIZE] = Default::default();
+            len_bytes.copy_from_slice(&serialized[.. consts::TEXT_LENGTH_SIZE]);
+            let len = u16::from_be_bytes(len_bytes) as usize;
+            if len > max_size {
+                return Err(error::Error::Corrupt(format!("Value of column '{}' has {} bytes : more than {}", column.name(), len, max_size)))
+            }
+            consts::TEXT_LENGTH_SIZE + len
+        },
+        ColumnType::VarText | ColumnType::Blob if serialized.len() >= consts::VARLEN_HEADER_SIZE => {
+            let (len, overflow_page_num, _) = varlen_parts(serialized);
+            consts::VARLEN_HEADER_SIZE + local_len(len, overflow_page_num)
+        },
+        ref column_type => column_type.size()
+    };
+    if size > serialized.len() {
+        return Err(error::Error::Corrupt(format!("Serialized row ends inside value of column '{}'", column.name())))
+    }
+    Ok(size)
+}
+
+fn read_varlen(pager: &mut pager::Pager, bytes: &[u8]) -> Result<Vec<u8>, error::Error> {
+    let (len, overflow_page_num, local) = varlen_parts(bytes);
+    let mut value = local.to_vec();
+    if overflow_page_num != 0 {
+        value.extend(pager.read_overflow(overflow_page_num, len - local.len())?);
+    }
+    Ok(value)
+}
+
+// bytes of a value of len kept in its cell ; a value with an overflow chain keeps its prefix.
+fn local_len(len: usize, overflow_page_num: u64) -> usize {
+    if overflow_page_num == 0 { len } else { cmp::min(len, consts::VARLEN_PREFIX_SIZE) }
+}
+
+// length : first overflow page num : local bytes ; bytes should have atleast the header.
+fn varlen_parts(bytes: &[u8]) -> (usize, u64, &[u8]) {
+    let mut len_bytes: [u8; consts::VARLEN_LENGTH_SIZE] = Default::default();
+    len_bytes.copy_from_slice(&bytes[.. consts::VARLEN_LENGTH_SIZE]);
+    let mut page_num_bytes: [u8; consts::OVERFLOW_PAGE_NUM_SIZE] = Default::default();
+    page_num_bytes.copy_from_slice(&bytes[consts::VARLEN_LENGTH_SIZE .. consts::VARLEN_HEADER_SIZE]);
+    let len = u32::from_be_bytes(len_bytes) as usize;
+    let overflow_page_num = u64::from_be_bytes(page_num_bytes);
+    let local_end = cmp::min(bytes.len(), consts::VARLEN_HEADER_SIZE + local_len(len, overflow_page_num));
+    (len, overflow_page_num, &bytes[consts::VARLEN_HEADER_SIZE .. local_end])
+}
@@ -0,0 +1,289 @@
+use std::fmt;
+use sqliters::{consts, key, page, error};
+
+// Name of the hidden key column of a table without primary key. Like sqlite rowid, it is
+// given the next id when a row is inserted and can be selected or set by its name.
+pub const ROWID_COLUMN_NAME: &str = "rowid";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnType {
+    Integer,
+    BigInt,
+    Real,
+    Boolean,
+    Timestamp,
+    Text(usize), // max bytes
+    // text and blob of any length ; long values of rows which do not fit in a leaf cell spill to overflow pages.
+    VarText,
+    Blob,
+    // type of the NULL literal ; no column is declared with it.
+    Null
+}
+
+#[derive(Debug, Clone)]
+pub struct Column {
+    name: String,
+    column_type: ColumnType,
+    not_null: bool,
+    // hidden columns are left out of * and of inserts without column names.
+    hidden: bool,
+    // key which is given an id larger than any id before when a row has none.
+    autoincrement: bool
+}
+
+// Describes the layout of a row : column names, their types and order.
+// Rows are serialized column by column in the same order as declared ; null values are
+// only marked in a bitmap before the columns. Primary key columns are never null.
+#[derive(Debug, Clone)]
+pub struct Schema {
+    columns: Vec<Column>,
+    key_columns: Vec<usize>
+}
+
+impl ColumnType {
+    // type_name is matched ignoring case ; varchar and char need a size : varchar(32)
+    // text without a size has no limit on length ; double and float are other names of real.
+    pub fn new(type_name: &str, size: Option<usize>) -> Result<Self, error::Error> {
+        match (type_name.to_lowercase().as_str(), size) {
+            ("int", None) | ("integer", None) => Ok(ColumnType::Integer),
+            ("bigint", None) => Ok(ColumnType::BigInt),
+            ("real", None) | ("double", None) | ("float", None) => Ok(ColumnType::Real),
+            ("boolean", None) | ("bool", None) => Ok(ColumnType::Boolean),
+            ("timestamp", None) => Ok(ColumnType::Timestamp),
+            ("text", None) => Ok(ColumnType::VarText),
+            ("blob", None) => Ok(ColumnType::Blob),
+            ("text", Some(size)) | ("varchar", Some(size)) | ("char", Some(size)) if size > 0 => Ok(ColumnType::Text(size)),
+            ("text", _) | ("varchar", _) | ("char", _) => Err(error::Error::InvalidSchema(format!("Column type '{}' needs a positive size", type_name))),
+            (_, Some(_)) if ColumnType::new(type_name, None).is_ok() => Err(error::Error::InvalidSchema(format!("Column type '{}' does not take a size", type_name))),
+            _ => Err(error::Error::InvalidSchema(format!("Unknown column type '{}'", type_name)))
+        }
+    }
+
+    // maximum number of bytes this type takes in a serialized row ; text and blob take less for short values
+    // and this much when they spill to overflow pages.
+    pub fn size(&self) -> usize {
+        match *self {
+            ColumnType::Integer => consts::INTEGER_SIZE,
+            ColumnType::BigInt => consts::BIGINT_SIZE,
+            ColumnType::Real => consts::REAL_SIZE,
+            ColumnType::Boolean => consts::BOOLEAN_SIZE,
+            ColumnType::Timestamp => consts::TIMESTAMP_SIZE,
+            ColumnType::Text(size) => consts::TEXT_LENGTH_SIZE + size,
+            ColumnType::VarText | ColumnType::Blob => consts::VARLEN_SIZE,
+            ColumnType::Null => 0
+        }
+    }
+
+    pub fn is_text(&self) -> bool {
+        matches!(*self, ColumnType::Text(_) | ColumnType::VarText)
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(*self, ColumnType::Integer | ColumnType::BigInt | ColumnType::Real)
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ColumnType::Integer => write!(fmt, "integer"),
+            ColumnType::BigInt => write!(fmt, "bigint"),
+            ColumnType::Real => write!(fmt, "real"),
+            ColumnType::Boolean => write!(fmt, "boolean"),
+            ColumnType::Timestamp => write!(fmt, "timestamp"),
+            ColumnType::Text(size) => write!(fmt, "text({})", size),
+            ColumnType::VarText => write!(fmt, "text"),
+            ColumnType::Blob => write!(fmt, "blob"),
+            ColumnType::Null => write!(fmt, "null")
+        }
+    }
+}
+
+impl Column {
+    pub fn new(name: &str, column_type: ColumnType) -> Self {
+        Column {
+            name: name.to_string(),
+            column_type,
+            not_null: false,
+            hidden: false,
+            autoincrement: false
+        }
+    }
+
+    pub fn not_null(self) -> Self {
+        Column {
+            not_null: true,
+            ..self
+        }
+    }
+
+    pub fn hidden(self) -> Self {
+        Column {
+            hidden: true,
+            ..self
+        }
+    }
+
+    pub fn autoincrement(self) -> Self {
+        Column {
+            autoincrement: true,
+            ..self
+        }
+    }
+
+    pub fn is_nullable(&self) -> bool {
+        !self.not_null
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
+    pub fn is_autoincrement(&self) -> bool {
+        self.autoincrement
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn column_type(&self) -> &ColumnType {
+        &self.column_type
+    }
+}
+
+impl Schema {
+    // key_columns are positions of the primary key columns in key order ; their encoded values are the B+tree key.
+    pub fn new(mut columns: Vec<Column>, key_columns: Vec<usize>) -> Result<Self, error::Error> {
+        if columns.is_empty() {
+            return Err(error::Error::InvalidSchema(String::from("Table should have atleast one column")))
+        }
+
+        for (pos, column) in columns.iter().enumerate() {
+            if columns[..pos].iter().any(|c| c.name == column.name) {
+                return Err(error::Error::InvalidSchema(format!("Duplicate column name '{}'", column.name)))
+            }
+        }
+
+        if key_columns.is_empty() {
+            return Err(error::Error::InvalidSchema(String::from("Primary key should have atleast one column")))
+        }
+        for (n, &key_column) in key_columns.iter().enumerate() {
+            match columns.get(key_column) {
+                Some(column) if key_columns[..n].contains(&key_column) => return Err(error::Error::InvalidSchema(format!("Primary key has column '{}' more than once", column.name))),
+                Some(column) if key::size(&column.column_type).is_none() =>
+                    return Err(error::Error::InvalidSchema(format!("Primary key column '{}' can not be of type {} : use text(n) for text keys", column.name, column.column_type))),
+                Some(_) => {},
+                None => return Err(error::Error::InvalidSchema(format!("Primary key column {} is not present in {} columns", key_column, columns.len())))
+            }
+            columns[key_column].not_null = true;
+        }
+
+        if let Some(column) = columns.iter().find(|c| c.autoincrement) {
+            let is_key = key_columns.len() == 1 && columns[key_columns[0]].name == column.name;
+            if !is_key || !matches!(column.column_type, ColumnType::Integer | ColumnType::BigInt) {
+                return Err(error::Error::InvalidSchema(format!("AUTOINCREMENT is only allowed on an integer primary key : column '{}'", column.name)))
+            }
+        }
+
+        Ok(Schema {
+            columns,
+            key_columns
+        })
+    }
+
+    // users table used in tests : id integer, username text(32), email text(32)
+    #[cfg(test)]
+    pub fn default_users() -> Self {
+        let columns = vec![
+            Column::new("id", ColumnType::Integer),
+            Column::new("username", ColumnType::Text(32)),
+            Column::new("email", ColumnType::Text(32))
+        ];
+        Schema::new(columns, vec![0]).expect("default schema should be valid")
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    // column names are matched ignoring case.
+    pub fn column_position(&self, name: &str) -> Result<usize, error::Error> {
+        self.columns.iter()
+            .position(|c| c.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| error::Error::NoSuchColumn(name.to_string()))
+    }
+
+    pub fn key_columns(&self) -> &[usize] {
+        &self.key_columns
+    }
+
+    // key column which is given an id when a row has none : rowid of a table without primary key or an AUTOINCREMENT key.
+    pub fn auto_key(&self) -> Option<usize> {
+        match self.key_columns[..] {
+            [pos] if self.columns[pos].hidden || self.columns[pos].autoincrement => Some(pos),
+            _ => None
+        }
+    }
+
+    // size of encoded primary key ; every key of the table has this size.
+    pub fn key_size(&self) -> usize {
+        self.key_columns.iter()
+            .map(|&pos| key::size(&self.columns[pos].column_type).expect("key columns are checked in new"))
+            .sum()
+    }
+
+    // bytes of null bitmap at the start of a serialized row : a bit per column.
+    pub fn null_bitmap_size(&self) -> usize {
+        self.columns.len().div_ceil(8)
+    }
+
+    // size of the largest serialized row.
+    pub fn row_size(&self) -> usize {
+        self.null_bitmap_size() + self.columns.iter().map(|c| c.column_type.size()).sum::<usize>()
+    }
+
+    // size of the largest leaf cell : KEY : ROW
+    pub fn cell_size(&self) -> usize {
+        self.key_size() + self.row_size()
+    }
+
+    // size of the largest leaf cell in pages of page_size ; rows with text or blob values are kept whole
+    // in cells upto the largest local cell, so that only big values spill to overflow pages.
+    pub fn leaf_cell_size(&self, page_size: usize) -> usize {
+        if self.columns.iter().any(|c| matches!(c.column_type, ColumnType::VarText | ColumnType::Blob)) {
+            self.cell_size().max(page::max_local_cell_size(page_size))
+        } else {
+            self.cell_size()
+        }
+    }
+}
+
+impl fmt::Display for Schema {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        // a hidden rowid comes back when the table is created again without primary key.
+        fmt.write_str("(")?;
+        for (n, (pos, column)) in self.columns.iter().enumerate().filter(|(_, c)| !c.hidden).enumerate() {
+            if n != 0 {
+                fmt.write_str(", ")?;
+            }
+            write!(fmt, "{} {}", quote_identifier(&column.name), column.column_type)?;
+            if self.key_columns == [pos] {
+                fmt.write_str(if column.autoincrement { " primary key autoincrement" } else { " primary key" })?;
+            } else if column.not_null && !self.key_columns.contains(&pos) {
+                fmt.write_str(" not null")?;
+            }
+        }
+        // a key of many columns is a table constraint.
+        if self.key_columns.len() > 1 {
+            let names: Vec<String> = self.key_columns.iter().map(|&pos| quote_identifier(&self.columns[pos].name)).collect();
+            write!(fmt, ", primary key ({})", names.join(", "))?;
+        }
+        fmt.write_str(")")
+    }
+}
+
+// identifiers are always quoted so that names which are keywords can be parsed back.
+pub fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
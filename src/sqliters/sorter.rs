@@ -0,0 +1,506 @@
+use std::{env, fs, mem};
+use std::cmp::Ordering;
+use std::io::{self, Read, Seek, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{self, AtomicUsize};
+use sqliters::{row, expr, error, consts};
+
+// External merge sort of rows by sort keys.
+// Rows are kept in memory till they take max_memory bytes ; then they are sorted and written
+// as a run of pages to a temporary file. Runs are merged when rows are read back ; when there are more
+// than SORT_MERGE_RUNS runs, groups of them are first merged into longer runs at the end of the file.
+// Rows with equal keys keep the order in which they were added.
+
+static NEXT_SPILL_FILE_ID: AtomicUsize = AtomicUsize::new(0);
+
+// (sort keys, row) with its position in input.
+#[derive(Debug)]
+pub struct Record {
+    keys: Vec<row::Value>,
+    seq: u64,
+    row: row::Row
+}
+
+// run of sorted records starting at first_page of spill file.
+#[derive(Debug)]
+struct Run {
+    first_page: u64,
+    num_records: u64
+}
+
+#[derive(Debug)]
+pub struct Sorter {
+    descending: Vec<bool>,
+    page_size: usize,
+    max_memory: usize,
+    records: Vec<Record>,
+    memory: usize,
+    next_seq: u64,
+    runs: Vec<Run>,
+    spill_file: Option<SpillFile>
+}
+
+impl Sorter {
+    // descending has one entry per sort key ; memory_pages pages of rows are sorted in memory.
+    pub fn new(descending: Vec<bool>, page_size: usize, memory_pages: usize) -> Self {
+        Sorter {
+            descending,
+            page_size,
+            max_memory: page_size * memory_pages,
+            records: vec![],
+            memory: 0,
+            next_seq: 0,
+            runs: vec![],
+            spill_file: None
+        }
+    }
+
+    pub fn add(&mut self, keys: Vec<row::Value>, row: row::Row) -> Result<(), error::Error> {
+        if keys.len() != self.descending.len() {
+            return Err(error::Error::Other(format!("Expected {} sort keys but found {}", self.descending.len(), keys.len())))
+        }
+
+        let record = Record { keys, seq: self.next_seq, row };
+        self.next_seq += 1;
+        self.memory += encoded_size(&record);
+        self.records.push(record);
+
+        if self.memory > self.max_memory {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub fn num_runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    // sorted rows ; rows left in memory are merged with spilled runs.
+    pub fn finish(mut self) -> Result<SortedRows, error::Error> {
+        if self.spill_file.is_none() {
+            let mut records = mem::take(&mut self.records);
+            let descending = self.descending.clone();
+            records.sort_by(|a, b| compare_records(&descending, a, b));
+            return Ok(SortedRows::Memory(records.into_iter()))
+        }
+
+        self.spill()?;
+        let mut spill_file = self.spill_file.take().expect("spill file is created by spill");
+        let mut runs = mem::take(&mut self.runs);
+        while runs.len() > consts::SORT_MERGE_RUNS {
+            let mut merged_runs = vec![];
+            for group in runs.chunks(consts::SORT_MERGE_RUNS) {
+                let mut merge = Merge::new(&self.descending, &spill_file, self.page_size, group)?;
+                let mut writer = RunWriter::new(&spill_file, self.page_size);
+                while let Some(record) = merge.next_record()? {
+                    writer.add(&mut spill_file, &record)?;
+                }
+                merged_runs.push(writer.finish(&mut spill_file)?);
+            }
+            runs = merged_runs;
+        }
+
+        Ok(SortedRows::Merge {
+            merge: Merge::new(&self.descending, &spill_file, self.page_size, &runs)?,
+            _spill_file: spill_file
+        })
+    }
+
+    // writes rows in memory as a sorted run.
+    fn spill(&mut self) -> Result<(), error::Error> {
+        if self.records.is_empty() {
+            return Ok(())
+        }
+
+        let mut records = mem::take(&mut self.records);
+        self.memory = 0;
+        let descending = &self.descending;
+        records.sort_by(|a, b| compare_records(descending, a, b));
+
+        if self.spill_file.is_none() {
+            self.spill_file = Some(SpillFile::create()?);
+        }
+        let spill_file = self.spill_file.as_mut().expect("spill file is created above");
+
+        let mut writer = RunWriter::new(spill_file, self.page_size);
+        for record in records.iter() {
+            writer.add(spill_file, record)?;
+        }
+        self.runs.push(writer.finish(spill_file)?);
+        Ok(())
+    }
+}
+
+pub enum SortedRows {
+    Memory(::std::vec::IntoIter<Record>),
+    Merge {
+        merge: Merge,
+        // removes the file once all rows are read.
+        _spill_file: SpillFile
+    }
+}
+
+impl SortedRows {
+    pub fn next_row(&mut self) -> Result<Option<row::Row>, error::Error> {
+        match *self {
+            SortedRows::Memory(ref mut records) => Ok(records.next().map(|r| r.row)),
+            SortedRows::Merge { ref mut merge, .. } => Ok(merge.next_record()?.map(|r| r.row))
+        }
+    }
+}
+
+// records of runs in sort order.
+pub struct Merge {
+    descending: Vec<bool>,
+    // reader of each run with its next record.
+    readers: Vec<(RunReader, Option<Record>)>
+}
+
+impl Merge {
+    fn new(descending: &[bool], spill_file: &SpillFile, page_size: usize, runs: &[Run]) -> Result<Self, error::Error> {
+        let mut readers = vec![];
+        for run in runs {
+            let mut reader = RunReader::new(spill_file, page_size, run)?;
+            let head = reader.next_record()?;
+            readers.push((reader, head));
+        }
+        Ok(Merge { descending: descending.to_vec(), readers })
+    }
+
+    fn next_record(&mut self) -> Result<Option<Record>, error::Error> {
+        // atmost SORT_MERGE_RUNS runs ; smallest head is found by a linear scan.
+        let mut smallest: Option<usize> = None;
+        for (pos, (_, head)) in self.readers.iter().enumerate() {
+            let head = match head {
+                Some(head) => head,
+                None => continue
+            };
+            let is_smaller = match smallest {
+                None => true,
+                Some(s) => {
+                    let current = self.readers[s].1.as_ref().expect("smallest run has a head");
+                    compare_records(&self.descending, head, current) == Ordering::Less
+                }
+            };
+            if is_smaller {
+                smallest = Some(pos);
+            }
+        }
+
+        match smallest {
+            None => Ok(None),
+            Some(pos) => {
+                let next = self.readers[pos].0.next_record()?;
+                Ok(mem::replace(&mut self.readers[pos].1, next))
+            }
+        }
+    }
+}
+
+// writes records as a run at the end of spill file.
+// runs start at a page boundary ; last page of run is padded with zeroes.
+struct RunWriter {
+    first_page: u64,
+    num_records: u64,
+    page: Vec<u8>,
+    page_size: usize
+}
+
+impl RunWriter {
+    fn new(spill_file: &SpillFile, page_size: usize) -> Self {
+        RunWriter {
+            first_page: spill_file.num_pages,
+            num_records: 0,
+            page: Vec::with_capacity(page_size),
+            page_size
+        }
+    }
+
+    fn add(&mut self, spill_file: &mut SpillFile, record: &Record) -> Result<(), error::Error> {
+        let mut remaining: &[u8] = &encode_record(record);
+        while !remaining.is_empty() {
+            let n = remaining.len().min(self.page_size - self.page.len());
+            self.page.extend_from_slice(&remaining[..n]);
+            remaining = &remaining[n..];
+            if self.page.len() == self.page_size {
+                spill_file.write_page(&self.page)?;
+                self.page.clear();
+            }
+        }
+        self.num_records += 1;
+        Ok(())
+    }
+
+    fn finish(mut self, spill_file: &mut SpillFile) -> Result<Run, error::Error> {
+        if !self.page.is_empty() {
+            self.page.resize(self.page_size, 0);
+            spill_file.write_page(&self.page)?;
+        }
+        Ok(Run { first_page: self.first_page, num_records: self.num_records })
+    }
+}
+
+fn compare_records(descending: &[bool], a: &Record, b: &Record) -> Ordering {
+    for ((l, r), &desc) in a.keys.iter().zip(b.keys.iter()).zip(descending.iter()) {
+        // values of one sort key have the same type ; it is checked before rows are read.
+        let ordering = expr::compare(l, r).unwrap_or(Ordering::Equal);
+        if ordering != Ordering::Equal {
+            return if desc { ordering.reverse() } else { ordering }
+        }
+    }
+    a.seq.cmp(&b.seq)
+}
+
+// Record layout : seq : num keys : keys : num values : values
+// integer : tag 0 : 4 bytes ; text : tag 1 : 4 bytes length : bytes ; blob : tag 2 like text ;
+// bigint : tag 3 : 8 bytes ; real : tag 4 : 8 bytes ; boolean : tag 5 : 1 byte ; timestamp : tag 6 : 8 bytes ; null : tag 7.
+// Numbers are big endian.
+const INTEGER_TAG: u8 = 0;
+const TEXT_TAG: u8 = 1;
+const BLOB_TAG: u8 = 2;
+const BIGINT_TAG: u8 = 3;
+const REAL_TAG: u8 = 4;
+const BOOLEAN_TAG: u8 = 5;
+const TIMESTAMP_TAG: u8 = 6;
+const NULL_TAG: u8 = 7;
+
+fn encoded_size(record: &Record) -> usize {
+    let values_size = |values: &[row::Value]| -> usize {
+        values.iter().map(|v| match *v {
+            row::Value::Null => 1,
+            row::Value::Integer(_) => 1 + 4,
+            row::Value::BigInt(_) | row::Value::Real(_) | row::Value::Timestamp(_) => 1 + 8,
+            row::Value::Boolean(_) => 1 + 1,
+            row::Value::Text(ref t) => 1 + 4 + t.len(),
+            row::Value::Blob(ref b) => 1 + 4 + b.len()
+        }).sum()
+    };
+    8 + 4 + values_size(&record.keys) + 4 + values_size(record.row.values())
+}
+
+fn encode_record(record: &Record) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(encoded_size(record));
+    buf.extend_from_slice(&record.seq.to_be_bytes());
+    encode_values(&mut buf, &record.keys);
+    encode_values(&mut buf, record.row.values());
+    buf
+}
+
+fn encode_values(buf: &mut Vec<u8>, values: &[row::Value]) {
+    buf.extend_from_slice(&(values.len() as u32).to_be_bytes());
+    for value in values {
+        match *value {
+            row::Value::Null => buf.push(NULL_TAG),
+            row::Value::Integer(v) => {
+                buf.push(INTEGER_TAG);
+                buf.extend_from_slice(&v.to_be_bytes());
+            },
+            row::Value::BigInt(v) => {
+                buf.push(BIGINT_TAG);
+                buf.extend_from_slice(&v.to_be_bytes());
+            },
+            row::Value::Real(v) => {
+                buf.push(REAL_TAG);
+                buf.extend_from_slice(&v.to_bits().to_be_bytes());
+            },
+            row::Value::Boolean(v) => {
+                buf.push(BOOLEAN_TAG);
+                buf.push(v as u8);
+            },
+            row::Value::Timestamp(v) => {
+                buf.push(TIMESTAMP_TAG);
+                buf.extend_from_slice(&v.to_be_bytes());
+            },
+            row::Value::Text(ref v) => {
+                buf.push(TEXT_TAG);
+                buf.extend_from_slice(&(v.len() as u32).to_be_bytes());
+                buf.extend_from_slice(v.as_bytes());
+            },
+            row::Value::Blob(ref v) => {
+                buf.push(BLOB_TAG);
+                buf.extend_from_slice(&(v.len() as u32).to_be_bytes());
+                buf.extend_from_slice(v);
+            }
+        }
+    }
+}
+
+// temporary file of sorted runs ; it is deleted on drop.
+#[derive(Debug)]
+pub struct SpillFile {
+    path: PathBuf,
+    file: fs::File,
+    num_pages: u64
+}
+
+impl SpillFile {
+    fn create() -> Result<Self, error::Error> {
+        let id = NEXT_SPILL_FILE_ID.fetch_add(1, atomic::Ordering::SeqCst);
+        let path = env::temp_dir().join(format!("sqliters_sort_{}_{}.tmp", ::std::process::id(), id));
+        let file = fs::OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path)
+            .map_err(|e| error::Error::io(format!("Unable to create sort file {}", path.display()), e))?;
+        Ok(SpillFile {
+            path,
+            file,
+            num_pages: 0
+        })
+    }
+
+    fn write_page(&mut self, page: &[u8]) -> Result<(), error::Error> {
+        self.file.write_all(page)
+            .map_err(|e| error::Error::io(format!("Failed to write sort file {}", self.path.display()), e))?;
+        self.num_pages += 1;
+        Ok(())
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        // file is only scratch space ; a failure to delete it does not affect results.
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// reads records of one run a page at a time.
+pub struct RunReader {
+    reader: io::BufReader<fs::File>,
+    remaining: u64,
+    path: PathBuf
+}
+
+impl RunReader {
+    fn new(spill_file: &SpillFile, page_size: usize, run: &Run) -> Result<Self, error::Error> {
+        let error = |e: io::Error| error::Error::io(format!("Unable to read sort file {}", spill_file.path.display()), e);
+        let mut file = fs::File::open(&spill_file.path).map_err(error)?;
+        file.seek(io::SeekFrom::Start(run.first_page * page_size as u64)).map_err(error)?;
+        Ok(RunReader {
+            reader: io::BufReader::with_capacity(page_size, file),
+            remaining: run.num_records,
+            path: spill_file.path.clone()
+        })
+    }
+
+    fn next_record(&mut self) -> Result<Option<Record>, error::Error> {
+        if self.remaining == 0 {
+            return Ok(None)
+        }
+        self.remaining -= 1;
+
+        let mut seq = [0u8; 8];
+        self.read(&mut seq)?;
+        let keys = self.read_values()?;
+        let values = self.read_values()?;
+        Ok(Some(Record {
+            keys,
+            seq: u64::from_be_bytes(seq),
+            row: row::Row::new(values)
+        }))
+    }
+
+    fn read_values(&mut self) -> Result<Vec<row::Value>, error::Error> {
+        let num_values = self.read_u32()?;
+        let mut values = Vec::with_capacity(num_values as usize);
+        for _ in 0 .. num_values {
+            let mut tag = [0u8; 1];
+            self.read(&mut tag)?;
+            match tag[0] {
+                INTEGER_TAG => values.push(row::Value::Integer(self.read_u32()? as i32)),
+                BIGINT_TAG => values.push(row::Value::BigInt(self.read_u64()? as i64)),
+                REAL_TAG => values.push(row::Value::Real(f64::from_bits(self.read_u64()?))),
+                BOOLEAN_TAG => {
+                    let mut byte = [0u8; 1];
+                    self.read(&mut byte)?;
+                    values.push(row::Value::Boolean(byte[0] != 0));
+                },
+                TIMESTAMP_TAG => values.push(row::Value::Timestamp(self.read_u64()? as i64)),
+                NULL_TAG => values.push(row::Value::Null),
+                TEXT_TAG => {
+                    let mut bytes = vec![0u8; self.read_u32()? as usize];
+                    self.read(&mut bytes)?;
+                    let text = String::from_utf8(bytes)
+                        .map_err(|e| error::Error::Corrupt(format!("Unable to read text from sort file {} : error {}", self.path.display(), e)))?;
+                    values.push(row::Value::Text(text));
+                },
+                BLOB_TAG => {
+                    let mut bytes = vec![0u8; self.read_u32()? as usize];
+                    self.read(&mut bytes)?;
+                    values.push(row::Value::Blob(bytes));
+                },
+                tag => return Err(error::Error::Corrupt(format!("Unknown value tag {} in sort file {}", tag, self.path.display())))
+            }
+        }
+        Ok(values)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, error::Error> {
+        let mut bytes = [0u8; 4];
+        self.read(&mut bytes)?;
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, error::Error> {
+        let mut bytes = [0u8; 8];
+        self.read(&mut bytes)?;
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), error::Error> {
+        self.reader.read_exact(buf)
+            .map_err(|e| error::Error::io(format!("Unable to read sort file {}", self.path.display()), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sort(rows: &[(i32, &str)], descending: Vec<bool>, memory_pages: usize) -> (usize, Vec<(i32, String)>) {
+        let mut sorter = Sorter::new(descending, 64, memory_pages);
+        for &(id, name) in rows {
+            let row = row::Row::new(vec![row::Value::Integer(id), row::Value::Text(name.to_string())]);
+            sorter.add(vec![row::Value::Text(name.to_string())], row).expect("row should be added");
+        }
+        let num_runs = sorter.num_runs();
+
+        let mut sorted = vec![];
+        let mut rows = sorter.finish().expect("rows should be sorted");
+        while let Some(row) = rows.next_row().expect("row should be read") {
+            match (row.get(0), row.get(1)) {
+                (Some(&row::Value::Integer(id)), Some(row::Value::Text(name))) => sorted.push((id, name.clone())),
+                other => panic!("unexpected row {:?}", other)
+            }
+        }
+        (num_runs, sorted)
+    }
+
+    #[test]
+    fn test_sorter() {
+        let names = ["m", "c", "x", "a", "c", "zz", "b", "m", "a", "y"];
+        let rows: Vec<(i32, &str)> = (0 .. 200).map(|i| (i, names[(i as usize * 7) % names.len()])).collect();
+
+        let mut expected: Vec<(i32, String)> = rows.iter().map(|&(id, name)| (id, name.to_string())).collect();
+        // stable sort keeps ids of equal names in order.
+        expected.sort_by(|a, b| a.1.cmp(&b.1));
+
+        let (num_runs, in_memory) = sort(&rows, vec![false], 1000);
+        assert!(num_runs == 0, "no spill expected : {} runs", num_runs);
+        assert!(in_memory == expected, "{:?}", in_memory);
+
+        let (num_runs, spilled) = sort(&rows, vec![false], 2);
+        assert!(num_runs > 2, "rows should spill to runs : {} runs", num_runs);
+        assert!(spilled == expected, "{:?}", spilled);
+
+        // runs are merged in more than one pass.
+        let (num_runs, many_runs) = sort(&rows, vec![false], 1);
+        assert!(num_runs > consts::SORT_MERGE_RUNS, "rows should spill to many runs : {} runs", num_runs);
+        assert!(many_runs == expected, "{:?}", many_runs);
+
+        expected.sort_by(|a, b| b.1.cmp(&a.1));
+        let (_, descending) = sort(&rows, vec![true], 2);
+        assert!(descending == expected, "{:?}", descending);
+
+        let (_, empty) = sort(&[], vec![false], 2);
+        assert!(empty.is_empty(), "{:?}", empty);
+    }
+}
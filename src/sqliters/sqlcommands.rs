@@ -1,47 +1,865 @@
-use sqliters::{statement, table, cursor, context};
+use std::collections::HashSet;
+use sqliters::{ast, statement, database, cursor, context, row, schema, table, index, pager, btree, expr, planner, sorter, consts, aggregate, error};
 
-pub fn process_sql_command(context: &mut context::Context, table: &mut table::Table, command : &str) -> Result<(), String>
+pub fn process_sql_command(context: &mut context::Context, db: &mut database::Database, command : &str) -> Result<(), error::Error>
 {
-    let result = statement::prepare_statement(command);
-    match result {
-        Ok(statement) => {
-            execute_statement(context, table, statement)
+    // a command with ? parameters fails as they are not bound.
+    prepare(context, command)?.execute(context, db)
+}
+
+// parses sql once into a statement which can be executed many times ; parsed statements are cached by sql.
+pub fn prepare(context: &mut context::Context, sql: &str) -> Result<statement::PreparedStatement, error::Error>
+{
+    Ok(statement::PreparedStatement::new(context.statements().get(sql)?))
+}
+
+pub fn execute_statement(context: &mut context::Context, db: &mut database::Database, statement: ast::Statement) -> Result<(), error::Error>
+{
+    match statement {
+        ast::Statement::CreateTable(create_statement) => {
+            execute_create_table_statement(db, create_statement)
+        },
+        ast::Statement::CreateIndex(create_statement) => {
+            execute_create_index_statement(db, create_statement)
         },
-        Err(msg) => {
-            Err(format!("Failure: {} for command '{}'", msg, command))
+        ast::Statement::Insert(insert_statement) => {
+            execute_insert_statement(db, insert_statement)
+        },
+        ast::Statement::Select(select_statement) => {
+            execute_select_statement(context, db, select_statement)
+        },
+        ast::Statement::Delete(delete_statement) => {
+            execute_delete_statement(db, delete_statement)
+        },
+        ast::Statement::Update(update_statement) => {
+            execute_update_statement(db, update_statement)
         }
     }
 }
 
-fn execute_statement(context: &mut context::Context, table: &mut table::Table, statement: statement::Statement) -> Result<(), String>
+fn execute_create_table_statement(db: &mut database::Database, statement: ast::CreateTableStatement) -> Result<(), error::Error>
 {
-    match statement {
-        statement::Statement::Insert(insert_statement) => {
-            execute_insert_statement(table, insert_statement)
+    if statement.table_name.eq_ignore_ascii_case(database::SEQUENCE_TABLE_NAME) {
+        return Err(error::Error::Other(format!("Table name '{}' is reserved", statement.table_name)))
+    }
+    let schema = statement.schema()?;
+
+    // unique columns other than a primary key of one column get an index ; they are named like sqlite names its auto indexes.
+    let auto_indexes: Vec<(String, &str)> = statement.columns.iter()
+        .enumerate()
+        .filter(|&(pos, c)| c.unique && schema.key_columns() != [pos])
+        .enumerate()
+        .map(|(n, (_, c))| (format!("sqliters_autoindex_{}_{}", statement.table_name, n + 1), c.name.as_str()))
+        .collect();
+    // checked before the table is created, so that a failed create leaves no table without its indexes.
+    for &(ref index_name, column_name) in auto_indexes.iter() {
+        db.check_new_index(index_name, &schema, column_name)?;
+    }
+
+    db.create_table(&statement.table_name, schema)?;
+    for (index_name, column_name) in auto_indexes {
+        db.create_index(&index_name, &statement.table_name, column_name, true)?;
+    }
+    Ok(())
+}
+
+fn execute_create_index_statement(db: &mut database::Database, statement: ast::CreateIndexStatement) -> Result<(), error::Error>
+{
+    modifiable_table(db, &statement.table_name)?;
+    db.create_index(&statement.index_name, &statement.table_name, &statement.column, statement.unique)
+}
+
+fn execute_insert_statement(db: &mut database::Database, statement: ast::InsertStatement) -> Result<(), error::Error>
+{
+    let table = modifiable_table(db, &statement.table_name)?;
+    let schema = table.schema();
+    let column_positions = insert_column_positions(schema, &statement.columns)?;
+    if let Some(ref target) = statement.conflict_target {
+        if schema.key_columns() != [schema.column_position(target)?] {
+            return Err(error::Error::Other(format!("ON CONFLICT column '{}' is not the primary key of table '{}'", target, table.name())))
+        }
+    }
+    let conflict_assignments = match statement.on_conflict {
+        ast::OnConflict::Update(ref assignments) => resolve_assignments(schema, assignments)?,
+        _ => vec![]
+    };
+
+    for literals in statement.rows.iter() {
+        let mut row = build_row(schema, &column_positions, literals)?;
+        if let Some(pos) = schema.auto_key() {
+            if row.values()[pos] == row::Value::Null {
+                let mut values = row.values().to_vec();
+                values[pos] = next_rowid(db, &table, pos)?;
+                row = row::Row::new(values);
+            }
+        }
+        check_not_null(&table, &row)?;
+        let key = row.key(schema)?;
+        row::validate_row(schema, &row)?;
+
+        let existing_row = find_row(db.pager(), &table, &key)?;
+
+        // REPLACE deletes other rows which have a value of a unique column of row ; the conflict target
+        // of an upsert is the primary key, so other conflicts fail the upsert.
+        if let ast::OnConflict::Replace = statement.on_conflict {
+            while let Some((other_key, _)) = unique_conflict(db.pager(), &table, &row)? {
+                let other_row = find_row(db.pager(), &table, &other_key)?
+                    .ok_or_else(|| format!("Unique index refers to missing key {:?} of table '{}'", other_key, table.name()))?;
+                delete_row(db.pager(), &table, &other_row)?;
+            }
+        } else if existing_row.is_none() {
+            if let Some((_, error)) = unique_conflict(db.pager(), &table, &row)? {
+                match statement.on_conflict {
+                    ast::OnConflict::Ignore => continue,
+                    _ => return Err(error)
+                }
+            }
+        }
+
+        let existing_row = match existing_row {
+            Some(existing_row) => existing_row,
+            None => {
+                insert_row(db.pager(), &table, &row)?;
+                add_index_entries(db.pager(), &table, &row)?;
+                note_inserted_row(db, &table, &row)?;
+                continue;
+            }
+        };
+
+        let new_row = match statement.on_conflict {
+            ast::OnConflict::Abort => return Err(error::Error::key_violation(table.name(), schema, &row)),
+            ast::OnConflict::Ignore => continue,
+            ast::OnConflict::Replace => row.clone(),
+            ast::OnConflict::Update(_) => {
+                let assignments = conflict_assignments.iter()
+                    .map(|&(pos, expr)| Ok((pos, expr::bind_excluded(expr, schema, &row)?)))
+                    .collect::<Result<Vec<_>, error::Error>>()?;
+                assign(schema, assignments.iter().map(|&(pos, ref expr)| (pos, expr)), &existing_row)?
+            }
+        };
+        write_updates(db.pager(), &table, vec![(existing_row, new_row)])?;
+        if let ast::OnConflict::Replace = statement.on_conflict {
+            note_inserted_row(db, &table, &row)?;
+        }
+    }
+
+    Ok(())
+}
+
+// id for a row which has none in the key column at key_pos : one more than the largest key of table.
+// An AUTOINCREMENT key is also larger than any id given before, so ids of deleted rows are not given again.
+fn next_rowid(db: &mut database::Database, table: &table::Table, key_pos: usize) -> Result<row::Value, error::Error>
+{
+    let largest = {
+        let last_key = vec![0xff; table.schema().key_size()];
+        let mut cursor = cursor::Cursor::table_seek_back(db.pager(), table, &last_key)?;
+        if cursor.end_of_table() { 0 } else { integer_key(&cursor.cursor_row(table.schema())?.values()[key_pos]).unwrap_or(0) }
+    };
+    let column = &table.schema().columns()[key_pos];
+    let given = if column.is_autoincrement() { sequence_value(db, table.name())? } else { 0 };
+
+    let next = largest.max(given).checked_add(1);
+    match (column.column_type(), next) {
+        (schema::ColumnType::Integer, Some(id)) if id <= i64::from(i32::MAX) => Ok(row::Value::Integer(id as i32)),
+        (schema::ColumnType::BigInt, Some(id)) => Ok(row::Value::BigInt(id)),
+        _ => Err(error::Error::Other(format!("Table '{}' has no more ids for column '{}'", table.name(), column.name())))
+    }
+}
+
+// keeps the key of a row inserted into a table keyed on an integer column ; AUTOINCREMENT keys also go to the sequence table.
+fn note_inserted_row(db: &mut database::Database, table: &table::Table, row: &row::Row) -> Result<(), error::Error>
+{
+    let schema = table.schema();
+    let (pos, id) = match *schema.key_columns() {
+        [pos] => match integer_key(&row.values()[pos]) {
+            Some(id) => (pos, id),
+            None => return Ok(())
         },
-        statement::Statement::Select => {
-            execute_select_statement(context, table)
+        _ => return Ok(())
+    };
+    db.set_last_insert_rowid(id);
+
+    if schema.columns()[pos].is_autoincrement() && id > sequence_value(db, table.name())? {
+        let sequence = db.get_table(database::SEQUENCE_TABLE_NAME)?;
+        let sequence_row = row::Row::new(vec![row::Value::Text(table.name().to_string()), row::Value::BigInt(id)]);
+        if let Some(old_row) = find_row(db.pager(), &sequence, &sequence_row.key(sequence.schema())?)? {
+            delete_row(db.pager(), &sequence, &old_row)?;
+        }
+        insert_row(db.pager(), &sequence, &sequence_row)?;
+    }
+    Ok(())
+}
+
+// largest id given to rows of table with an AUTOINCREMENT key ; 0 before the first row.
+fn sequence_value(db: &mut database::Database, table_name: &str) -> Result<i64, error::Error>
+{
+    let sequence = db.get_table(database::SEQUENCE_TABLE_NAME)?;
+    let key = row::Row::new(vec![row::Value::Text(table_name.to_string()), row::Value::Null]).key(sequence.schema())?;
+    match find_row(db.pager(), &sequence, &key)? {
+        Some(row) => integer_key(&row.values()[1]).ok_or_else(|| error::Error::Corrupt(format!("Bad sequence row {}", row))),
+        None => Ok(0)
+    }
+}
+
+fn integer_key(value: &row::Value) -> Option<i64> {
+    match *value {
+        row::Value::Integer(v) => Some(i64::from(v)),
+        row::Value::BigInt(v) => Some(v),
+        _ => None
+    }
+}
+
+// adds a row whose key is not in table.
+fn insert_row(pager: &mut pager::Pager, table: &table::Table, row: &row::Row) -> Result<(), error::Error>
+{
+    let key = row.key(table.schema())?;
+    let max_row_size = table.max_row_size(pager)?;
+    let serialized = row::serialize_row(pager, table.schema(), row, max_row_size)?;
+    cursor::Cursor::table_find(pager, table, &key)?.insert(&key, serialized).map_err(|e| match e {
+        error::Error::UniqueViolation { .. } => error::Error::key_violation(table.name(), table.schema(), row),
+        e => e
+    })
+}
+
+// cell of the row with key, without reading overflow pages of its values.
+fn serialized_row(pager: &mut pager::Pager, table: &table::Table, key: &[u8]) -> Result<Vec<u8>, error::Error>
+{
+    let mut cursor = cursor::Cursor::table_find(pager, table, key)?;
+    if !cursor.is_at(key)? {
+        return Err(error::Error::Other(format!("Row with key {:?} is not present in table '{}'", key, table.name())))
+    }
+    Ok(cursor.cursor_value()?.to_vec())
+}
+
+// removes the cell of key with the overflow pages of its values.
+fn remove_row_cell(pager: &mut pager::Pager, table: &table::Table, key: &[u8]) -> Result<(), error::Error>
+{
+    let serialized = serialized_row(pager, table, key)?;
+    row::free_overflow(pager, table.schema(), &serialized)?;
+    btree::delete_key(pager, table.root_page_num(), key)?;
+    Ok(())
+}
+
+fn add_index_entries(pager: &mut pager::Pager, table: &table::Table, row: &row::Row) -> Result<(), error::Error>
+{
+    for index in table.indexes() {
+        index.add(pager, table.schema(), row)?;
+    }
+    Ok(())
+}
+
+fn delete_row(pager: &mut pager::Pager, table: &table::Table, row: &row::Row) -> Result<(), error::Error>
+{
+    remove_row_cell(pager, table, &row.key(table.schema())?)?;
+    for index in table.indexes() {
+        index.remove(pager, table.schema(), row)?;
+    }
+    Ok(())
+}
+
+fn find_row(pager: &mut pager::Pager, table: &table::Table, key: &[u8]) -> Result<Option<row::Row>, error::Error>
+{
+    let mut cursor = cursor::Cursor::table_find(pager, table, key)?;
+    if !cursor.is_at(key)? {
+        return Ok(None)
+    }
+    cursor.cursor_row(table.schema()).map(Some)
+}
+
+// keys of rows which have value in the column of index ; index keys only have a prefix of long values.
+fn keys_with_value(pager: &mut pager::Pager, table: &table::Table, index: &index::Index, value: &row::Value) -> Result<Vec<Vec<u8>>, error::Error>
+{
+    let mut keys = vec![];
+    for key in index.find(pager, value)? {
+        let row = find_row(pager, table, &key)?
+            .ok_or_else(|| format!("Index '{}' refers to missing key {:?} of table '{}'", index.name(), key, table.name()))?;
+        if row.values()[index.column_pos()] == *value {
+            keys.push(key);
         }
     }
+    Ok(keys)
 }
 
-fn execute_insert_statement(table: &mut table::Table, statement: statement::InsertStatement) -> Result<(), String>
+// key of another row which has the value of row in a unique index, with the violation it gives.
+fn unique_conflict(pager: &mut pager::Pager, table: &table::Table, row: &row::Row) -> Result<Option<(Vec<u8>, error::Error)>, error::Error>
 {
-    let deserialized = statement::serialize_row(&statement)?;
-    let mut cursor = cursor::Cursor::table_find(table, statement.id())?;
-    cursor.serialize_row_add(deserialized)
+    let key = row.key(table.schema())?;
+    for index in table.indexes().iter().filter(|i| i.is_unique()) {
+        let value = &row.values()[index.column_pos()];
+        if let Some(other_key) = keys_with_value(pager, table, index, value)?.into_iter().find(|k| *k != key) {
+            return Ok(Some((other_key, error::Error::unique_violation(table.name(), index.column().name(), value))))
+        }
+    }
+    Ok(None)
 }
 
-fn execute_select_statement(context: &mut context::Context, table: &mut table::Table) -> Result<(), String>
+// position of each inserted value in the schema ; columns which are not given are null.
+// Without column names values go to columns which are not hidden.
+fn insert_column_positions(schema: &schema::Schema, columns: &Option<Vec<String>>) -> Result<Vec<usize>, error::Error>
 {
-    let mut cursor = cursor::Cursor::table_start(table)?;
+    let columns = match *columns {
+        None => return Ok((0 .. schema.columns().len()).filter(|&pos| !schema.columns()[pos].is_hidden()).collect()),
+        Some(ref columns) => columns
+    };
+
+    let mut positions = vec![];
+    for name in columns {
+        let pos = schema.column_position(name)?;
+        if positions.contains(&pos) {
+            return Err(error::Error::Other(format!("Column '{}' is given more than once", name)))
+        }
+        positions.push(pos);
+    }
 
+    Ok(positions)
+}
+
+fn check_not_null(table: &table::Table, row: &row::Row) -> Result<(), error::Error>
+{
+    let columns = table.schema().columns();
+    match columns.iter().zip(row.values()).find(|&(column, value)| *value == row::Value::Null && !column.is_nullable()) {
+        Some((column, _)) => Err(error::Error::not_null_violation(table.name(), column.name())),
+        None => Ok(())
+    }
+}
+
+fn build_row(schema: &schema::Schema, column_positions: &[usize], literals: &[ast::Literal]) -> Result<row::Row, error::Error>
+{
+    if literals.len() != column_positions.len() {
+        return Err(error::Error::Other(format!("Expected {} values but found {}", column_positions.len(), literals.len())))
+    }
+
+    let mut values = vec![row::Value::Null; schema.columns().len()];
+    for (pos, literal) in column_positions.iter().zip(literals.iter()) {
+        values[*pos] = row::Value::from_literal(&schema.columns()[*pos], literal)?;
+    }
+
+    Ok(row::Row::new(values))
+}
+
+fn execute_select_statement(context: &mut context::Context, db: &mut database::Database, statement: ast::SelectStatement) -> Result<(), error::Error>
+{
+    let table = db.get_table(&statement.table_name)?;
+    let schema = table.schema();
+    let projection = resolve_projection(schema, &statement.projection)?;
+    let order_by = resolve_order_by(schema, &statement.order_by, &projection)?;
+    let columns: Vec<schema::Column> = projection.iter().map(|(column, _)| column.clone()).collect();
+    context.select_columns(&columns);
+    let project = |row: &row::Row| -> Result<row::Row, error::Error> {
+        let values = projection.iter()
+            .map(|(column, expr)| expr::evaluate(expr, schema, row)?.cast(column))
+            .collect::<Result<Vec<_>, error::Error>>()?;
+        Ok(row::Row::new(values))
+    };
+
+    // rows before offset are skipped ; returns false once limit rows are out.
+    let offset = statement.offset.unwrap_or(0);
+    let (mut skipped, mut emitted) = (0, 0);
+    let mut emit = |row: &row::Row| -> bool {
+        if skipped < offset {
+            skipped += 1;
+        } else {
+            context.select_out(&columns, row);
+            emitted += 1;
+        }
+        statement.limit.is_none_or(|limit| emitted < limit)
+    };
+    if statement.limit == Some(0) {
+        return Ok(())
+    }
+
+    let aggregated = !statement.group_by.is_empty()
+        || projection.iter().any(|(_, expr)| expr::contains_aggregate(expr))
+        || order_by.iter().any(|(expr, _)| expr::contains_aggregate(expr));
+    if aggregated {
+        return select_groups(db.pager(), &table, &statement, &projection, &order_by, emit)
+    }
+
+    // rows come in key order from the tree when order by is a prefix of key columns in one direction ;
+    // any other order needs a sort of all matching rows.
+    let is_key_prefix = order_by.len() <= schema.key_columns().len()
+        && order_by.iter().zip(schema.key_columns()).all(|(term, &key_column)| match *term {
+            (ast::Expr::Column(ref name), _) => schema.column_position(name).ok() == Some(key_column),
+            _ => false
+        });
+    let key_order = match order_by.first() {
+        Some(&(_, descending)) if is_key_prefix && order_by.iter().all(|&(_, d)| d == descending) => Some(descending),
+        _ => None
+    };
+
+    // without order by rows can come in any order.
+    if order_by.is_empty() || key_order.is_some() {
+        return scan_matching_rows(db.pager(), &table, statement.where_clause.as_ref(), key_order, |row| {
+            Ok(emit(&project(row)?))
+        })
+    }
+
+    let mut sorter = sorter::Sorter::new(order_by.iter().map(|&(_, descending)| descending).collect(), db.pager().page_size(), consts::SORT_MEMORY_PAGES);
+    for_each_matching_row(db.pager(), &table, statement.where_clause.as_ref(), |row| {
+        let keys = order_by.iter()
+            .map(|(expr, _)| expr::evaluate(expr, schema, row))
+            .collect::<Result<Vec<_>, error::Error>>()?;
+        sorter.add(keys, project(row)?)
+    })?;
+    emit_sorted(sorter, emit)
+}
+
+fn emit_sorted<F>(sorter: sorter::Sorter, mut emit: F) -> Result<(), error::Error>
+    where F: FnMut(&row::Row) -> bool
+{
+    let mut sorted = sorter.finish()?;
+    while let Some(row) = sorted.next_row()? {
+        if !emit(&row) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// one output row per group of rows with equal group by values ; a query with aggregates
+// and no group by has a single group of all matching rows.
+fn select_groups<F>(pager: &mut pager::Pager, table: &table::Table, statement: &ast::SelectStatement,
+                    projection: &[(schema::Column, ast::Expr)], order_by: &[(ast::Expr, bool)], mut emit: F) -> Result<(), error::Error>
+    where F: FnMut(&row::Row) -> bool
+{
+    let schema = table.schema();
+    let group_by = &statement.group_by;
+    for expr in group_by {
+        if expr::contains_aggregate(expr) {
+            return Err(error::Error::Other(format!("Aggregate can not be used in GROUP BY : {}", expr)))
+        }
+        expr::result_type(expr, schema)?;
+    }
+
+    let outputs: Vec<&ast::Expr> = projection.iter().map(|(_, expr)| expr).chain(order_by.iter().map(|(expr, _)| expr)).collect();
+    for expr in outputs.iter() {
+        aggregate::check_grouped(expr, group_by)?;
+    }
+    let aggregates = aggregate::collect_aggregates(&outputs);
+
+    let tree_results = if group_by.is_empty() {
+        tree_aggregates(pager, table, statement.where_clause.as_ref(), &aggregates)?
+    } else {
+        None
+    };
+    let groups = match tree_results {
+        Some(results) => vec![(vec![], results)],
+        None => group_rows(pager, table, statement.where_clause.as_ref(), group_by, &aggregates)?
+    };
+
+    // groups come in group by order ; order by needs another sort.
+    let mut sorter = if order_by.is_empty() {
+        None
+    } else {
+        Some(sorter::Sorter::new(order_by.iter().map(|&(_, descending)| descending).collect(), pager.page_size(), consts::SORT_MEMORY_PAGES))
+    };
+    let no_columns = row::Row::new(vec![]);
+    for (group_values, results) in groups {
+        let value_of = |expr: &ast::Expr| {
+            expr::evaluate(&aggregate::bind_group(expr, group_by, &group_values, &aggregates, &results), schema, &no_columns)
+        };
+        // values of groups and aggregates go through literals ; they are cast back to their result types.
+        let row = row::Row::new(projection.iter().map(|(column, expr)| value_of(expr)?.cast(column)).collect::<Result<Vec<_>, error::Error>>()?);
+        match sorter {
+            Some(ref mut sorter) => sorter.add(order_by.iter().map(|(expr, _)| value_of(expr)).collect::<Result<Vec<_>, error::Error>>()?, row)?,
+            None => if !emit(&row) {
+                return Ok(())
+            }
+        }
+    }
+
+    match sorter {
+        Some(sorter) => emit_sorted(sorter, emit),
+        None => Ok(())
+    }
+}
+
+// (group by values, aggregate results) of a group.
+type Group = (Vec<row::Value>, Vec<row::Value>);
+
+// groups in order of group by values.
+// Rows are sorted by group by values so that rows of a group come together.
+fn group_rows(pager: &mut pager::Pager, table: &table::Table, where_clause: Option<&ast::Expr>,
+              group_by: &[ast::Expr], aggregates: &[ast::Expr]) -> Result<Vec<Group>, error::Error>
+{
+    let schema = table.schema();
+    // argument of each aggregate for a row ; count(*) has no argument.
+    let arguments = |row: &row::Row| -> Result<Vec<row::Value>, error::Error> {
+        aggregates.iter().map(|aggregate| match *aggregate {
+            ast::Expr::Aggregate { arg: Some(ref arg), .. } => expr::evaluate(arg, schema, row),
+            _ => Ok(row::Value::Integer(0))
+        }).collect()
+    };
+    let new_accumulators = || aggregates.iter().map(|aggregate| match *aggregate {
+        ast::Expr::Aggregate { function, .. } => aggregate::Accumulator::new(function),
+        _ => unreachable!("only aggregates are collected")
+    }).collect::<Vec<_>>();
+    let finish = |accumulators: &[aggregate::Accumulator]| -> Result<Vec<row::Value>, String> {
+        accumulators.iter().zip(aggregates.iter()).map(|(acc, aggregate)| acc.finish(aggregate)).collect()
+    };
+
+    if group_by.is_empty() {
+        let mut accumulators = new_accumulators();
+        for_each_matching_row(pager, table, where_clause, |row| {
+            for (acc, value) in accumulators.iter_mut().zip(arguments(row)?) {
+                acc.add(value)?;
+            }
+            Ok(())
+        })?;
+        return Ok(vec![(vec![], finish(&accumulators)?)])
+    }
+
+    // sorted rows are group by values followed by aggregate arguments.
+    let mut sorter = sorter::Sorter::new(vec![false; group_by.len()], pager.page_size(), consts::SORT_MEMORY_PAGES);
+    for_each_matching_row(pager, table, where_clause, |row| {
+        let keys = group_by.iter().map(|expr| expr::evaluate(expr, schema, row)).collect::<Result<Vec<_>, error::Error>>()?;
+        let mut values = keys.clone();
+        values.extend(arguments(row)?);
+        sorter.add(keys, row::Row::new(values))
+    })?;
+
+    let mut groups = vec![];
+    let mut current: Option<(Vec<row::Value>, Vec<aggregate::Accumulator>)> = None;
+    let mut sorted = sorter.finish()?;
+    while let Some(row) = sorted.next_row()? {
+        let (keys, values) = row.values().split_at(group_by.len());
+        let same_group = match current {
+            Some((ref current_keys, _)) => current_keys.as_slice() == keys,
+            None => false
+        };
+        if !same_group {
+            if let Some((group_keys, accumulators)) = current.take() {
+                groups.push((group_keys, finish(&accumulators)?));
+            }
+            current = Some((keys.to_vec(), new_accumulators()));
+        }
+
+        let accumulators = &mut current.as_mut().expect("current group is set above").1;
+        for (acc, value) in accumulators.iter_mut().zip(values.iter()) {
+            acc.add(value.clone())?;
+        }
+    }
+    if let Some((group_keys, accumulators)) = current {
+        groups.push((group_keys, finish(&accumulators)?));
+    }
+    Ok(groups)
+}
+
+// results of aggregates read from the tree without visiting every row ; None when some aggregate needs all rows.
+// count(*) adds up cells of leaves and min / max of first key column stop at the first matching row from either end.
+fn tree_aggregates(pager: &mut pager::Pager, table: &table::Table, where_clause: Option<&ast::Expr>,
+                   aggregates: &[ast::Expr]) -> Result<Option<Vec<row::Value>>, error::Error>
+{
+    let schema = table.schema();
+    let is_key = |arg: &ast::Expr| match *arg {
+        ast::Expr::Column(ref name) => schema.column_position(name).ok() == Some(schema.key_columns()[0]),
+        _ => false
+    };
+
+    let mut results = vec![];
+    for aggregate in aggregates {
+        let (function, arg) = match *aggregate {
+            ast::Expr::Aggregate { function, ref arg } => (function, arg),
+            _ => return Ok(None)
+        };
+
+        match (function, arg) {
+            (ast::AggregateFunction::Count, &None) if where_clause.is_none() => {
+                let count = btree::count_keys(pager, table.root_page_num())?;
+                results.push(aggregate::Accumulator::Count(count as i64).finish(aggregate)?);
+            },
+            (ast::AggregateFunction::Min, &Some(ref arg)) | (ast::AggregateFunction::Max, &Some(ref arg)) if is_key(arg) => {
+                let mut accumulator = aggregate::Accumulator::new(function);
+                let mut found = None;
+                scan_matching_rows(pager, table, where_clause, Some(function == ast::AggregateFunction::Max), |row| {
+                    found = Some(row.values()[schema.key_columns()[0]].clone());
+                    Ok(false)
+                })?;
+                if let Some(value) = found {
+                    accumulator.add(value)?;
+                }
+                results.push(accumulator.finish(aggregate)?);
+            },
+            _ => return Ok(None)
+        }
+    }
+    Ok(Some(results))
+}
+
+// expressions to sort by with their direction ; a name which is not a column of table can refer to an alias in projection.
+fn resolve_order_by(schema: &schema::Schema, order_by: &[ast::OrderByItem], projection: &[(schema::Column, ast::Expr)]) -> Result<Vec<(ast::Expr, bool)>, error::Error>
+{
+    let mut resolved = vec![];
+    for item in order_by {
+        let expr = match item.expr {
+            ast::Expr::Column(ref name) if schema.column_position(name).is_err() => {
+                match projection.iter().find(|(column, _)| column.name() == name) {
+                    Some((_, expr)) => expr.clone(),
+                    None => item.expr.clone()
+                }
+            },
+            _ => item.expr.clone()
+        };
+        expr::result_type(&expr, schema)?;
+        resolved.push((expr, item.descending));
+    }
+    Ok(resolved)
+}
+
+// result column and expression of each selected value ; * expands to all columns of table which are not hidden.
+fn resolve_projection(schema: &schema::Schema, projection: &[ast::SelectItem]) -> Result<Vec<(schema::Column, ast::Expr)>, error::Error>
+{
+    let mut resolved = vec![];
+    for item in projection {
+        match *item {
+            ast::SelectItem::Wildcard => {
+                for column in schema.columns().iter().filter(|c| !c.is_hidden()) {
+                    resolved.push((column.clone(), ast::Expr::Column(column.name().to_string())));
+                }
+            },
+            ast::SelectItem::Expr { ref expr, ref alias } => {
+                let column_type = expr::result_type(expr, schema)?;
+                let name = match *alias {
+                    Some(ref alias) => alias.clone(),
+                    None => expr.to_string()
+                };
+                resolved.push((schema::Column::new(&name, column_type), expr.clone()));
+            }
+        }
+    }
+    Ok(resolved)
+}
+
+fn execute_delete_statement(db: &mut database::Database, statement: ast::DeleteStatement) -> Result<(), error::Error>
+{
+    let table = modifiable_table(db, &statement.table_name)?;
+
+    // collect rows first ; deleting moves cells under the cursor.
+    let mut rows = vec![];
+    for_each_matching_row(db.pager(), &table, statement.where_clause.as_ref(), |row| {
+        rows.push(row.clone());
+        Ok(())
+    })?;
+
+    for row in rows {
+        delete_row(db.pager(), &table, &row)?;
+    }
+    Ok(())
+}
+
+fn execute_update_statement(db: &mut database::Database, statement: ast::UpdateStatement) -> Result<(), error::Error>
+{
+    let table = modifiable_table(db, &statement.table_name)?;
+    let schema = table.schema();
+    let assignments = resolve_assignments(schema, &statement.assignments)?;
+
+    let mut old_rows = vec![];
+    for_each_matching_row(db.pager(), &table, statement.where_clause.as_ref(), |row| {
+        old_rows.push(row.clone());
+        Ok(())
+    })?;
+
+    // build all new rows before writing so that a bad value does not leave the update half done.
+    let mut updates = vec![];
+    for old_row in old_rows {
+        let new_row = assign(schema, assignments.iter().cloned(), &old_row)?;
+        updates.push((old_row, new_row));
+    }
+
+    write_updates(db.pager(), &table, updates)
+}
+
+// positions of assigned columns with their expressions.
+fn resolve_assignments<'a>(schema: &schema::Schema, assignments: &'a [ast::Assignment]) -> Result<Vec<(usize, &'a ast::Expr)>, error::Error>
+{
+    let mut resolved: Vec<(usize, &ast::Expr)> = vec![];
+    for assignment in assignments {
+        let pos = schema.column_position(&assignment.column)?;
+        if resolved.iter().any(|&(p, _)| p == pos) {
+            return Err(error::Error::Other(format!("Column '{}' is assigned more than once", assignment.column)))
+        }
+        resolved.push((pos, &assignment.value));
+    }
+    Ok(resolved)
+}
+
+// new row with assigned columns evaluated against old row and cast to their column types.
+fn assign<'a, I>(schema: &schema::Schema, assignments: I, old_row: &row::Row) -> Result<row::Row, error::Error>
+    where I: Iterator<Item = (usize, &'a ast::Expr)>
+{
+    let mut values = old_row.values().to_vec();
+    for (pos, expr) in assignments {
+        values[pos] = expr::evaluate(expr, schema, old_row)?.cast(&schema.columns()[pos])?;
+    }
+    Ok(row::Row::new(values))
+}
+
+// writes (old row, new row) of updated rows and updates their index entries.
+fn write_updates(pager: &mut pager::Pager, table: &table::Table, updates: Vec<(row::Row, row::Row)>) -> Result<(), error::Error>
+{
+    let schema = table.schema();
+    // (old key, new key, new row) ; all rows are checked before anything is written.
+    let mut keyed_updates = vec![];
+    for (old_row, new_row) in updates.iter() {
+        check_not_null(table, new_row)?;
+        row::validate_row(schema, new_row)?;
+        keyed_updates.push((old_row.key(schema)?, new_row.key(schema)?, new_row));
+    }
+
+    // rows with a new key move to another place in the tree ; others are rewritten in their cell.
+    let (moved, in_place): (Vec<_>, Vec<_>) = keyed_updates.into_iter().partition(|(old_key, new_key, _)| old_key != new_key);
+    check_moved_keys(pager, table, &moved)?;
+    check_unique_updates(pager, table, &updates)?;
+
+    for (old_key, _, _) in moved.iter() {
+        remove_row_cell(pager, table, old_key)?;
+    }
+    for (_, _, new_row) in moved {
+        insert_row(pager, table, new_row)?;
+    }
+
+    // new overflow pages are written before old ones are freed, so that a failed write leaves the old row.
+    let max_row_size = table.max_row_size(pager)?;
+    for (key, _, new_row) in in_place {
+        let old_serialized = serialized_row(pager, table, &key)?;
+        let serialized = row::serialize_row(pager, schema, new_row, max_row_size)?;
+        row::free_overflow(pager, schema, &old_serialized)?;
+        cursor::Cursor::table_find(pager, table, &key)?.replace_value(serialized)?;
+    }
+
+    // old entries of all rows go first ; a new entry can be same as the old entry of another row.
+    for index in table.indexes() {
+        let pos = index.column_pos();
+        let changed: Vec<_> = updates.iter()
+            .filter(|(old_row, new_row)| old_row.values()[pos] != new_row.values()[pos] || old_row.key(schema) != new_row.key(schema))
+            .collect();
+        for (old_row, _) in changed.iter() {
+            index.remove(pager, schema, old_row)?;
+        }
+        for (_, new_row) in changed.iter() {
+            index.add(pager, schema, new_row)?;
+        }
+    }
+
+    Ok(())
+}
+
+// new keys should not collide with each other or with keys of rows which stay in the table.
+fn check_moved_keys(pager: &mut pager::Pager, table: &table::Table, moved: &[(Vec<u8>, Vec<u8>, &row::Row)]) -> Result<(), error::Error>
+{
+    for (pos, (_, new_key, new_row)) in moved.iter().enumerate() {
+        let moved_away = moved.iter().any(|(k, _, _)| k == new_key);
+        if moved[..pos].iter().any(|(_, k, _)| k == new_key) || (!moved_away && key_exists(pager, table, new_key)?) {
+            return Err(error::Error::key_violation(table.name(), table.schema(), new_row))
+        }
+    }
+    Ok(())
+}
+
+// new values of unique columns should differ from each other and from values of rows which are not updated.
+fn check_unique_updates(pager: &mut pager::Pager, table: &table::Table, updates: &[(row::Row, row::Row)]) -> Result<(), error::Error>
+{
+    let schema = table.schema();
+    let old_keys = updates.iter().map(|(old_row, _)| old_row.key(schema)).collect::<Result<HashSet<_>, String>>()?;
+    for index in table.indexes().iter().filter(|i| i.is_unique()) {
+        let pos = index.column_pos();
+        let mut new_values = HashSet::new();
+        for (old_row, new_row) in updates {
+            let value = &new_row.values()[pos];
+            if *value == row::Value::Null {
+                continue;
+            }
+            let repeated = !new_values.insert(value);
+            let taken = *value != old_row.values()[pos] && keys_with_value(pager, table, index, value)?.iter().any(|k| !old_keys.contains(k));
+            if repeated || taken {
+                return Err(error::Error::unique_violation(table.name(), index.column().name(), value))
+            }
+        }
+    }
+    Ok(())
+}
+
+fn key_exists(pager: &mut pager::Pager, table: &table::Table, key: &[u8]) -> Result<bool, error::Error>
+{
+    cursor::Cursor::table_find(pager, table, key)?.is_at(key)
+}
+
+fn modifiable_table(db: &database::Database, name: &str) -> Result<table::Table, error::Error>
+{
+    let table = db.get_table(name)?;
+    if table.name() == database::CATALOG_TABLE_NAME {
+        return Err(error::Error::Other(format!("Table '{}' can not be modified", table.name())))
+    }
+    Ok(table)
+}
+
+// calls f with rows of table which satisfy where clause in any order.
+fn for_each_matching_row<F>(pager: &mut pager::Pager, table: &table::Table, where_clause: Option<&ast::Expr>, mut f: F) -> Result<(), error::Error>
+    where F: FnMut(&row::Row) -> Result<(), error::Error>
+{
+    scan_matching_rows(pager, table, where_clause, None, |row| f(row).map(|_| true))
+}
+
+// calls f with rows of table which satisfy where clause ; key_order is Some(descending) when rows
+// should come in key order. Scan stops early when f returns false.
+fn scan_matching_rows<F>(pager: &mut pager::Pager, table: &table::Table, where_clause: Option<&ast::Expr>, key_order: Option<bool>, mut f: F) -> Result<(), error::Error>
+    where F: FnMut(&row::Row) -> Result<bool, error::Error>
+{
+    // conditions on first key column only decide where to seek and stop ; all rows are still checked against where clause.
+    if let Some(expr) = where_clause {
+        if expr::contains_aggregate(expr) {
+            return Err(error::Error::Other(format!("Aggregate can not be used in WHERE : {}", expr)))
+        }
+        expr::result_type(expr, table.schema())?;
+    }
+    let range = planner::key_range(where_clause, table.schema());
+    if range.is_empty() {
+        return Ok(())
+    }
+    let selected = |row: &row::Row| match where_clause {
+        Some(expr) => expr::matches(expr, table.schema(), row),
+        None => Ok(true)
+    };
+
+    // an index on a column compared in where clause is used when key columns give no range.
+    if key_order.is_none() && range == planner::KeyRange::full(table.schema().key_size()) {
+        let index_range = table.indexes().iter()
+            .filter_map(|index| planner::column_range(where_clause, table.schema(), index.column_pos()).map(|r| (index, r)))
+            .next();
+        if let Some((index, value_range)) = index_range {
+            for key in index.primary_keys(pager, &value_range)? {
+                let row = find_row(pager, table, &key)?
+                    .ok_or_else(|| format!("Index '{}' refers to missing key {:?} of table '{}'", index.name(), key, table.name()))?;
+                if selected(&row)? && !f(&row)? {
+                    break;
+                }
+            }
+            return Ok(())
+        }
+    }
+
+    let descending = key_order.unwrap_or(false);
+    let mut cursor = if descending {
+        cursor::Cursor::table_seek_back(pager, table, range.end())?
+    } else {
+        cursor::Cursor::table_seek(pager, table, range.start())?
+    };
     while !cursor.end_of_table() {
-        {
-            let serialized = cursor.cursor_value()?;
-            context.select_out(&statement::deserialize_row(serialized.to_vec())?);
+        let key = cursor.cell_key()?;
+        if (descending && &key[..] < range.start()) || (!descending && &key[..] > range.end()) {
+            break;
+        }
+
+        let row = cursor.cursor_row(table.schema())?;
+        if selected(&row)? && !f(&row)? {
+            break;
+        }
+
+        if descending {
+            cursor.retreat_cursor()?
+        } else {
+            cursor.advance_cursor()?
         }
-        cursor.advance_cursor()?
     }
 
     Ok(())
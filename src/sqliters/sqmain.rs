@@ -1,8 +1,8 @@
 use std::io::{self, Write};
-use sqliters::{metacommands, sqlcommands, table, context};
+use sqliters::{metacommands, sqlcommands, database, context, error};
 
 pub fn sq_main() {
-    let mut table = table::Table::new("sqliters.db").expect("Unable to open/create db file.");
+    let mut db = database::Database::new("sqliters.db").expect("Unable to open/create db file.");
     let mut context = context::Context::new(Box::new(context::ConsoleOutFn::new()));
 
     loop
@@ -14,7 +14,7 @@ pub fn sq_main() {
             .read_line(&mut user_command_input)
             .expect("Expecting user input");
 
-        let result = process_command(&mut context, &mut table, user_command_input.as_str());
+        let result = process_command(&mut context, &mut db, user_command_input.as_str());
 
         match result {
             Err(msg) => println!("Error: {}", msg),
@@ -29,36 +29,40 @@ fn print_prompt()
     io::stdout().flush().expect("failed to flust in print_prompt");
 }
 
-fn process_command(context: &mut context::Context, table: &mut table::Table, user_command_input: &str) -> Result<(), String> {
+fn process_command(context: &mut context::Context, db: &mut database::Database, user_command_input: &str) -> Result<(), error::Error> {
     let user_command = user_command_input.trim();
 
     match user_command.chars().next() {
-        Some('.') => metacommands::process_meta_command(table, user_command),
-        Some(_) => sqlcommands::process_sql_command(context, table, user_command),
-        None => Err(String::from("command expected."))
+        Some('.') => Ok(metacommands::process_meta_command(db, user_command)?),
+        Some(_) => sqlcommands::process_sql_command(context, db, user_command),
+        None => Err(error::Error::Other(String::from("command expected.")))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use sqliters::{consts, statement};
+    use sqliters::{consts, page, schema, row, btree, timestamp, statement, replacement, header, cursor};
     use std::{fs, path::Path};
 
+    // rows of users table which take several pages.
+    const TEST_ROWS: usize = 153;
+
     #[test]
     fn test_1_insert_select()
     {
         let db_filename = "test1.db";
         test_setup(db_filename);
 
-        let mut table = table::Table::new(db_filename).expect("Unable to create/open db file.");
-        let commands = ["insert 1 ashishnegi abc@abc.com", "select"];
+        let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+        create_users_table(&mut db);
+        let commands = ["insert into users values (1, 'ashishnegi', 'abc@abc.com')", "select * from users"];
         let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
 
         for command in commands.iter() {
-            process_command(&mut context, &mut table, command).expect(format!("Failed at command '{}'", command).as_str());
+            process_command(&mut context, &mut db, command).expect(format!("Failed at command '{}'", command).as_str());
         }
-        table.delete_db().expect("Unable to delete test db");
+        db.delete_db().expect("Unable to delete test db");
     }
 
     #[test]
@@ -67,18 +71,19 @@ mod tests {
         let db_filename = "test_1_page.db";
         test_setup(db_filename);
 
-        let mut table = table::Table::new(db_filename).expect("Unable to create/open db file.");
-        let commands: Vec<String> =  (1 .. consts::CELLS_PER_PAGE)
-            .map(|s| format!("insert {} ashishnegi abc@abc.com", s))
+        let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+        create_users_table(&mut db);
+        let commands: Vec<String> =  (1 .. default_cells_per_page())
+            .map(|s| format!("insert into users values ({}, 'ashishnegi', 'abc@abc.com')", s))
             .collect::<Vec<String>>();
         let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
 
         for command in commands.iter() {
-            process_command(&mut context, &mut table, command).expect(format!("Failed at command '{}'", command).as_str());
+            process_command(&mut context, &mut db, command).expect(format!("Failed at command '{}'", command).as_str());
         }
 
-        assert!(process_command(&mut context, &mut table, "select").is_ok(), "select should always work");
-        table.delete_db().expect("Unable to delete test db");
+        assert!(process_command(&mut context, &mut db, "select * from users").is_ok(), "select should always work");
+        db.delete_db().expect("Unable to delete test db");
     }
 
     #[test]
@@ -87,25 +92,26 @@ mod tests {
         let db_filename = "test2.db";
         test_setup(db_filename);
 
-        let mut table = table::Table::new(db_filename).expect("Unable to create/open db file.");
+        let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+        create_users_table(&mut db);
         let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
-        let mut commands: Vec<String> =  (1 .. consts::TABLE_MAX_ROWS)
-            .map(|s| format!("insert {} ashishnegi abc@abc.com", s))
+        let mut commands: Vec<String> =  (1 .. TEST_ROWS)
+            .map(|s| format!("insert into users values ({}, 'ashishnegi', 'abc@abc.com')", s))
             .collect::<Vec<String>>();
-        commands.push(String::from("select"));
+        commands.push(String::from("select * from users"));
 
         for command in commands.iter() {
-            process_command(&mut context, &mut table, command)
-                .expect(format!("Failed at command '{}' : table : {} \r\n : {:?} ", command, table.print(), table).as_str());
+            process_command(&mut context, &mut db, command)
+                .expect(format!("Failed at command '{}' : table : {} \r\n : {:?} ", command, db.print(), db).as_str());
         }
         // make sure that select saw all the rows.
         if let Some(foo) = context.get_out().downcast_ref::<AssertSelectOutFn>() {
-            assert!(foo.count() as usize == consts::TABLE_MAX_ROWS,
+            assert!(foo.count() as usize == TEST_ROWS,
                 "Should be able to see all data written {}", foo.count());
         } else {
             assert!(true, "Failed to get AssertSelectOutFn out of context");
         }
-        table.delete_db().expect("Unable to delete test db");
+        db.delete_db().expect("Unable to delete test db");
     }
 
     #[test]
@@ -114,25 +120,26 @@ mod tests {
         let db_filename = "test3.db";
         test_setup(db_filename);
 
-        let mut table = table::Table::new(db_filename).expect("Unable to create/open db file.");
-        let commands: Vec<String> = (1 .. consts::TABLE_MAX_ROWS)
-            .map(|s| format!("insert {} ashishnegi abc@abc.com", s))
+        let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+        create_users_table(&mut db);
+        let commands: Vec<String> = (1 .. TEST_ROWS)
+            .map(|s| format!("insert into users values ({}, 'ashishnegi', 'abc@abc.com')", s))
             .collect::<Vec<String>>();
         let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
 
         for command in commands.iter() {
-            process_command(&mut context, &mut table, command).expect(format!("Failed at command '{}', table {:?}", command, table).as_str());
+            process_command(&mut context, &mut db, command).expect(format!("Failed at command '{}', table {:?}", command, db).as_str());
         }
-        assert!(process_command(&mut context, &mut table, "insert 2 abc abc@bcd.com").is_err(), "should not be able to insert more data");
-        assert!(process_command(&mut context, &mut table, "select").is_ok(), "select should always work");
+        assert!(process_command(&mut context, &mut db, "insert into users values (2, 'abc', 'abc@bcd.com')").is_err(), "should not be able to insert a duplicate key");
+        assert!(process_command(&mut context, &mut db, "select * from users").is_ok(), "select should always work");
         // make sure that select saw all the rows.
         if let Some(foo) = context.get_out().downcast_ref::<AssertSelectOutFn>() {
-            assert!(foo.count() as usize == consts::TABLE_MAX_ROWS,
+            assert!(foo.count() as usize == TEST_ROWS,
                 "Should be able to see all data written {}", foo.count());
         } else {
             assert!(true, "Failed to get AssertSelectOutFn out of context");
         }
-        table.delete_db().expect("Unable to delete test db");
+        db.delete_db().expect("Unable to delete test db");
     }
 
     #[test]
@@ -141,30 +148,31 @@ mod tests {
         let db_filename = "test4.db";
         test_setup(db_filename);
 
-        let mut table = table::Table::new(db_filename).expect("Unable to create/open db file.");
-        let commands: Vec<String> =  (1 .. consts::TABLE_MAX_ROWS)
+        let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+        create_users_table(&mut db);
+        let commands: Vec<String> =  (1 .. TEST_ROWS)
             .rev()
-            .map(|s| format!("insert {} ashishnegi abc@abc.com", s))
+            .map(|s| format!("insert into users values ({}, 'ashishnegi', 'abc@abc.com')", s))
             .collect::<Vec<String>>();
         let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
 
         for command in commands.iter() {
-            process_command(&mut context, &mut table, command).expect(format!("Failed at command '{}', table {:?}", command, table).as_str());
+            process_command(&mut context, &mut db, command).expect(format!("Failed at command '{}', table {:?}", command, db).as_str());
         }
 
-        table.print();
+        db.print();
 
-        assert!(process_command(&mut context, &mut table, "select").is_ok(), "select should always work");
+        assert!(process_command(&mut context, &mut db, "select * from users").is_ok(), "select should always work");
 
         // make sure that select saw all the rows.
         if let Some(foo) = context.get_out().downcast_ref::<AssertSelectOutFn>() {
-            assert!(foo.count() as usize == consts::TABLE_MAX_ROWS,
+            assert!(foo.count() as usize == TEST_ROWS,
                 "Should be able to see all data written");
         } else {
             assert!(true, "Failed to get AssertSelectOutFn out of context");
         }
 
-        table.delete_db().expect("Unable to delete test db");
+        db.delete_db().expect("Unable to delete test db");
     }
 
     #[test]
@@ -174,44 +182,1613 @@ mod tests {
         test_setup(db_filename);
 
         {
-            let mut table = table::Table::new(db_filename).expect("Unable to create/open db file.");
-            let commands: Vec<String> =  (1 .. consts::TABLE_MAX_ROWS)
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            create_users_table(&mut db);
+            let commands: Vec<String> =  (1 .. TEST_ROWS)
                 .rev()
-                .map(|s| format!("insert {} ashishnegi abc@abc.com", s))
+                .map(|s| format!("insert into users values ({}, 'ashishnegi', 'abc@abc.com')", s))
                 .collect::<Vec<String>>();
             let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
 
             for command in commands.iter() {
-                process_command(&mut context, &mut table, command).expect(format!("Failed at command '{}', table {:?}", command, table).as_str());
+                process_command(&mut context, &mut db, command).expect(format!("Failed at command '{}', table {:?}", command, db).as_str());
             }
 
-            assert!(process_command(&mut context, &mut table, "select").is_ok(), "select should always work");
+            assert!(process_command(&mut context, &mut db, "select * from users").is_ok(), "select should always work");
             if let Some(foo) = context.get_out().downcast_ref::<AssertSelectOutFn>() {
-                assert!(foo.count() as usize == consts::TABLE_MAX_ROWS,
+                assert!(foo.count() as usize == TEST_ROWS,
                     "Should be able to see all data written");
             } else {
                 assert!(false, "Failed to get AssertSelectOutFn out of context");
             }
 
-            table.print();
+            db.print();
         }
 
         {
-            let mut table = table::Table::new(db_filename).expect("Unable to create/open db file.");
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
             let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
 
-            assert!(process_command(&mut context, &mut table, "select").is_ok(), "select should always work");
+            assert!(process_command(&mut context, &mut db, "select * from users").is_ok(), "select should always work");
 
             if let Some(foo) = context.get_out().downcast_ref::<AssertSelectOutFn>() {
-                assert!(foo.count() as usize == consts::TABLE_MAX_ROWS,
+                assert!(foo.count() as usize == TEST_ROWS,
                     "Should be able to see all previous data written after opening file again");
             } else {
                 assert!(false, "Failed to get AssertSelectOutFn out of context");
             }
 
-            table.delete_db().expect("Unable to delete test db");
         }
-        // add duplicate keys and see that it overrides the old one.
+
+        {
+            // duplicate keys override the old row with insert or replace.
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            assert!(process_command(&mut context, &mut db, "insert into users values (7, 'duplicate', 'dup@abc.com')").is_err(),
+                "plain insert should not override");
+            process_command(&mut context, &mut db, "insert or replace into users values (7, 'replaced', 'new@abc.com')")
+                .expect("insert or replace should override");
+        }
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let rows = select_rows(&mut db, "select * from users where id between 6 and 8");
+            assert!(rows.len() == 3, "rows {:?}", rows);
+            assert!(rows[1].get(1) == Some(&row::Value::Text(String::from("replaced"))), "rows {:?}", rows);
+            assert!(select_rows(&mut db, "select * from users").len() == TEST_ROWS - 1, "replace should not add a row");
+
+            db.delete_db().expect("Unable to delete test db");
+        }
+    }
+
+    #[test]
+    fn test_create_table_insert_select()
+    {
+        let db_filename = "test_create_table.db";
+        test_setup(db_filename);
+
+        let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+        let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+        let commands = [
+            "create table orders (item varchar(8), id integer primary key, quantity int, note text(100))",
+            "insert into orders values ('pen', 2, 10, 'blue')",
+            "insert into orders (note, quantity, id, item) values ('paperback', 3, 1, 'book')",
+            "INSERT INTO orders VALUES ('ink', 3, 7, 'black')"];
+
+        for command in commands.iter() {
+            process_command(&mut context, &mut db, command).expect(format!("Failed at command '{}'", command).as_str());
+        }
+
+        let table = db.get_table("orders").expect("orders table should be present");
+        assert!(table.schema().columns().len() == 4, "schema should come from create table");
+        assert!(table.schema().key_columns() == [1], "id should be the key column");
+
+        let rows = select_rows(&mut db, "select * from orders");
+        assert!(rows.len() == 3, "Should see all rows : {:?}", rows);
+        assert!(rows[0] == row::Row::new(vec![row::Value::Text(String::from("book")), row::Value::Integer(1), row::Value::Integer(3), row::Value::Text(String::from("paperback"))]),
+            "rows should be sorted on key column : {:?}", rows);
+        assert!(rows[2].get(0) == Some(&row::Value::Text(String::from("ink"))), "rows should be sorted on key column : {:?}", rows);
+
+        db.delete_db().expect("Unable to delete test db");
+    }
+
+    #[test]
+    fn test_create_table_errors()
+    {
+        let db_filename = "test_create_table_errors.db";
+        test_setup(db_filename);
+
+        let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+        let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+
+        assert!(process_command(&mut context, &mut db, "create table t (name text primary key)").is_err(), "key should have a fixed size");
+        assert!(process_command(&mut context, &mut db, "create table t (id integer, id text(8))").is_err(), "column names should be unique");
+        assert!(process_command(&mut context, &mut db, "create table t (id blobs)").is_err(), "unknown type");
+        assert!(process_command(&mut context, &mut db, "create table t id integer").is_err(), "columns should be in parentheses");
+        assert!(process_command(&mut context, &mut db, "create table t (id integer, name text(4000))").is_err(), "atleast 2 rows should fit in a page");
+
+        process_command(&mut context, &mut db, "create table t (id integer, name text(4))").expect("create table should work");
+        assert!(process_command(&mut context, &mut db, "create table T (id integer)").is_err(), "table is already created");
+        assert!(process_command(&mut context, &mut db, "create table sqliters_master (id integer)").is_err(), "catalog table is already present");
+        assert!(process_command(&mut context, &mut db, "insert into t values (1)").is_err(), "values should match number of columns");
+        assert!(process_command(&mut context, &mut db, "insert into t values ('one', 'abc')").is_err(), "id should be an integer");
+        assert!(process_command(&mut context, &mut db, "insert into t values (1, 'abcde')").is_err(), "name can be maximum of 4 bytes");
+        assert!(process_command(&mut context, &mut db, "insert into t2 values (1, 'abcd')").is_err(), "no such table");
+        assert!(process_command(&mut context, &mut db, "select * from t2").is_err(), "no such table");
+        assert!(process_command(&mut context, &mut db, "insert into sqliters_master values (1, 't', 1, 'abcd')").is_err(), "catalog can't be modified");
+        process_command(&mut context, &mut db, "insert into t values (1, 'abcd')").expect("insert should work");
+
+        db.delete_db().expect("Unable to delete test db");
+    }
+
+    #[test]
+    fn test_multiple_tables_durability()
+    {
+        let db_filename = "test_multiple_tables.db";
+        test_setup(db_filename);
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            let mut commands = vec![
+                String::from("create table users (id integer, username text(32), email text(32))"),
+                String::from("create table items (name text(16), id integer primary key)")];
+            // interleave inserts so that pages of both tables are mixed in the file.
+            for i in 1 .. 60 {
+                commands.push(format!("insert into users values ({}, 'ashishnegi', 'abc@abc.com')", i));
+                commands.push(format!("insert into items values ('item{}', {})", i, 100 - i));
+            }
+
+            for command in commands.iter() {
+                process_command(&mut context, &mut db, command).expect(format!("Failed at command '{}'", command).as_str());
+            }
+        }
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            assert!(db.tables().len() == 2, "Both tables should be loaded from catalog");
+
+            let users = select_rows(&mut db, "select * from users");
+            assert!(users.len() == 59, "Should see all users after reopen : {}", users.len());
+            assert!(users[0].get(0) == Some(&row::Value::Integer(1)), "users should be sorted : {:?}", users[0]);
+
+            let items = select_rows(&mut db, "select * from items");
+            assert!(items.len() == 59, "Should see all items after reopen : {}", items.len());
+            assert!(items[0] == row::Row::new(vec![row::Value::Text(String::from("item59")), row::Value::Integer(41)]),
+                "items should be sorted on id : {:?}", items[0]);
+
+            let catalog = select_rows(&mut db, "select * from sqliters_master");
+            assert!(catalog.len() == 2, "catalog should have one row per table : {:?}", catalog);
+            assert!(catalog[1].get(1) == Some(&row::Value::Text(String::from("items"))), "catalog should have table name : {:?}", catalog[1]);
+
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            process_command(&mut context, &mut db, "create table more (id integer)").expect("create table after reopen should work");
+            process_command(&mut context, &mut db, "insert into more values (1)").expect("insert should work");
+            assert!(select_rows(&mut db, "select * from more").len() == 1, "new table should have its own tree");
+            assert!(select_rows(&mut db, "select * from items").len() == 59, "new table should not change items");
+
+            db.delete_db().expect("Unable to delete test db");
+        }
+    }
+
+    #[test]
+    fn test_quoted_values_and_identifiers()
+    {
+        let db_filename = "test_quoted_values.db";
+        test_setup(db_filename);
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            let commands = [
+                "CREATE TABLE notes (\"key\" integer primary key, \"from\" text(8), body text(64));",
+                "insert into notes values (2, 'b', 'Select, from ( values )'), (1, 'a', 'it''s')"];
+
+            for command in commands.iter() {
+                process_command(&mut context, &mut db, command).expect(format!("Failed at command '{}'", command).as_str());
+            }
+
+            let error = process_command(&mut context, &mut db, "insert into notes values (3, 'c' 'd')").expect_err("missing comma");
+            assert!(error.to_string().contains("line 1, column 34"), "error should point to position : {}", error);
+            assert!(process_command(&mut context, &mut db, "insert into notes (\"from\", body) values ('c', 'd')").is_err(), "key column has no value");
+            assert!(process_command(&mut context, &mut db, "insert into notes values (3, 'c', 'd'), (4, 'e')").is_err(), "second row has less values");
+        }
+
+        {
+            // column names which are keywords should be read back from catalog.
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let rows = select_rows(&mut db, "select * from notes");
+            assert!(rows.len() == 3, "rows {:?}", rows);
+            assert!(rows[0] == row::Row::new(vec![row::Value::Integer(1), row::Value::Text(String::from("a")), row::Value::Text(String::from("it's"))]),
+                "rows {:?}", rows);
+            assert!(rows[1].get(2) == Some(&row::Value::Text(String::from("Select, from ( values )"))), "rows {:?}", rows);
+
+            db.delete_db().expect("Unable to delete test db");
+        }
+    }
+
+    #[test]
+    fn test_where_select()
+    {
+        let db_filename = "test_where_select.db";
+        test_setup(db_filename);
+
+        let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+        create_users_table(&mut db);
+        // spread rows over several leaves so that seeks have to go through internal nodes.
+        let num_rows = 3 * default_cells_per_page() as i32;
+        {
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            for i in (1 .. num_rows + 1).rev() {
+                let command = format!("insert into users values ({}, 'user{}', 'user{}@abc.com')", i, i % 5, i);
+                process_command(&mut context, &mut db, &command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+            }
+        }
+
+        let cases: Vec<(String, Vec<i32>)> = vec![
+            (String::from("id = 40"), vec![40]),
+            (String::from("id = 0"), vec![]),
+            (String::from("id between 27 and 31"), (27 .. 32).collect()),
+            (format!("id > {}", num_rows - 3), vec![num_rows - 2, num_rows - 1, num_rows]),
+            (String::from("3 >= id"), vec![1, 2, 3]),
+            (String::from("id >= 50 and id < 53 and id != 51"), vec![50, 52]),
+            (String::from("id < 20 and username = 'user2'"), vec![2, 7, 12, 17]),
+            (String::from("email = 'user33@abc.com'"), vec![33]),
+            (String::from("id = 4 or id = 60"), vec![4, 60]),
+            (String::from("id not between 3 and 100 and id < 10"), vec![1, 2]),
+            (String::from("id > 10 and id < 5"), vec![]),
+            (String::from("id > 2147483647"), vec![])
+        ];
+        for (where_clause, expected) in cases.iter() {
+            let actual = select_ids(&mut db, &format!("select * from users where {}", where_clause));
+            assert!(actual == *expected, "where {} : {:?} != {:?}", where_clause, actual, expected);
+        }
+
+        let mut context = context::Context::new(Box::new(CollectSelectOutFn::new()));
+        assert!(process_command(&mut context, &mut db, "select * from users where username = 5").is_err(), "text compared with integer");
+        assert!(process_command(&mut context, &mut db, "select * from users where age > 5").is_err(), "unknown column");
+        assert!(process_command(&mut context, &mut db, "select * from users where id >").is_err(), "incomplete where");
+
+        db.delete_db().expect("Unable to delete test db");
+    }
+
+    #[test]
+    fn test_delete_rebalance()
+    {
+        let db_filename = "test_delete_rebalance.db";
+        test_setup(db_filename);
+
+        let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+        create_users_table(&mut db);
+        let root_page_num = db.get_table("users").expect("users table should exist").root_page_num();
+        let num_rows = 100;
+        let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+
+        // freed pages are reused ; otherwise db grows in every round.
+        for round in 0 .. 3 {
+            for i in 1 .. num_rows + 1 {
+                let command = format!("insert into users values ({}, 'ashishnegi', 'abc@abc.com')", i);
+                process_command(&mut context, &mut db, &command).unwrap_or_else(|e| panic!("Failed at command '{}' in round {} : {}", command, round, e));
+            }
+            btree::validate(db.pager(), root_page_num).expect("tree should be valid after inserts");
+
+            // 37 and 100 are co-prime ; so this deletes every key once in a scattered order.
+            let mut remaining: Vec<i32> = (1 .. num_rows + 1).collect();
+            for i in 0 .. num_rows {
+                let key = (i * 37) % num_rows + 1;
+                let command = format!("delete from users where id = {}", key);
+                process_command(&mut context, &mut db, &command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+                remaining.retain(|&k| k != key);
+
+                btree::validate(db.pager(), root_page_num).unwrap_or_else(|e| panic!("tree should be valid after deleting {} : {}", key, e));
+                if i % 10 == 0 {
+                    assert!(select_ids(&mut db, "select * from users") == remaining, "after deleting {} in round {}", key, round);
+                }
+            }
+            assert!(select_ids(&mut db, "select * from users").is_empty(), "all rows should be deleted in round {}", round);
+        }
+
+        db.delete_db().expect("Unable to delete test db");
+    }
+
+    #[test]
+    fn test_delete_where_durability()
+    {
+        let db_filename = "test_delete_where.db";
+        test_setup(db_filename);
+
+        let num_pages = {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            create_users_table(&mut db);
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            for i in 1 .. 81 {
+                let command = format!("insert into users values ({}, 'user{}', 'abc@abc.com')", i, i % 2);
+                process_command(&mut context, &mut db, &command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+            }
+
+            let commands = [
+                "delete from users where id between 11 and 70",
+                "delete from users where username = 'user1' and id > 75",
+                "delete from users where id = 1000"];
+            for command in commands.iter() {
+                process_command(&mut context, &mut db, command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+            }
+
+            assert!(process_command(&mut context, &mut db, "delete from sqliters_master").is_err(), "catalog can not be modified");
+            assert!(process_command(&mut context, &mut db, "delete from users where age = 1").is_err(), "unknown column");
+            db.pager().num_pages()
+        };
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let expected: Vec<i32> = (1 .. 11).chain(vec![71, 72, 73, 74, 75, 76, 78, 80]).collect();
+            assert!(select_ids(&mut db, "select * from users") == expected, "rows left after delete");
+            let root_page_num = db.get_table("users").expect("users table should exist").root_page_num();
+            btree::validate(db.pager(), root_page_num).expect("tree should be valid after reopen");
+
+            // deleted rows can be inserted again in freed pages.
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            for i in 11 .. 71 {
+                let command = format!("insert into users values ({}, 'user', 'abc@abc.com')", i);
+                process_command(&mut context, &mut db, &command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+            }
+            assert!(db.pager().num_pages() == num_pages, "pages {} should be reused : {}", num_pages, db.pager().num_pages());
+
+            process_command(&mut context, &mut db, "delete from users").expect("delete all should work");
+            assert!(select_ids(&mut db, "select * from users").is_empty(), "all rows should be deleted");
+
+            db.delete_db().expect("Unable to delete test db");
+        }
+    }
+
+    #[test]
+    fn test_update()
+    {
+        let db_filename = "test_update.db";
+        test_setup(db_filename);
+
+        let user = |id: i32, username: &str, email: &str| row::Row::new(vec![
+            row::Value::Integer(id), row::Value::Text(username.to_string()), row::Value::Text(email.to_string())]);
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            create_users_table(&mut db);
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            for i in 1 .. 61 {
+                let command = format!("insert into users values ({}, 'user{}', 'abc@abc.com')", i, i);
+                process_command(&mut context, &mut db, &command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+            }
+
+            let commands = [
+                "update users set email = 'new@abc.com', username = 'updated' where id between 10 and 39",
+                "update users set username = 'last' where id = 60",
+                // key changes move rows ; 55 .. 59 become 56 .. 60 after 60 moves away.
+                "update users set id = id * 100 where id = 60",
+                "update users set id = id + 1 where id >= 55 and id < 60",
+                "update users set id = 0 - id where id < 3",
+                "update users set username = 'none' where id = 1000"];
+            for command in commands.iter() {
+                process_command(&mut context, &mut db, command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+            }
+
+            let errors = [
+                ("update users set id = 5 where id = 6", "key 5 is present"),
+                ("update users set id = 7 where id between 6 and 7", "6 and 7 both get key 7"),
+                ("update users set username = 5 where id = 6", "integer in text column"),
+                ("update users set username = 'abcdefghijklmnopqrstuvwxyz0123456789' where id = 6", "text too long"),
+                ("update users set id = id / 0 where id = 6", "division by zero"),
+                ("update users set age = 1", "unknown column"),
+                ("update users set username = 'a', username = 'b'", "column assigned twice"),
+                ("update sqliters_master set name = 'a'", "catalog can not be modified")];
+            for &(command, reason) in errors.iter() {
+                assert!(process_command(&mut context, &mut db, command).is_err(), "'{}' should fail : {}", command, reason);
+            }
+            let root_page_num = db.get_table("users").expect("users table should exist").root_page_num();
+            btree::validate(db.pager(), root_page_num).expect("tree should be valid after updates");
+        }
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let rows = select_rows(&mut db, "select * from users");
+            let ids: Vec<i32> = select_ids(&mut db, "select * from users");
+            let expected_ids: Vec<i32> = vec![-2, -1].into_iter().chain(3 .. 55).chain(56 .. 61).chain(vec![6000]).collect();
+            assert!(ids == expected_ids, "ids {:?}", ids);
+
+            assert!(rows[0] == user(-2, "user2", "abc@abc.com"), "{}", rows[0]);
+            assert!(rows[8] == user(9, "user9", "abc@abc.com"), "{}", rows[8]);
+            assert!(rows[9] == user(10, "updated", "new@abc.com"), "{}", rows[9]);
+            assert!(rows[38] == user(39, "updated", "new@abc.com"), "{}", rows[38]);
+            assert!(rows[39] == user(40, "user40", "abc@abc.com"), "{}", rows[39]);
+            assert!(select_rows(&mut db, "select * from users where id = 56") == vec![user(56, "user55", "abc@abc.com")], "moved row keeps its values");
+            assert!(select_rows(&mut db, "select * from users where id = 6000") == vec![user(6000, "last", "abc@abc.com")], "row moved after update");
+            assert!(select_rows(&mut db, "select * from users where id = 6") == vec![user(6, "user6", "abc@abc.com")], "failed updates change nothing");
+
+            db.delete_db().expect("Unable to delete test db");
+        }
+    }
+
+    #[test]
+    fn test_insert_on_conflict()
+    {
+        let db_filename = "test_insert_on_conflict.db";
+        test_setup(db_filename);
+
+        let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+        let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+        let text = |v: &str| row::Value::Text(v.to_string());
+        let row_of = |db: &mut database::Database, id: i32| -> Vec<row::Value> {
+            let rows = select_rows(db, &format!("select * from counters where id = {}", id));
+            assert!(rows.len() == 1, "row {} : {:?}", id, rows);
+            rows[0].values().to_vec()
+        };
+
+        let commands = [
+            "create table counters (name text(16), id integer primary key, hits integer)",
+            "insert into counters values ('a', 1, 1), ('b', 2, 1), ('c', 3, 1)",
+            "insert or ignore into counters values ('ignored', 1, 100), ('d', 4, 1)",
+            "insert or replace into counters values ('replaced', 2, 50), ('e', 5, 1)",
+            "insert into counters values ('x', 3, 1) on conflict (id) do update set hits = hits + 1, name = excluded.name",
+            "insert into counters values ('y', 3, 5) on conflict do update set hits = hits + excluded.hits",
+            "insert into counters values ('nothing', 4, 1) on conflict do nothing",
+            "insert into counters values ('f', 6, 1) on conflict do update set hits = 0",
+            // same key twice in one statement : second row updates the first.
+            "insert into counters values ('g', 7, 1), ('g', 7, 1) on conflict (ID) do update set hits = hits + 1",
+            // update can move the row to a new key.
+            "insert into counters values ('h', 5, 1) on conflict do update set id = id * 10"];
+        for command in commands.iter() {
+            process_command(&mut context, &mut db, command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+        }
+
+        assert!(row_of(&mut db, 1) == vec![text("a"), row::Value::Integer(1), row::Value::Integer(1)], "ignored");
+        assert!(row_of(&mut db, 2) == vec![text("replaced"), row::Value::Integer(2), row::Value::Integer(50)], "replaced");
+        assert!(row_of(&mut db, 3) == vec![text("x"), row::Value::Integer(3), row::Value::Integer(7)], "updated twice");
+        assert!(row_of(&mut db, 4) == vec![text("d"), row::Value::Integer(4), row::Value::Integer(1)], "do nothing");
+        assert!(row_of(&mut db, 6) == vec![text("f"), row::Value::Integer(6), row::Value::Integer(1)], "no conflict inserts");
+        assert!(row_of(&mut db, 7) == vec![text("g"), row::Value::Integer(7), row::Value::Integer(2)], "duplicate in same insert");
+        assert!(row_of(&mut db, 50) == vec![text("e"), row::Value::Integer(50), row::Value::Integer(1)], "moved by update");
+        assert!(select_ids_of(&mut db, "select * from counters", 1) == vec![1, 2, 3, 4, 6, 7, 50], "all keys");
+
+        let errors = [
+            ("insert into counters values ('z', 1, 1)", "duplicate key"),
+            ("insert into counters values ('z', 1, 1) on conflict (hits) do nothing", "conflict target is not the key"),
+            ("insert into counters values ('z', 1, 1) on conflict do update set id = 2", "updated key is present"),
+            ("insert into counters values ('z', 1, 1) on conflict do update set hits = excluded.name", "text in integer column"),
+            ("update counters set hits = excluded.hits", "excluded outside of upsert")];
+        for &(command, reason) in errors.iter() {
+            assert!(process_command(&mut context, &mut db, command).is_err(), "'{}' should fail : {}", command, reason);
+        }
+        assert!(row_of(&mut db, 1) == vec![text("a"), row::Value::Integer(1), row::Value::Integer(1)], "failed upserts change nothing");
+
+        db.delete_db().expect("Unable to delete test db");
+    }
+
+    #[test]
+    fn test_select_projection()
+    {
+        let db_filename = "test_select_projection.db";
+        test_setup(db_filename);
+
+        let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+        create_users_table(&mut db);
+        let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+        for id in 1 .. 4 {
+            let command = format!("insert into users values ({}, 'user{}', 'user{}@abc.com')", id, id, id);
+            process_command(&mut context, &mut db, &command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+        }
+
+        let mut context = context::Context::new(Box::new(CollectSelectOutFn::new()));
+        let command = "select username, id * 2 as double_id, id > 1 bigger, 'x', * from users where id >= 2";
+        process_command(&mut context, &mut db, command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+        let out = context.get_out().downcast_ref::<CollectSelectOutFn>().expect("Failed to get CollectSelectOutFn out of context");
+
+        let columns: Vec<String> = out.columns().iter().map(|c| format!("{} {}", c.name(), c.column_type())).collect();
+        let expected_columns = ["username text(32)", "double_id integer", "bigger boolean", "'x' text(1)",
+                                "id integer", "username text(32)", "email text(32)"];
+        assert!(columns == expected_columns, "{:?}", columns);
+
+        let text = |v: &str| row::Value::Text(v.to_string());
+        let rows: Vec<Vec<row::Value>> = out.rows().iter().map(|r| r.values().to_vec()).collect();
+        let expected_rows = vec![
+            vec![text("user2"), row::Value::Integer(4), row::Value::Boolean(true), text("x"), row::Value::Integer(2), text("user2"), text("user2@abc.com")],
+            vec![text("user3"), row::Value::Integer(6), row::Value::Boolean(true), text("x"), row::Value::Integer(3), text("user3"), text("user3@abc.com")]];
+        assert!(rows == expected_rows, "{:?}", rows);
+
+        let errors = [
+            ("select age from users", "unknown column"),
+            ("select username * 2 from users", "arithmetic on text"),
+            ("select id = username from users", "integer compared with text"),
+            ("select id from users where id = 'a'", "where is type checked on empty range"),
+            ("select id / 0 from users", "division by zero")];
+        for &(command, reason) in errors.iter() {
+            assert!(process_command(&mut context, &mut db, command).is_err(), "'{}' should fail : {}", command, reason);
+        }
+
+        db.delete_db().expect("Unable to delete test db");
+    }
+
+    #[test]
+    fn test_order_by_limit()
+    {
+        let db_filename = "test_order_by_limit.db";
+        test_setup(db_filename);
+
+        let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+        create_users_table(&mut db);
+        let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+        let name_of = |id: i32| format!("user{}", (id * 7) % 13);
+        // ids are inserted out of order and some are deleted so that rows span many leaves.
+        for i in 0 .. 140 {
+            let id = (i * 53) % 140;
+            let command = format!("insert into users values ({}, '{}', 'user{}@example.com')", id, name_of(id), id);
+            process_command(&mut context, &mut db, &command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+        }
+        process_command(&mut context, &mut db, "delete from users where id between 30 and 59 or id > 130").expect("delete should succeed");
+        let ids: Vec<i32> = (0 .. 131).filter(|id| *id < 30 || *id > 59).collect();
+
+        let reversed: Vec<i32> = ids.iter().rev().cloned().collect();
+        assert!(select_ids(&mut db, "select * from users order by id desc") == reversed, "descending scan");
+        assert!(select_ids(&mut db, "select * from users order by id asc") == ids, "ascending scan");
+        assert!(select_ids(&mut db, "select * from users where id >= 20 and id < 70 order by id desc") ==
+                vec![69, 68, 67, 66, 65, 64, 63, 62, 61, 60, 29, 28, 27, 26, 25, 24, 23, 22, 21, 20], "descending range");
+        assert!(select_ids(&mut db, "select * from users where id > 1000 order by id desc").is_empty(), "empty descending range");
+        assert!(select_ids(&mut db, "select * from users order by id desc limit 3 offset 2") == vec![128, 127, 126], "descending limit");
+        assert!(select_ids(&mut db, "select * from users where username = 'user0' limit 2") == vec![0, 13], "limit with filter");
+        assert!(select_ids(&mut db, "select * from users limit 0").is_empty(), "limit 0");
+        assert!(select_ids(&mut db, "select * from users limit 5 offset 1000").is_empty(), "offset past end");
+
+        // non key order is sorted ; rows with equal names stay in id order.
+        let mut by_name = ids.clone();
+        by_name.sort_by_key(|id| ::std::cmp::Reverse(name_of(*id)));
+        assert!(select_ids(&mut db, "select * from users order by username desc") == by_name, "sort by name");
+        assert!(select_ids(&mut db, "select * from users order by username desc limit 4 offset 10") == by_name[10 .. 14].to_vec(), "sorted limit");
+
+        let mut by_name_then_id = ids.clone();
+        by_name_then_id.sort_by(|a, b| name_of(*a).cmp(&name_of(*b)).then(b.cmp(a)));
+        assert!(select_ids(&mut db, "select * from users order by username, id desc") == by_name_then_id, "sort by name and id");
+
+        let mut by_expr = ids.clone();
+        by_expr.sort_by_key(|id| -(id / 10));
+        assert!(select_ids(&mut db, "select id, 0 - id / 10 as bucket from users order by bucket") == by_expr, "sort by alias");
+
+        let errors = [
+            ("select * from users order by age", "unknown column"),
+            ("select * from users order by username = 1", "type mismatch"),
+            ("select * from users order by id / 0", "division by zero")];
+        for &(command, reason) in errors.iter() {
+            assert!(process_command(&mut context, &mut db, command).is_err(), "'{}' should fail : {}", command, reason);
+        }
+
+        db.delete_db().expect("Unable to delete test db");
+    }
+
+    #[test]
+    fn test_aggregates()
+    {
+        let db_filename = "test_aggregates.db";
+        test_setup(db_filename);
+
+        let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+        create_users_table(&mut db);
+        let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+        for id in 1 .. 121 {
+            let command = format!("insert into users values ({}, 'user{}', 'user{}@abc.com')", id, id % 5, id);
+            process_command(&mut context, &mut db, &command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+        }
+        process_command(&mut context, &mut db, "delete from users where id > 100 or id between 10 and 19").expect("delete should succeed");
+        let ids: Vec<i32> = (1 .. 101).filter(|id| *id < 10 || *id > 19).collect();
+
+        let int = row::Value::Integer;
+        let big = row::Value::BigInt;
+        let text = |v: &str| row::Value::Text(v.to_string());
+        let values = |db: &mut database::Database, command: &str| -> Vec<Vec<row::Value>> {
+            select_rows(db, command).iter().map(|r| r.values().to_vec()).collect()
+        };
+
+        let sum: i64 = ids.iter().map(|&id| i64::from(id)).sum();
+        let len = ids.len() as i64;
+        assert!(values(&mut db, "select count(*), min(id), max(id), sum(id), avg(id) from users") ==
+                vec![vec![big(len), int(1), int(100), big(sum), row::Value::Real(sum as f64 / len as f64)]], "whole table");
+        assert!(values(&mut db, "select count(*), min(id), max(id) from users where id > 5 and id < 25") ==
+                vec![vec![big(9), int(6), int(24)]], "key range");
+        assert!(values(&mut db, "select count(id), min(username), max(email), max(id) * 2 + 1 from users where username = 'user3'") ==
+                vec![vec![big(18), text("user3"), text("user98@abc.com"), int(197)]], "filtered");
+        assert!(values(&mut db, "select count(*), sum(id) from users where id > 1000") == vec![vec![big(0), row::Value::Null]], "no rows");
+        assert!(values(&mut db, "select max(id), avg(id) from users where username = 'none'") == vec![vec![row::Value::Null, row::Value::Null]],
+                "max and avg of no rows");
+
+        let mut context = context::Context::new(Box::new(CollectSelectOutFn::new()));
+        process_command(&mut context, &mut db, "select username, count(*), max(id) - min(id) as spread from users group by username")
+            .expect("group by should succeed");
+        let out = context.get_out().downcast_ref::<CollectSelectOutFn>().expect("Failed to get CollectSelectOutFn out of context");
+        let names: Vec<&str> = out.columns().iter().map(|c| c.name()).collect();
+        assert!(names == ["username", "count(*)", "spread"], "{:?}", names);
+        let groups: Vec<Vec<row::Value>> = out.rows().iter().map(|r| r.values().to_vec()).collect();
+        let expected: Vec<Vec<row::Value>> = (0 .. 5).map(|m| {
+            let group: Vec<i32> = ids.iter().cloned().filter(|id| id % 5 == m).collect();
+            vec![text(&format!("user{}", m)), big(group.len() as i64), int(group[group.len() - 1] - group[0])]
+        }).collect();
+        assert!(groups == expected, "{:?}", groups);
+
+        assert!(values(&mut db, "select username, sum(id) as total from users where id < 10 group by username order by total desc limit 2") ==
+                vec![vec![text("user4"), big(13)], vec![text("user3"), big(11)]], "order by aggregate");
+        assert!(values(&mut db, "select id / 50, count(*) from users group by id / 50 order by id / 50 desc") ==
+                vec![vec![int(2), big(1)], vec![int(1), big(50)], vec![int(0), big(39)]], "group by expression");
+        assert!(values(&mut db, "select username, count(*) from users where id > 1000 group by username").is_empty(), "no groups");
+
+        // avg of integers is not rounded and sums of integers go beyond integer.
+        let commands = [
+            "create table amounts (id integer primary key, amount integer)",
+            "insert into amounts values (1, 20), (2, 25), (3, 2)",
+            "insert into amounts values (4, 2147483647), (5, 2147483647)",
+            "insert into amounts values (6, null)"];
+        for command in commands.iter() {
+            process_command(&mut context, &mut db, command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+        }
+        assert!(values(&mut db, "select avg(amount), sum(amount) from amounts where id < 4") == vec![vec![row::Value::Real(47.0 / 3.0), big(47)]], "avg of integers");
+        assert!(values(&mut db, "select sum(amount), count(*), avg(amount) from amounts where id > 3 and id < 6") ==
+                vec![vec![big(4294967294), big(2), row::Value::Real(2147483647.0)]], "sum of integers");
+        assert!(values(&mut db, "select sum(amount), count(amount), avg(amount) from amounts where amount is null") ==
+                vec![vec![row::Value::Null, big(0), row::Value::Null]], "sum of only nulls");
+
+        let errors = [
+            ("select username, count(*) from users", "column not in group by"),
+            ("select * from users group by username", "wildcard with group by"),
+            ("select sum(username) from users", "sum of text"),
+            ("select * from users where count(*) > 1", "aggregate in where"),
+            ("select count(*) from users group by count(*)", "aggregate in group by"),
+            ("select count(max(id)) from users", "nested aggregate"),
+            ("select length(id) from users", "unknown function"),
+            ("select sum(*) from users", "only count takes *")];
+        for &(command, reason) in errors.iter() {
+            assert!(process_command(&mut context, &mut db, command).is_err(), "'{}' should fail : {}", command, reason);
+        }
+
+        db.delete_db().expect("Unable to delete test db");
+    }
+
+    #[test]
+    fn test_create_index()
+    {
+        let db_filename = "test_create_index.db";
+        test_setup(db_filename);
+
+        // rows found through an index should be the rows a full scan finds ; `or id = 0` keeps the planner off the index.
+        let check_lookups = |db: &mut database::Database| {
+            for m in 0 .. 7 {
+                let indexed = select_ids(db, &format!("select * from users where username = 'user{}'", m));
+                let scanned = select_ids(db, &format!("select * from users where username = 'user{}' or id = 0", m));
+                assert!(indexed == scanned, "user{} : {:?} != {:?}", m, indexed, scanned);
+            }
+            let mut indexed = select_ids(db, "select * from users where email > 'e130' and 'e160' >= email");
+            let mut scanned = select_ids(db, "select * from users where (email > 'e130' and 'e160' >= email) or id = 0");
+            indexed.sort();
+            scanned.sort();
+            assert!(indexed == scanned, "email range : {:?} != {:?}", indexed, scanned);
+
+            let table = db.get_table("users").expect("users table should exist");
+            let num_rows = btree::count_keys(db.pager(), table.root_page_num()).expect("rows should be counted");
+            for index in table.indexes() {
+                btree::validate(db.pager(), index.root_page_num()).unwrap_or_else(|e| panic!("index {} should be valid : {}", index.name(), e));
+                let num_entries = btree::count_keys(db.pager(), index.root_page_num()).expect("entries should be counted");
+                assert!(num_entries == num_rows, "index {} has {} entries for {} rows", index.name(), num_entries, num_rows);
+            }
+        };
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            create_users_table(&mut db);
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            // emails go down as ids go up.
+            let insert = |context: &mut context::Context, db: &mut database::Database, id: i32| {
+                let command = format!("insert into users values ({}, 'user{}', 'e{:03}')", id, id % 7, 200 - id);
+                process_command(context, db, &command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+            };
+            for id in 1 .. 41 {
+                insert(&mut context, &mut db, id);
+            }
+            process_command(&mut context, &mut db, "create index users_username on users (username)").expect("index should be built from rows");
+            process_command(&mut context, &mut db, "CREATE INDEX \"Users Email\" ON users (EMAIL)").expect("index should be created");
+            for id in 41 .. 81 {
+                insert(&mut context, &mut db, id);
+            }
+            check_lookups(&mut db);
+
+            // rows come in email order through the index.
+            assert!(select_ids(&mut db, "select * from users where email between 'e145' and 'e148'") == vec![55, 54, 53, 52], "range through index");
+            assert!(select_ids(&mut db, "select * from users where email = 'e150' and username = 'user1'") == vec![50], "equality through index");
+            assert!(select_ids(&mut db, "select * from users where email > 'e999'").is_empty(), "empty range");
+            assert!(select_ids(&mut db, "select * from users where email between 'e148' and 'e145'").is_empty(), "reversed range");
+
+            let commands = [
+                "update users set username = 'user9' where id between 10 and 30",
+                "update users set id = id + 1000 where username = 'user3'",
+                "update users set email = 'e000' where id > 70",
+                "delete from users where username = 'user1' or id between 40 and 50",
+                "insert or replace into users values (2, 'user5', 'e555')",
+                "insert into users values (4, 'user0', 'e444') on conflict do update set username = excluded.username, id = id + 2000"];
+            for command in commands.iter() {
+                process_command(&mut context, &mut db, command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+            }
+            check_lookups(&mut db);
+            assert!(select_ids(&mut db, "select * from users where email = 'e555'") == vec![2], "replaced row");
+            assert!(select_ids(&mut db, "select * from users where username = 'user0' and email = 'e196'") == vec![2004], "moved by upsert");
+
+            let errors = [
+                ("create index users_username on users (email)", "duplicate index name"),
+                ("create index users on users (email)", "name of a table"),
+                ("create index i on users (age)", "unknown column"),
+                ("create index i on customers (id)", "unknown table"),
+                ("create index i on sqliters_master (name)", "catalog can not be indexed"),
+                ("create table \"users email\" (id integer)", "name of an index")];
+            for &(command, reason) in errors.iter() {
+                assert!(process_command(&mut context, &mut db, command).is_err(), "'{}' should fail : {}", command, reason);
+            }
+        }
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let names: Vec<String> = db.get_table("users").expect("users table should exist").indexes().iter().map(|i| i.name().to_string()).collect();
+            assert!(names == ["users_username", "Users Email"], "indexes after reopen {:?}", names);
+            check_lookups(&mut db);
+
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            process_command(&mut context, &mut db, "insert into users values (500, 'user6', 'e001')").expect("insert after reopen");
+            check_lookups(&mut db);
+            assert!(select_ids(&mut db, "select * from users where email < 'e002'") == vec![72, 74, 75, 76, 77, 79, 1003, 1031, 1038, 1045, 1052, 1059, 1066, 1073, 1080, 500], "index updated after reopen");
+
+            db.delete_db().expect("Unable to delete test db");
+        }
+    }
+
+    #[test]
+    fn test_unique_constraints()
+    {
+        let db_filename = "test_unique_constraints.db";
+        test_setup(db_filename);
+
+        let text = |v: &str| row::Value::Text(v.to_string());
+        let violation = |column: &str, value: row::Value| error::Error::unique_violation("accounts", column, &value);
+        let accounts = |db: &mut database::Database| -> Vec<Vec<row::Value>> {
+            select_rows(db, "select * from accounts").iter().map(|r| r.values().to_vec()).collect()
+        };
+        let account = |id: i32, email: &str, name: &str| vec![row::Value::Integer(id), text(email), text(name)];
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            let commands = [
+                "create table accounts (id integer primary key unique, email text(32) unique, name text(16))",
+                "insert into accounts values (1, 'a@x', 'a'), (2, 'b@x', 'b'), (3, 'c@x', 'c'), (4, 'd@x', 'd')",
+                // skipped for taken email.
+                "insert or ignore into accounts values (5, 'a@x', 'e'), (6, 'f@x', 'f')",
+                // deletes 6 which has the email.
+                "insert or replace into accounts values (7, 'f@x', 'g')",
+                // replaces 4 and deletes 3 which has the email.
+                "insert or replace into accounts values (4, 'c@x', 'h')",
+                "update accounts set name = 'same' where id < 3",
+                // a row keeps its own value when it moves.
+                "update accounts set id = 10 where id = 2",
+                "insert into accounts values (1, 'z@x', 'z') on conflict do update set email = excluded.email"];
+            for command in commands.iter() {
+                process_command(&mut context, &mut db, command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+            }
+            let expected = vec![account(1, "z@x", "same"), account(4, "c@x", "h"), account(7, "f@x", "g"), account(10, "b@x", "same")];
+            assert!(accounts(&mut db) == expected, "{:?}", accounts(&mut db));
+
+            let errors = [
+                ("insert into accounts values (11, 'b@x', 'k')", violation("email", text("b@x"))),
+                ("insert into accounts values (12, 'k@x', 'k'), (13, 'k@x', 'l')", violation("email", text("k@x"))),
+                ("insert into accounts values (1, 'k@x', 'k')", violation("id", row::Value::Integer(1))),
+                ("insert into accounts values (1, 'k@x', 'k') on conflict do update set email = 'c@x'", violation("email", text("c@x"))),
+                ("insert into accounts values (14, 'f@x', 'k') on conflict do update set name = 'k'", violation("email", text("f@x"))),
+                ("update accounts set email = 'k@x' where id > 5", violation("email", text("k@x"))),
+                ("update accounts set email = 'c@x' where id = 7", violation("email", text("c@x"))),
+                ("update accounts set id = 4 where id = 1", violation("id", row::Value::Integer(4)))];
+            for &(command, ref expected_error) in errors.iter() {
+                match process_command(&mut context, &mut db, command) {
+                    Err(ref e) if e == expected_error => {},
+                    other => panic!("'{}' should fail with {:?} : {:?}", command, expected_error, other)
+                }
+            }
+            let expected_after_errors = vec![account(1, "z@x", "same"), account(4, "c@x", "h"), account(7, "f@x", "g"), account(10, "b@x", "same"), account(12, "k@x", "k")];
+            assert!(accounts(&mut db) == expected_after_errors, "only rows before the violation are inserted : {:?}", accounts(&mut db));
+
+            let not_unique = process_command(&mut context, &mut db, "create unique index accounts_name on accounts (name)");
+            assert!(not_unique == Err(violation("name", text("same"))), "names are not unique : {:?}", not_unique);
+            // pages of the failed index are freed and taken by the next one.
+            let pages = db.pager().num_pages();
+            process_command(&mut context, &mut db, "update accounts set name = 'one' where id = 1").expect("update should succeed");
+            process_command(&mut context, &mut db, "create unique index accounts_name on accounts (name)").expect("names are unique");
+            assert!(db.pager().num_pages() == pages, "num pages {} after {}", db.pager().num_pages(), pages);
+
+            let table = db.get_table("accounts").expect("accounts table should exist");
+            let names: Vec<&str> = table.indexes().iter().map(|i| i.name()).collect();
+            assert!(names == ["sqliters_autoindex_accounts_1", "accounts_name"], "primary key needs no index : {:?}", names);
+        }
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            let unique: Vec<bool> = db.get_table("accounts").expect("accounts table should exist").indexes().iter().map(|i| i.is_unique()).collect();
+            assert!(unique == [true, true], "unique indexes after reopen");
+            assert!(process_command(&mut context, &mut db, "insert into accounts values (20, 'k@x', 'x')") == Err(violation("email", text("k@x"))), "email after reopen");
+            assert!(process_command(&mut context, &mut db, "insert into accounts values (20, 'x@x', 'h')") == Err(violation("name", text("h"))), "name after reopen");
+            process_command(&mut context, &mut db, "delete from accounts where email = 'k@x'").expect("delete should succeed");
+            process_command(&mut context, &mut db, "insert into accounts values (20, 'k@x', 'k')").expect("deleted value can be used again");
+
+            // auto index names are checked before the table is created.
+            let tables = db.tables().len();
+            for &command in ["create table customers_v2 (id integer primary key, email text unique)",
+                             "create table customers (id integer primary key, email text unique, bio text(4000) unique)"].iter() {
+                assert!(process_command(&mut context, &mut db, command).is_err(), "'{}' should fail", command);
+            }
+            assert!(db.tables().len() == tables && db.get_table("customers_v2").is_err() && db.get_table("customers").is_err(), "failed creates leave no table");
+            process_command(&mut context, &mut db, "create table customers (id integer primary key, email text unique)").expect("names fit in the catalog");
+            assert!(process_command(&mut context, &mut db, "create table sqliters_autoindex_customers_1 (id integer primary key)").is_err(), "index name is taken");
+
+            db.delete_db().expect("Unable to delete test db");
+        }
+    }
+
+    #[test]
+    fn test_variable_length_values()
+    {
+        let db_filename = "test_variable_length_values.db";
+        test_setup(db_filename);
+
+        let text = |v: &str| row::Value::Text(v.to_string());
+        // both emails are longer than the prefix kept in a cell and have the same prefix.
+        let email1 = format!("{}@example.com", "a".repeat(40));
+        let email2 = format!("{}@example.org", "a".repeat(40));
+        let bio = "long bio ".repeat(600);
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            let commands = [
+                "create table profiles (id integer primary key, email text unique, bio text, avatar blob)".to_string(),
+                format!("insert into profiles values (1, '{}', 'short', x'00ff10')", email1),
+                format!("insert into profiles values (2, '{}', '{}', x'')", email2, bio)];
+            for command in commands.iter() {
+                process_command(&mut context, &mut db, command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+            }
+
+            let rows = select_rows(&mut db, &format!("select id, avatar from profiles where email = '{}'", email2));
+            assert!(rows == [row::Row::new(vec![row::Value::Integer(2), row::Value::Blob(vec![])])], "index finds the row of full value : {:?}", rows);
+            assert!(select_ids(&mut db, "select id from profiles where avatar = x'00FF10'") == [1]);
+            assert!(select_ids(&mut db, &format!("select id from profiles where bio = '{}'", bio)) == [2]);
+
+            let duplicate = format!("insert into profiles values (3, '{}', 'b', x'01')", email1);
+            assert!(process_command(&mut context, &mut db, &duplicate) == Err(error::Error::unique_violation("profiles", "email", &text(&email1))),
+                    "long values are compared in full");
+
+            // overflow pages of old values are freed and reused ; new values are written before old ones are freed,
+            // so pages only grow till the first repeat of the commands.
+            let commands = [
+                "update profiles set bio = 'short' where id = 2".to_string(),
+                format!("update profiles set bio = '{}' where id = 1", bio),
+                "delete from profiles where id = 1".to_string(),
+                format!("insert into profiles values (1, '{}', '{}', x'00ff10')", email1, bio),
+                "update profiles set id = 3 where id = 1".to_string(),
+                "update profiles set id = 1 where id = 3".to_string()];
+            let mut num_pages = vec![];
+            for _ in 0 .. 4 {
+                for command in commands.iter() {
+                    process_command(&mut context, &mut db, command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+                }
+                num_pages.push(db.pager().num_pages());
+            }
+            assert!(num_pages[2] == num_pages[1] && num_pages[3] == num_pages[1], "num pages {:?}", num_pages);
+        }
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let rows = select_rows(&mut db, "select * from profiles");
+            let expected = vec![
+                row::Row::new(vec![row::Value::Integer(1), text(&email1), text(&bio), row::Value::Blob(vec![0, 255, 16])]),
+                row::Row::new(vec![row::Value::Integer(2), text(&email2), text("short"), row::Value::Blob(vec![])])];
+            assert!(rows == expected, "values after reopen : {:?}", rows);
+
+            let columns = select_rows(&mut db, "select sql from sqliters_master where name = 'profiles'");
+            assert!(columns[0].get(0) == Some(&text("create table \"profiles\" (\"id\" integer primary key, \"email\" text, \"bio\" text, \"avatar\" blob)")),
+                    "{:?}", columns);
+
+            // values of rows which fit in a leaf cell are kept whole without overflow pages.
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            let note = "n".repeat(201);
+            process_command(&mut context, &mut db, "create table notes (id integer primary key, body text)").expect("create should succeed");
+            let pages_before = db.pager().num_pages();
+            for id in 0 .. 300 {
+                let command = format!("insert into notes values ({}, '{}')", id, note);
+                process_command(&mut context, &mut db, &command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+            }
+            let pages = db.pager().num_pages() - pages_before;
+            assert!(pages < 40, "300 rows of {} bytes take {} pages", note.len(), pages);
+            let bodies = select_rows(&mut db, "select body from notes where id >= 298");
+            assert!(bodies == [row::Row::new(vec![text(&note)]), row::Row::new(vec![text(&note)])], "{:?}", bodies);
+            db.delete_db().expect("Unable to delete test db");
+        }
+    }
+
+    #[test]
+    fn test_mixed_size_rows()
+    {
+        let db_filename = "test_mixed_size_rows.db";
+        test_setup(db_filename);
+
+        let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+        create_users_table(&mut db);
+        let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+        let mut run = |db: &mut database::Database, command: &str| {
+            process_command(&mut context, db, command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+        };
+
+        // short values take only their bytes, so more rows fit in a leaf than rows of the largest size.
+        let num_rows = 2 * default_cells_per_page() as i32;
+        for i in 1 .. num_rows + 1 {
+            run(&mut db, &format!("insert into users values ({}, 'u{}', 'e{}')", i, i, i));
+        }
+        let root_page_num = db.get_table("users").expect("users table should exist").root_page_num();
+        assert!(db.pager().get_page(root_page_num as usize).expect("root page should be read").is_leaf(), "short rows should share the root leaf");
+
+        // values grow and shrink in place and move between leaves.
+        run(&mut db, "create table notes (id integer primary key, body text(200))");
+        let mut expected: Vec<(i32, String)> = (1 .. 121).map(|i| (i, "n".repeat(i as usize % 7 * 25))).collect();
+        for &(id, ref body) in expected.iter() {
+            run(&mut db, &format!("insert into notes values ({}, '{}')", id, body));
+        }
+        for round in 0 .. 6 {
+            let (low, high) = (round * 20 + 1, round * 20 + 15);
+            let body = "g".repeat(if round % 2 == 0 { 200 } else { 3 });
+            run(&mut db, &format!("update notes set body = '{}' where id between {} and {}", body, low, high));
+            run(&mut db, &format!("delete from notes where id between {} and {}", high + 1, high + 3));
+            for note in expected.iter_mut().filter(|n| n.0 >= low && n.0 <= high) {
+                note.1 = body.clone();
+            }
+            expected.retain(|n| n.0 < high + 1 || n.0 > high + 3);
+
+            let notes = db.get_table("notes").expect("notes table should exist");
+            btree::validate(db.pager(), notes.root_page_num()).unwrap_or_else(|e| panic!("tree should be valid in round {} : {}", round, e));
+        }
+
+        let rows = select_rows(&mut db, "select * from notes");
+        let actual: Vec<(i32, String)> = rows.iter()
+            .map(|r| match (r.get(0), r.get(1)) {
+                (Some(row::Value::Integer(id)), Some(row::Value::Text(body))) => (*id, body.clone()),
+                other => panic!("bad row {:?}", other)
+            })
+            .collect();
+        assert!(actual == expected, "rows after updates and deletes : {:?}", actual);
+
+        db.delete_db().expect("Unable to delete test db");
+    }
+
+    #[test]
+    fn test_column_types()
+    {
+        let db_filename = "test_column_types.db";
+        test_setup(db_filename);
+
+        let time = |v: &str| row::Value::Timestamp(timestamp::parse(v).expect("timestamp should parse"));
+        let values = |db: &mut database::Database, command: &str| -> Vec<Vec<row::Value>> {
+            select_rows(db, command).iter().map(|r| r.values().to_vec()).collect()
+        };
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            let commands = [
+                "create table events (id integer primary key, total bigint, price real, paid boolean, at timestamp, payload blob)",
+                "insert into events values (1, 3000000000, 9.5, true, '2024-01-15 10:00:00', x'01')",
+                "insert into events values (2, -5, 2, false, '2023-12-31', x'')",
+                "insert into events values (3, 7, -0.25, TRUE, timestamp '2024-01-15 09:59:59', x'ff')",
+                "create index events_total on events (total)",
+                "create index events_price on events (price)",
+                "create index events_at on events (at)"];
+            for command in commands.iter() {
+                process_command(&mut context, &mut db, command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+            }
+
+            let rows = select_rows(&mut db, "select * from events where id = 1");
+            assert!(rows[0].to_string() == "(1, 3000000000, 9.5, true, 2024-01-15 10:00:00, x'01')", "{}", rows[0]);
+
+            // index lookups on each number type and comparisons across number types.
+            assert!(select_ids(&mut db, "select id from events where price > 1 order by price") == [2, 1]);
+            assert!(select_ids(&mut db, "select id from events where price < 0") == [3]);
+            assert!(select_ids(&mut db, "select id from events where total > 2147483647") == [1]);
+            assert!(select_ids(&mut db, "select id from events where total >= -5 and total < 7") == [2]);
+            assert!(select_ids(&mut db, "select id from events where at >= timestamp '2024-01-01' order by at") == [3, 1]);
+            assert!(select_ids(&mut db, "select id from events where paid") == [1, 3]);
+            assert!(select_ids(&mut db, "select id from events where paid = false") == [2]);
+            assert!(select_ids(&mut db, "select id from events where id < 1.5") == [1]);
+
+            assert!(values(&mut db, "select total * 2, price + id, id * 1.5 from events where id = 2") ==
+                    vec![vec![row::Value::BigInt(-10), row::Value::Real(4.0), row::Value::Real(3.0)]], "arithmetic");
+            assert!(values(&mut db, "select sum(price), avg(price), sum(total), min(at), max(price) from events") ==
+                    vec![vec![row::Value::Real(11.25), row::Value::Real(3.75), row::Value::BigInt(3000000002), time("2023-12-31"), row::Value::Real(9.5)]],
+                    "aggregates");
+            assert!(values(&mut db, "select sum(total) from events where id > 1") == vec![vec![row::Value::BigInt(2)]], "small bigint");
+
+            let commands = [
+                "update events set price = 3 where id = 2",
+                "update events set total = total + id, paid = not paid where id > 1"];
+            for command in commands.iter() {
+                process_command(&mut context, &mut db, command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+            }
+            assert!(select_ids(&mut db, "select id from events order by price desc") == [1, 2, 3]);
+
+            let errors = [
+                ("select id * 2147483647 from events where id = 2", "integer overflow"),
+                ("select total * 3074457345618258603 from events", "bigint overflow"),
+                ("select id from events where paid > 1", "boolean compared with integer"),
+                ("select price + paid from events", "arithmetic on boolean"),
+                ("select id from events where at = '2024-01-15'", "timestamp compared with text"),
+                ("update events set paid = 1", "integer in boolean column"),
+                ("update events set id = total", "bigint out of range of integer"),
+                ("insert into events values (4, 1, 'abc', true, '2024-01-01', x'')", "text in real column"),
+                ("insert into events values (4, 1, 1.0, true, 'not a time', x'')", "bad timestamp"),
+                ("insert into events values (4, 1.5, 1.0, true, '2024-01-01', x'')", "real in bigint column")];
+            for &(command, reason) in errors.iter() {
+                assert!(process_command(&mut context, &mut db, command).is_err(), "'{}' should fail : {}", command, reason);
+            }
+        }
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let expected = vec![
+                vec![row::Value::Integer(1), row::Value::BigInt(3000000000), row::Value::Real(9.5), row::Value::Boolean(true),
+                     time("2024-01-15 10:00:00"), row::Value::Blob(vec![1])],
+                vec![row::Value::Integer(2), row::Value::BigInt(-3), row::Value::Real(3.0), row::Value::Boolean(true),
+                     time("2023-12-31 00:00:00"), row::Value::Blob(vec![])],
+                vec![row::Value::Integer(3), row::Value::BigInt(10), row::Value::Real(-0.25), row::Value::Boolean(false),
+                     time("2024-01-15 09:59:59"), row::Value::Blob(vec![255])]];
+            assert!(values(&mut db, "select * from events") == expected, "values after reopen");
+            assert!(select_ids(&mut db, "select id from events where total = 10") == [3], "index after reopen");
+            db.delete_db().expect("Unable to delete test db");
+        }
+    }
+
+    #[test]
+    fn test_null_values()
+    {
+        let db_filename = "test_null_values.db";
+        test_setup(db_filename);
+
+        let values = |db: &mut database::Database, command: &str| -> Vec<Vec<row::Value>> {
+            select_rows(db, command).iter().map(|r| r.values().to_vec()).collect()
+        };
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            let commands = [
+                "create table people (id integer primary key, name text(16) not null, nickname text unique, age integer, score real)",
+                "insert into people values (1, 'ann', NULL, 30, null), (2, 'bob', 'bobby', null, 1.5)",
+                "insert into people (id, name) values (3, 'cy')",
+                "insert into people values (4, '', '', 0, 0)"];
+            for command in commands.iter() {
+                process_command(&mut context, &mut db, command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+            }
+
+            let rows = select_rows(&mut db, "select * from people where id = 3");
+            assert!(rows[0].to_string() == "(3, cy, NULL, NULL, NULL)", "{}", rows[0]);
+
+            // null is not equal to anything, so only IS NULL finds it ; empty text is not null.
+            assert!(select_ids(&mut db, "select id from people where nickname is null") == [1, 3]);
+            assert!(select_ids(&mut db, "select id from people where nickname is not null") == [2, 4]);
+            assert!(select_ids(&mut db, "select id from people where nickname = ''") == [4]);
+            assert!(select_ids(&mut db, "select id from people where age = null or age != null").is_empty());
+            assert!(select_ids(&mut db, "select id from people where age > 20") == [1]);
+            assert!(select_ids(&mut db, "select id from people where not (age > 20)") == [4], "not of null is null");
+            assert!(select_ids(&mut db, "select id from people where age > 20 or score > 1") == [1, 2], "true or null is true");
+            assert!(select_ids(&mut db, "select id from people where (age > 20 and score > 1) is null") == [1, 2, 3]);
+            assert!(select_ids(&mut db, "select id from people order by age") == [2, 3, 4, 1], "nulls sort first");
+
+            assert!(values(&mut db, "select count(*), count(age), count(nickname), sum(age), avg(score), max(age), min(score) from people") ==
+                    vec![vec![row::Value::BigInt(4), row::Value::BigInt(2), row::Value::BigInt(2), row::Value::BigInt(30),
+                              row::Value::Real(0.75), row::Value::Integer(30), row::Value::Real(0.0)]], "aggregates skip nulls");
+            assert!(values(&mut db, "select age + 1, null from people where id = 2") == vec![vec![row::Value::Null, row::Value::Null]]);
+
+            let violations = [
+                ("insert into people values (5, null, 'e', 1, 1)", "name"),
+                ("insert into people (id, age) values (5, 1)", "name"),
+                ("insert into people (name) values ('e')", "id"),
+                ("update people set name = null where id = 1", "name")];
+            for &(command, column) in violations.iter() {
+                assert!(process_command(&mut context, &mut db, command) == Err(error::Error::not_null_violation("people", column)),
+                        "'{}' should fail on column {}", command, column);
+            }
+
+            // unique columns and indexes take any number of nulls.
+            let commands = [
+                "update people set age = age + 1",
+                "update people set nickname = null where id = 2",
+                "create unique index people_age on people (age)",
+                "insert into people (id, name) values (5, 'dee')"];
+            for command in commands.iter() {
+                process_command(&mut context, &mut db, command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+            }
+            assert!(select_ids(&mut db, "select id from people where nickname is null") == [1, 2, 3, 5]);
+            assert!(select_ids(&mut db, "select id from people where age >= 1") == [4, 1], "index has no nulls");
+        }
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let text = |v: &str| row::Value::Text(v.to_string());
+            let expected = vec![
+                vec![row::Value::Integer(1), text("ann"), row::Value::Null, row::Value::Integer(31), row::Value::Null],
+                vec![row::Value::Integer(2), text("bob"), row::Value::Null, row::Value::Null, row::Value::Real(1.5)],
+                vec![row::Value::Integer(3), text("cy"), row::Value::Null, row::Value::Null, row::Value::Null],
+                vec![row::Value::Integer(4), text(""), text(""), row::Value::Integer(1), row::Value::Real(0.0)],
+                vec![row::Value::Integer(5), text("dee"), row::Value::Null, row::Value::Null, row::Value::Null]];
+            assert!(values(&mut db, "select * from people") == expected, "values after reopen");
+
+            let sql = select_rows(&mut db, "select sql from sqliters_master where name = 'people'");
+            assert!(sql[0].get(0) == Some(&text("create table \"people\" (\"id\" integer primary key, \"name\" text(16) not null, \"nickname\" text, \"age\" integer, \"score\" real)")),
+                    "{:?}", sql);
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            assert!(process_command(&mut context, &mut db, "insert into people (id) values (6)") == Err(error::Error::not_null_violation("people", "name")),
+                    "not null is kept in catalog");
+            db.delete_db().expect("Unable to delete test db");
+        }
+    }
+
+    #[test]
+    fn test_primary_keys()
+    {
+        let db_filename = "test_primary_keys.db";
+        test_setup(db_filename);
+
+        let text = |v: &str| row::Value::Text(v.to_string());
+        let values = |db: &mut database::Database, command: &str| -> Vec<Vec<row::Value>> {
+            select_rows(db, command).iter().map(|r| r.values().to_vec()).collect()
+        };
+        let visit = |city: &str, day: i32| vec![text(city), row::Value::Integer(day)];
+        let cities = ["delhi", "agra", "pune", "goa"];
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            let mut commands = vec![
+                String::from("create table visits (city text(8), day integer, count bigint, primary key (city, day))"),
+                String::from("create table tags (name char(4) primary key, uses integer)"),
+                String::from("create table ticks (at bigint primary key)"),
+                String::from("insert into tags values ('b', 1), ('ab', 2), ('a', 3), ('', 4)"),
+                String::from("insert into ticks values (3000000000), (-3000000000), (0)")];
+            // days go down so that rows come in key order only after sorting.
+            for day in (1 .. 31).rev() {
+                for (n, city) in cities.iter().enumerate() {
+                    commands.push(format!("insert into visits values ('{}', {}, {})", city, day, day * 10 + n as i32));
+                }
+            }
+            for command in commands.iter() {
+                process_command(&mut context, &mut db, command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+            }
+
+            // rows are in order of city and then day.
+            let all = values(&mut db, "select city, day from visits");
+            let mut expected: Vec<Vec<row::Value>> = vec![];
+            for city in ["agra", "delhi", "goa", "pune"].iter() {
+                expected.extend((1 .. 31).map(|day| visit(city, day)));
+            }
+            assert!(all == expected, "rows should be sorted on key columns : {:?}", &all[.. 3]);
+            assert!(select_ids_of(&mut db, "select city, day from visits where city = 'goa' and day between 3 and 5", 1) == [3, 4, 5]);
+            assert!(select_ids_of(&mut db, "select city, day from visits where day = 7", 1) == [7, 7, 7, 7], "second key column alone is a scan");
+            assert!(values(&mut db, "select city, day from visits order by city desc limit 2") == vec![visit("pune", 30), visit("pune", 29)]);
+            // tree order is used only for a prefix of key columns in one direction.
+            let orders = [
+                ("city, day", vec![visit("agra", 1), visit("agra", 2)]),
+                ("city desc, day desc", vec![visit("pune", 30), visit("pune", 29)]),
+                ("city, day desc", vec![visit("agra", 30), visit("agra", 29)]),
+                ("city desc, day", vec![visit("pune", 1), visit("pune", 2)]),
+                ("day, city", vec![visit("agra", 1), visit("delhi", 1)]),
+                ("city desc, count", vec![visit("pune", 1), visit("pune", 2)])];
+            for &(order, ref expected) in orders.iter() {
+                let rows = values(&mut db, &format!("select city, day from visits order by {} limit 2", order));
+                assert!(rows == *expected, "order by {} : {:?}", order, rows);
+            }
+            assert!(values(&mut db, "select min(city), max(city), count(*) from visits where city > 'b'") ==
+                    vec![vec![text("delhi"), text("pune"), row::Value::BigInt(90)]]);
+
+            assert!(values(&mut db, "select name from tags") == vec![vec![text("")], vec![text("a")], vec![text("ab")], vec![text("b")]]);
+            assert!(values(&mut db, "select uses from tags where name > 'a'") == vec![vec![row::Value::Integer(2)], vec![row::Value::Integer(1)]]);
+            assert!(values(&mut db, "select at from ticks where at > 2147483647 or at < 0") ==
+                    vec![vec![row::Value::BigInt(-3000000000)], vec![row::Value::BigInt(3000000000)]]);
+
+            // a key is taken when all its columns are equal.
+            let duplicate = process_command(&mut context, &mut db, "insert into visits values ('agra', 1, 5)");
+            assert!(duplicate.as_ref().map_err(|e| e.to_string()) == Err(String::from("UNIQUE constraint failed : visits.city, visits.day = ('agra', 1)")),
+                    "{:?}", duplicate);
+            assert!(process_command(&mut context, &mut db, "insert into tags values ('a', 5)") == Err(error::Error::unique_violation("tags", "name", &text("a"))));
+            assert!(process_command(&mut context, &mut db, "insert into tags values ('abcde', 5)").is_err(), "key should fit in its column");
+            assert!(process_command(&mut context, &mut db, "insert into visits (city, count) values ('agra', 5)") == Err(error::Error::not_null_violation("visits", "day")));
+
+            let commands = [
+                "insert into visits values ('agra', 31, 5)",
+                "update visits set day = day + 100 where city = 'goa' and day > 28",
+                "update tags set name = 'c' where name = 'a'",
+                "insert or replace into visits values ('pune', 1, 7)",
+                "delete from visits where city = 'delhi' and day > 1",
+                "create unique index visits_count on visits (count)"];
+            for command in commands.iter() {
+                process_command(&mut context, &mut db, command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+            }
+            assert!(process_command(&mut context, &mut db, "update visits set day = 1 where city = 'agra' and day = 2").is_err(), "moved key should be free");
+        }
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            assert!(select_ids_of(&mut db, "select city, day from visits where city = 'goa' and day > 27", 1) == [28, 129, 130]);
+            assert!(select_ids_of(&mut db, "select city, day from visits where city = 'delhi'", 1) == [1]);
+            assert!(values(&mut db, "select city, day from visits where count = 7") == vec![visit("pune", 1)], "index entries have composite keys");
+            assert!(values(&mut db, "select city, day from visits where count = 5") == vec![visit("agra", 31)]);
+            assert!(values(&mut db, "select name from tags order by name desc") == vec![vec![text("c")], vec![text("b")], vec![text("ab")], vec![text("")]]);
+
+            let sql = select_rows(&mut db, "select sql from sqliters_master where name = 'visits'");
+            assert!(sql[0].get(0) == Some(&text("create table \"visits\" (\"city\" text(8), \"day\" integer, \"count\" bigint, primary key (\"city\", \"day\"))")),
+                    "{:?}", sql);
+            db.delete_db().expect("Unable to delete test db");
+        }
+    }
+
+    #[test]
+    fn test_rowids()
+    {
+        let db_filename = "test_rowids.db";
+        test_setup(db_filename);
+
+        let text = |v: &str| row::Value::Text(v.to_string());
+        let values = |db: &mut database::Database, command: &str| -> Vec<Vec<row::Value>> {
+            select_rows(db, command).iter().map(|r| r.values().to_vec()).collect()
+        };
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            let mut failing = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            let mut run = |db: &mut database::Database, command: &str| {
+                process_command(&mut context, db, command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+            };
+
+            // a table without primary key is keyed on rowid in order of inserts.
+            run(&mut db, "create table logs (msg text(16), level integer unique)");
+            run(&mut db, "insert into logs values ('b', 2), ('a', 1)");
+            assert!(db.last_insert_rowid() == 2, "last_insert_rowid {}", db.last_insert_rowid());
+            assert!(values(&mut db, "select * from logs") == vec![vec![text("b"), row::Value::Integer(2)], vec![text("a"), row::Value::Integer(1)]],
+                    "rowid is not in * : {:?}", values(&mut db, "select * from logs"));
+            assert!(values(&mut db, "select rowid from logs where level = 1") == vec![vec![row::Value::BigInt(2)]]);
+
+            run(&mut db, "insert into logs (rowid, msg) values (10, 'c')");
+            run(&mut db, "insert into logs (msg) values ('d')");
+            assert!(db.last_insert_rowid() == 11, "next rowid comes after the largest : {}", db.last_insert_rowid());
+            run(&mut db, "delete from logs where rowid > 9");
+            run(&mut db, "insert into logs (msg) values ('e')");
+            assert!(db.last_insert_rowid() == 3, "rowids of deleted rows can come again : {}", db.last_insert_rowid());
+            assert!(process_command(&mut failing, &mut db, "insert into logs values ('f', 1)") == Err(error::Error::unique_violation("logs", "level", &row::Value::Integer(1))));
+
+            // an AUTOINCREMENT key never gives an id again ; its sequence table is created before the table.
+            let pages = db.pager().num_pages();
+            assert!(process_command(&mut failing, &mut db, "create table huge (id integer primary key autoincrement, body text(5000))").is_err());
+            assert!(db.get_table(database::SEQUENCE_TABLE_NAME).is_err() && db.pager().num_pages() == pages, "failed create writes nothing");
+            run(&mut db, "create table jobs (id integer primary key autoincrement, name text(8))");
+            let names: Vec<String> = db.tables().iter().map(|t| t.name().to_string()).collect();
+            assert!(names.ends_with(&[database::SEQUENCE_TABLE_NAME.to_string(), "jobs".to_string()]), "{:?}", names);
+            run(&mut db, "insert into jobs (name) values ('x'), ('y')");
+            run(&mut db, "delete from jobs where id = 2");
+            run(&mut db, "insert into jobs (name) values ('z')");
+            assert!(select_ids(&mut db, "select id from jobs") == [1, 3], "id 2 is not given again");
+            run(&mut db, "insert into jobs values (10, 'w')");
+            run(&mut db, "delete from jobs where id = 10");
+            run(&mut db, "insert into jobs values (NULL, 'v')");
+            assert!(db.last_insert_rowid() == 11, "explicit ids count as given : {}", db.last_insert_rowid());
+
+            assert!(process_command(&mut failing, &mut db, "create table sqliters_sequence (name text(8))").is_err(), "sequence table name is reserved");
+            assert!(process_command(&mut failing, &mut db, "create table bad (name text(8) primary key autoincrement)").is_err(), "autoincrement key is an integer");
+        }
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            process_command(&mut context, &mut db, "insert into jobs (name) values ('u')").expect("insert should work");
+            process_command(&mut context, &mut db, "insert into logs (msg) values ('g')").expect("insert should work");
+            assert!(select_ids(&mut db, "select id from jobs") == [1, 3, 11, 12], "sequence is kept in file");
+            assert!(values(&mut db, "select seq from sqliters_sequence where name = 'jobs'") == vec![vec![row::Value::BigInt(12)]]);
+            assert!(values(&mut db, "select rowid, msg from logs") == vec![vec![row::Value::BigInt(1), text("b")], vec![row::Value::BigInt(2), text("a")],
+                                                                            vec![row::Value::BigInt(3), text("e")], vec![row::Value::BigInt(4), text("g")]]);
+
+            let sql = values(&mut db, "select sql from sqliters_master where name = 'logs' or name = 'jobs'");
+            assert!(sql == vec![vec![text("create table \"logs\" (\"msg\" text(16), \"level\" integer)")],
+                                vec![text("create table \"jobs\" (\"id\" integer primary key autoincrement, \"name\" text(8))")]], "{:?}", sql);
+
+            process_command(&mut context, &mut db, "insert into jobs values (2147483647, 'max')").expect("insert should work");
+            assert!(process_command(&mut context, &mut db, "insert into jobs (name) values ('over')").is_err(), "integer ids run out");
+            db.delete_db().expect("Unable to delete test db");
+        }
+    }
+
+    #[test]
+    fn test_prepared_statements()
+    {
+        let db_filename = "test_prepared_statements.db";
+        test_setup(db_filename);
+
+        let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+        let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+        process_command(&mut context, &mut db, "create table notes (id integer primary key, body text, score real)").expect("create should work");
+
+        // a statement is bound and executed many times ; values need no quoting.
+        let mut insert = sqlcommands::prepare(&mut context, "insert into notes values (?, ?, ?)").expect("insert should prepare");
+        assert!(insert.parameter_count() == 3, "{}", insert.parameter_count());
+        for (id, body) in [(1, "it's"), (2, "x'00'"), (3, "'); delete from notes; --")].iter() {
+            insert.bind(1, row::Value::Integer(*id)).expect("bind should work");
+            insert.bind(2, row::Value::Text(body.to_string())).expect("bind should work");
+            insert.bind(3, row::Value::Integer(*id * 10)).expect("bind should work");
+            insert.execute(&mut context, &mut db).expect("insert should work");
+        }
+        let rows = select_rows(&mut db, "select body, score from notes where id = 3");
+        assert!(rows == vec![row::Row::new(vec![row::Value::Text(String::from("'); delete from notes; --")), row::Value::Real(30.0)])], "{:?}", rows);
+
+        assert!(insert.bind(0, row::Value::Null).is_err(), "parameters are numbered from 1");
+        assert!(insert.bind(4, row::Value::Null) == Err(error::Error::ParameterOutOfRange { index: 4, count: 3 }));
+        insert.reset();
+        assert!(insert.execute(&mut context, &mut db) == Err(error::Error::UnboundParameter(1)), "reset clears bindings");
+        assert!(process_command(&mut context, &mut db, "delete from notes where id = ?").is_err(), "parameters of a command are not bound");
+
+        let mut update = sqlcommands::prepare(&mut context, "update notes set score = score + ? where id between ? and ?").expect("update should prepare");
+        for (n, value) in [row::Value::Real(0.5), row::Value::Integer(2), row::Value::Integer(3)].iter().enumerate() {
+            update.bind(n + 1, value.clone()).expect("bind should work");
+        }
+        update.execute(&mut context, &mut db).expect("update should work");
+        update.execute(&mut context, &mut db).expect("bindings are kept between executes");
+        let rows = select_rows(&mut db, "select score from notes");
+        assert!(rows.iter().map(|r| r.values()[0].clone()).collect::<Vec<_>>() == [row::Value::Real(10.0), row::Value::Real(21.0), row::Value::Real(31.0)], "{:?}", rows);
+
+        // parsed statements are cached by sql ; the least recently used one is evicted.
+        assert!(context.statements().contains("insert into notes values (?, ?, ?)"), "prepared statement is cached");
+        let mut cache = statement::StatementCache::new(2);
+        cache.get("select * from a").expect("select should parse");
+        cache.get("select * from b").expect("select should parse");
+        cache.get("select * from a").expect("select should parse");
+        cache.get("select * from c").expect("select should parse");
+        assert!(cache.contains("select * from a") && !cache.contains("select * from b") && cache.contains("select * from c"), "b is least recently used");
+        assert!(cache.get("select from").is_err() && !cache.contains("select from"), "errors are not cached");
+
+        db.delete_db().expect("Unable to delete test db");
+    }
+
+    #[test]
+    fn test_error_variants()
+    {
+        let db_filename = "test_error_variants.db";
+        test_setup(db_filename);
+
+        {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            create_users_table(&mut db);
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            process_command(&mut context, &mut db, "insert into users values (1, 'a', 'a@abc.com')").expect("insert should work");
+
+            let parse_error = process_command(&mut context, &mut db, "insert users values (2)").expect_err("insert without into");
+            assert!(matches!(parse_error, error::Error::Parse(_)) && parse_error.code() == error::ErrorCode::Error, "{:?}", parse_error);
+            let duplicate = process_command(&mut context, &mut db, "insert into users values (1, 'b', 'b@abc.com')").expect_err("duplicate key");
+            assert!(duplicate == error::Error::unique_violation("users", "id", &row::Value::Integer(1)) && duplicate.code() as i32 == 19, "{:?}", duplicate);
+            let missing = process_command(&mut context, &mut db, "select * from missing").expect_err("no such table");
+            assert!(missing == error::Error::NoSuchTable(String::from("missing")) && missing.code() as i32 == 1, "{:?}", missing);
+            let exists = process_command(&mut context, &mut db, "create table USERS (id integer primary key)").expect_err("table exists");
+            assert!(exists == error::Error::already_exists("Table", "USERS") && exists.code() as i32 == 1, "{:?}", exists);
+            let column = process_command(&mut context, &mut db, "update users set age = 1").expect_err("no such column");
+            assert!(column == error::Error::NoSuchColumn(String::from("age")), "{:?}", column);
+            for command in ["insert into users values ('x', 'b', 'b@abc.com')", "select * from users where username + 1 > 2"].iter() {
+                let mismatch = process_command(&mut context, &mut db, command).expect_err("type mismatch");
+                assert!(matches!(mismatch, error::Error::TypeMismatch(_)) && mismatch.code() as i32 == 20, "{} : {:?}", command, mismatch);
+            }
+            let schema = process_command(&mut context, &mut db, "create table pairs (a integer, a integer)").expect_err("duplicate column");
+            assert!(matches!(schema, error::Error::InvalidSchema(_)), "{:?}", schema);
+
+            // the B+tree only knows the key of a duplicate.
+            let key = row::Row::new(vec![row::Value::Integer(1), row::Value::Null, row::Value::Null]).key(&schema::Schema::default_users()).expect("key");
+            let root_page_num = db.get_table("users").expect("users table should exist").root_page_num();
+            let duplicate = cursor::Cursor::find(db.pager(), root_page_num, &key).and_then(|mut cursor| cursor.insert(&key, vec![0; 8]))
+                .expect_err("key is present");
+            assert!(duplicate == error::Error::duplicate_key(&key) && duplicate.code() as i32 == 19, "{:?}", duplicate);
+
+            db.delete_db().expect("Unable to delete test db");
+        }
+
+        let full = error::Error::io(String::from("Failed to write file"), std::io::Error::from(std::io::ErrorKind::StorageFull));
+        assert!(full == error::Error::Full && full.code() as i32 == 13, "{:?}", full);
+
+        // io errors keep their source.
+        let io_error = database::Database::new(".").expect_err("a directory is not a database");
+        assert!(io_error.code() as i32 == 10 && std::error::Error::source(&io_error).is_some(), "{:?}", io_error);
+    }
+
+    fn create_users_table(db: &mut database::Database) {
+        db.create_table("users", schema::Schema::default_users()).expect("Unable to create users table");
+    }
+
+    #[test]
+    fn test_page_cache()
+    {
+        let db_filename = "test_page_cache.db";
+        let num_rows = 2000;
+        let policies: Vec<Box<dyn replacement::ReplacementPolicy>> = vec![
+            Box::new(replacement::Lru::new()),
+            Box::new(replacement::Clock::new()),
+            Box::new(replacement::TwoQueue::new())
+        ];
+
+        for policy in policies {
+            test_setup(db_filename);
+
+            {
+                // a cache of a few pages evicts pages of a tree much bigger than it.
+                let config = database::Config { cache_size: 4, policy, ..Default::default() };
+                let mut db = database::Database::with_config(db_filename, config).expect("Unable to create/open db file.");
+                create_users_table(&mut db);
+                let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+                process_command(&mut context, &mut db, "create index users_email on users (email)").expect("create index should work");
+                let root_page_num = db.get_table("users").expect("users table should exist").root_page_num();
+                for i in (1 .. num_rows + 1).rev() {
+                    let command = format!("insert into users values ({}, 'user{}', 'e{}@abc.com')", i, i, i);
+                    process_command(&mut context, &mut db, &command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+                    assert!(db.pager().cached_pages() <= 4, "cached pages {}", db.pager().cached_pages());
+                }
+                // long text goes to overflow pages which are evicted too.
+                process_command(&mut context, &mut db, "create table docs (id integer primary key, body text)").expect("create should work");
+                for id in 1 .. 4 {
+                    let command = format!("insert into docs values ({}, '{}')", id, id.to_string().repeat(consts::DEFAULT_PAGE_SIZE * 3));
+                    process_command(&mut context, &mut db, &command).expect("insert should work");
+                }
+                process_command(&mut context, &mut db, "delete from users where id > 1500").expect("delete should work");
+
+                btree::validate(db.pager(), root_page_num).expect("tree should be valid with a small cache");
+                assert!(db.pager().num_pages() > 40, "db should not be limited by cache : {} pages", db.pager().num_pages());
+                assert!(select_ids(&mut db, "select * from users").len() == 1500);
+                assert!(select_ids(&mut db, "select * from users where email = 'e1234@abc.com'") == vec![1234], "index should be read from evicted pages");
+            }
+
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let ids = select_ids(&mut db, "select * from users");
+            assert!(ids == (1 .. 1501).collect::<Vec<i32>>(), "evicted and flushed pages should be in db file : {} rows", ids.len());
+            assert!(db.pager().dirty_pages() == 0, "reads should not make pages dirty : {} of {}", db.pager().dirty_pages(), db.pager().cached_pages());
+            let rows = select_rows(&mut db, "select * from docs where id = 2");
+            assert!(rows[0].get(1) == Some(&row::Value::Text("2".repeat(consts::DEFAULT_PAGE_SIZE * 3))), "overflow chain should be in db file");
+            assert!(select_ids(&mut db, "select * from users where email = 'e1499@abc.com'") == vec![1499], "index should be in db file");
+            assert!(select_ids(&mut db, "select * from users where email = 'e1501@abc.com'").is_empty(), "deletes should be in db file");
+            db.delete_db().expect("Unable to delete test db");
+        }
+    }
+
+    #[test]
+    fn test_file_header()
+    {
+        let db_filename = "test_file_header.db";
+        test_setup(db_filename);
+        let page_size = consts::DEFAULT_PAGE_SIZE;
+        let long_text = "x".repeat(page_size * 3);
+
+        let num_pages = {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            create_users_table(&mut db);
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            process_command(&mut context, &mut db, "create table docs (id integer primary key, body text)").expect("create should work");
+            for id in 1 .. 4 {
+                process_command(&mut context, &mut db, &format!("insert into docs values ({}, '{}')", id, long_text)).expect("insert should work");
+            }
+            // overflow pages of deleted rows go to the freelist.
+            process_command(&mut context, &mut db, "delete from docs where id > 1").expect("delete should work");
+            db.pager().num_pages()
+        };
+
+        let bytes = fs::read(db_filename).expect("db file should be readable");
+        let header = header::Header::read(&bytes).expect("db file should start with header");
+        assert!(bytes.starts_with(b"sqliters format\0") && bytes.len() == num_pages as usize * page_size, "{:?}", header);
+        assert!(header.page_size == page_size && header.page_count == num_pages && header.schema_root == 1 && header.freelist_head != 0, "{:?}", header);
+
+        {
+            // freelist is read from header ; its pages are reused before db grows.
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            for id in 2 .. 4 {
+                process_command(&mut context, &mut db, &format!("insert into docs values ({}, '{}')", id, long_text)).expect("insert should work");
+            }
+            assert!(db.pager().num_pages() == num_pages, "free pages should be reused : {} pages", db.pager().num_pages());
+            assert!(select_rows(&mut db, "select * from docs").len() == 3);
+        }
+
+        let open_error = |bytes: &[u8]| {
+            fs::write(db_filename, bytes).expect("db file should be writable");
+            database::Database::new(db_filename).expect_err("file should not open")
+        };
+        let valid = fs::read(db_filename).expect("db file should be readable");
+
+        let not_a_db = open_error(b"hello, this is not a database file");
+        assert!(matches!(not_a_db, error::Error::NotADatabase(_)) && not_a_db.code() as i32 == 26, "{:?}", not_a_db);
+        let mut newer = valid.clone();
+        newer[consts::FORMAT_VERSION_OFFSET + consts::FORMAT_VERSION_SIZE - 1] = 99;
+        assert!(matches!(open_error(&newer), error::Error::NotADatabase(_)), "newer format version should be rejected");
+        let truncated = open_error(&valid[.. page_size * 2]);
+        assert!(matches!(truncated, error::Error::Corrupt(_)), "{:?}", truncated);
+
+        // a file written before the header page has pages of 2046 bytes, the catalog root at page 0 and no freelist.
+        // It is made from a file with a header of that page size, whose catalog root is page 1.
+        let page_size = consts::HEADERLESS_PAGE_SIZE;
+        let empty = header::Header { page_size, page_count: 1, freelist_head: 0, schema_root: 0 };
+        fs::write(db_filename, empty.write()).expect("db file should be writable");
+        let num_pages = {
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+            process_command(&mut context, &mut db, "create table docs (id integer primary key, body text)").expect("create should work");
+            for id in 1 .. 4 {
+                process_command(&mut context, &mut db, &format!("insert into docs values ({}, '{}')", id, long_text)).expect("insert should work");
+            }
+            assert!(db.pager().schema_root() == 1);
+            db.pager().num_pages()
+        };
+        let mut headerless = fs::read(db_filename).expect("db file should be readable");
+        headerless.copy_within(page_size .. page_size * 2, 0);
+        let mut free_page = page::Page::new_free(page_size, 0);
+        free_page.flush();
+        headerless[page_size .. page_size * 2].copy_from_slice(free_page.get_data());
+        fs::write(db_filename, &headerless).expect("db file should be writable");
+        {
+            let mut db = database::Database::new(db_filename).expect("headerless file should be migrated");
+            assert!(db.pager().schema_root() == num_pages && db.pager().num_pages() == num_pages + 1, "catalog root should move to the end");
+            assert!(db.pager().page_size() == page_size);
+            assert!(select_ids(&mut db, "select * from docs") == vec![1, 2, 3]);
+            process_command(&mut context::Context::new(Box::new(AssertSelectOutFn::new(1))), &mut db, "create table more (id integer primary key)")
+                .expect("create should work");
+            assert!(db.get_table("more").expect("table should exist").root_page_num() == 1, "old catalog root should be reused from freelist");
+        }
+        let mut db = database::Database::new(db_filename).expect("migrated file should open");
+        assert!(select_ids(&mut db, "select * from docs") == vec![1, 2, 3] && db.get_table("more").is_ok());
+        db.delete_db().expect("Unable to delete test db");
+    }
+
+    #[test]
+    fn test_page_size()
+    {
+        let db_filename = "test_page_size.db";
+
+        for &page_size in [consts::MIN_PAGE_SIZE, 8192, consts::MAX_PAGE_SIZE].iter() {
+            test_setup(db_filename);
+            {
+                let config = database::Config { page_size, ..Default::default() };
+                let mut db = database::Database::with_config(db_filename, config).expect("Unable to create/open db file.");
+                create_users_table(&mut db);
+                let mut context = context::Context::new(Box::new(AssertSelectOutFn::new(1)));
+                for i in 1 .. TEST_ROWS + 1 {
+                    let command = format!("insert into users values ({}, 'user{}', 'e{}@abc.com')", i, i, i);
+                    process_command(&mut context, &mut db, &command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+                }
+                let root_page_num = db.get_table("users").expect("users table should exist").root_page_num();
+                btree::validate(db.pager(), root_page_num).expect("tree should be valid");
+            }
+
+            let bytes = fs::read(db_filename).expect("db file should be readable");
+            let header = header::Header::read(&bytes).expect("db file should start with header");
+            assert!(header.page_size == page_size && bytes.len() == header.page_count as usize * page_size, "{:?}", header);
+
+            // page size of the file is kept when it is opened with another page size.
+            let mut db = database::Database::new(db_filename).expect("Unable to create/open db file.");
+            assert!(db.pager().page_size() == page_size, "page size {}", db.pager().page_size());
+            assert!(select_ids(&mut db, "select * from users") == (1 .. TEST_ROWS as i32 + 1).collect::<Vec<i32>>(), "page size {}", page_size);
+            db.delete_db().expect("Unable to delete test db");
+        }
+
+        for &page_size in [256, 1000, 2046, 131072].iter() {
+            test_setup(db_filename);
+            let config = database::Config { page_size, ..Default::default() };
+            assert!(database::Database::with_config(db_filename, config).is_err(), "page size {} should be rejected", page_size);
+            assert!(!Path::new(db_filename).exists(), "rejected page size {} should not create a file", page_size);
+        }
+        test_setup(db_filename);
+    }
+
+    fn select_rows(db: &mut database::Database, command: &str) -> Vec<row::Row> {
+        let mut context = context::Context::new(Box::new(CollectSelectOutFn::new()));
+        process_command(&mut context, db, command).unwrap_or_else(|e| panic!("Failed at command '{}' : {}", command, e));
+        let out = context.get_out().downcast_ref::<CollectSelectOutFn>().expect("Failed to get CollectSelectOutFn out of context");
+        out.rows().to_vec()
+    }
+
+    fn select_ids(db: &mut database::Database, command: &str) -> Vec<i32> {
+        select_ids_of(db, command, 0)
+    }
+
+    // values of integer column at key_pos of selected rows.
+    fn select_ids_of(db: &mut database::Database, command: &str, key_pos: usize) -> Vec<i32> {
+        select_rows(db, command).iter()
+            .map(|row| match row.get(key_pos) {
+                Some(&row::Value::Integer(id)) => id,
+                other => panic!("id should be integer : {:?}", other)
+            })
+            .collect()
+    }
+
+    fn default_cells_per_page() -> usize {
+        page::leaf_max_cells(consts::DEFAULT_PAGE_SIZE, schema::Schema::default_users().cell_size()) as usize
     }
 
     fn test_setup(db_filename: &str) {
@@ -235,9 +1812,35 @@ mod tests {
     }
 
     impl context::OutFn for AssertSelectOutFn {
-        fn outfn(&mut self, insert: &statement::InsertStatement) {
-            assert!(self.count == insert.id(), "self.count {} == insert.id() {}", self.count, insert.id());
+        fn outfn(&mut self, _columns: &[schema::Column], row: &row::Row) {
+            assert!(row.get(0) == Some(&row::Value::Integer(self.count)), "self.count {} == row id {:?}", self.count, row.get(0));
             self.count += 1;
         }
     }
+
+    pub struct CollectSelectOutFn {
+        columns: Vec<schema::Column>,
+        rows: Vec<row::Row>
+    }
+
+    impl CollectSelectOutFn {
+        pub fn new() -> Self {
+            CollectSelectOutFn{columns: vec![], rows: vec![]}
+        }
+
+        pub fn columns(&self) -> &[schema::Column] {
+            &self.columns
+        }
+
+        pub fn rows(&self) -> &[row::Row] {
+            &self.rows
+        }
+    }
+
+    impl context::OutFn for CollectSelectOutFn {
+        fn outfn(&mut self, columns: &[schema::Column], row: &row::Row) {
+            self.columns = columns.to_vec();
+            self.rows.push(row.clone());
+        }
+    }
 }
@@ -1,124 +1,187 @@
-use std::fmt::{self, Write};
-use sqliters::consts;
-
-#[derive(Debug)]
-pub enum Statement {
-    Insert(InsertStatement),
-    Select
+use std::collections::HashMap;
+use sqliters::{ast, context, database, error, expr, parser, replacement, row, sqlcommands};
+
+// A statement which is parsed once and executed many times with values bound to its ? parameters.
+// Parameters are numbered from 1 in order of appearance ; every parameter is bound before execute.
+pub struct PreparedStatement {
+    statement: ast::Statement,
+    bindings: Vec<Option<ast::Literal>>
 }
 
-#[derive(Debug, Default)]
-pub struct InsertStatement {
-    id: i32,
-    username: [u8; consts::USERNAME_SIZE],
-    email: [u8; consts::EMAIL_SIZE]
+// Parsed statements by their sql text. When the cache is full, the statement which was used
+// least recently is evicted for a new one.
+pub struct StatementCache {
+    capacity: usize,
+    statements: HashMap<String, ast::Statement>,
+    // sql of cached statements in order of use.
+    lru: replacement::Lru<String>
 }
 
-pub fn prepare_statement(command: &str) -> Result<Statement, String>
-{
-    // remove empty string from splits.
-    let splits : Vec<&str> = command.split(char::is_whitespace).filter(|c| !c.is_empty()).collect();
-    match splits.first()
-    {
-        Some(&"insert") => prepare_insert_statement(splits),
-        Some(&"select") => prepare_select_statement(splits),
-        _ => Err(String::from("Unknown command"))
+impl PreparedStatement {
+    pub fn new(statement: ast::Statement) -> Self {
+        let mut parameter_count = 0;
+        map_literals(&statement, &mut |literal| {
+            if let ast::Literal::Parameter(n) = *literal {
+                parameter_count = parameter_count.max(n);
+            }
+            Ok(literal.clone())
+        }).expect("counting parameters does not fail");
+
+        PreparedStatement {
+            statement,
+            bindings: vec![None; parameter_count]
+        }
     }
-}
 
-fn prepare_insert_statement(command_splits: Vec<&str>) -> Result<Statement, String>
-{
-    // column 	type
-    // id 	    integer
-    // username varchar(32)
-    // email 	varchar(255)
-    // insert 1 ashishnegi thisismyidashish@gmail.com
-    if command_splits.len() != 4 {
-        return Err(String::from("Bad insert command : Length != 4"))
+    pub fn parameter_count(&self) -> usize {
+        self.bindings.len()
     }
 
-    let user_id = command_splits[1].parse::<i32>()
-        .map_err(|_e| format!("First argument '{}' should be id : integer", command_splits[1]))?;
-
-    let username = command_splits[2];
-    let username_bytes = username.as_bytes();
-    if username_bytes.len() > consts::USERNAME_SIZE {
-        return Err(format!("Username '{}' can be maximum of {} bytes", username, consts::USERNAME_SIZE))
+    // binds value to parameter ?index ; a value bound before is replaced.
+    pub fn bind(&mut self, index: usize, value: row::Value) -> Result<(), error::Error> {
+        let count = self.parameter_count();
+        match self.bindings.get_mut(index.wrapping_sub(1)) {
+            Some(binding) => {
+                *binding = Some(expr::value_literal(&value));
+                Ok(())
+            },
+            None => Err(error::Error::ParameterOutOfRange { index, count })
+        }
     }
 
-    let email = command_splits[3];
-    let email_bytes = email.as_bytes();
-    if email_bytes.len() > consts::EMAIL_SIZE {
-        return Err(format!("Email '{}' can be maximum of {} bytes", email, consts::EMAIL_SIZE))
+    // clears bound values, so that the statement can be bound again.
+    pub fn reset(&mut self) {
+        for binding in self.bindings.iter_mut() {
+            *binding = None;
+        }
     }
 
-    let mut insert: InsertStatement = Default::default();
-    insert.id = user_id;
-    insert.username[..username_bytes.len()].copy_from_slice(username_bytes);
-    insert.email[..email_bytes.len()].copy_from_slice(email_bytes);
-
-    Ok(Statement::Insert(insert))
-}
-
-fn prepare_select_statement(command_splits: Vec<&str>) -> Result<Statement, String>
-{
-    if command_splits.len() != 1 {
-        return Err(String::from("Bad insert command : Length != 1"))
+    // executes statement with bound values ; bindings are kept for next execute.
+    pub fn execute(&self, context: &mut context::Context, db: &mut database::Database) -> Result<(), error::Error> {
+        if let Some(n) = self.bindings.iter().position(|b| b.is_none()) {
+            return Err(error::Error::UnboundParameter(n + 1))
+        }
+        let statement = map_literals(&self.statement, &mut |literal| match *literal {
+            ast::Literal::Parameter(n) => Ok(self.bindings[n - 1].clone().expect("parameters are bound")),
+            _ => Ok(literal.clone())
+        })?;
+        sqlcommands::execute_statement(context, db, statement)
     }
-    return Ok(Statement::Select)
 }
 
-pub fn serialize_row(insert: &InsertStatement) -> Result<Vec<u8>, String>
-{
-    use std::mem::transmute;
+impl StatementCache {
+    pub fn new(capacity: usize) -> Self {
+        StatementCache {
+            capacity,
+            statements: HashMap::new(),
+            lru: replacement::Lru::default()
+        }
+    }
 
-    let mut serialized = Vec::<u8>::new();
-    let id_bytes: [u8; consts::ID_SIZE] = unsafe { transmute(insert.id.to_be()) };
-    serialized.extend_from_slice(&id_bytes);
-    serialized.extend_from_slice(&insert.username);
-    serialized.extend_from_slice(&insert.email);
-    if serialized.len() != consts::INSERT_STATEMENT_SIZE {
-        return Err(format!("serialized size is not {}", consts::INSERT_STATEMENT_SIZE))
+    // statement of sql ; sql which is not in cache is parsed and cached.
+    pub fn get(&mut self, sql: &str) -> Result<ast::Statement, error::Error> {
+        if let Some(statement) = self.statements.get(sql) {
+            self.lru.touch(sql.to_string());
+            return Ok(statement.clone())
+        }
+
+        let statement = parser::parse(sql)
+            .map_err(|msg| error::Error::Parse(format!("Failure: {} for command '{}'", msg, sql)))?;
+        if self.capacity == 0 {
+            return Ok(statement)
+        }
+        if self.statements.len() >= self.capacity {
+            if let Some(least_used) = self.lru.evict_least_used() {
+                self.statements.remove(&least_used);
+            }
+        }
+        self.statements.insert(sql.to_string(), statement.clone());
+        self.lru.touch(sql.to_string());
+        Ok(statement)
     }
 
-    Ok(serialized)
+    #[cfg(test)]
+    pub fn contains(&self, sql: &str) -> bool {
+        self.statements.contains_key(sql)
+    }
 }
 
-pub fn deserialize_row(deserialized: Vec<u8>) -> Result<InsertStatement, String>
+// replaces each literal of statement with map of it.
+fn map_literals<F>(statement: &ast::Statement, map: &mut F) -> Result<ast::Statement, String>
+    where F: FnMut(&ast::Literal) -> Result<ast::Literal, String>
 {
-    use std::mem::transmute;
-    if deserialized.len() != consts::INSERT_STATEMENT_SIZE {
-        return Err(format!("deserialized size is not {}", consts::INSERT_STATEMENT_SIZE))
-    }
-
-    let mut insert: InsertStatement = Default::default();
-
-    let mut id_bytes: [u8; consts::ID_SIZE] = Default::default();
-    id_bytes.copy_from_slice(&deserialized[0..consts::ID_SIZE]);
-    insert.id = unsafe { transmute::<[u8;4], i32>(id_bytes) }.to_be();
-
-    insert.username.copy_from_slice(&deserialized[consts::USERNAME_OFFSET..consts::EMAIL_OFFSET]);
-    insert.email.copy_from_slice(&deserialized[consts::EMAIL_OFFSET..]);
-
-    Ok(insert)
+    Ok(match *statement {
+        ast::Statement::CreateTable(_) | ast::Statement::CreateIndex(_) => statement.clone(),
+        ast::Statement::Insert(ref insert) => {
+            let rows = insert.rows.iter()
+                .map(|literals| literals.iter().map(&mut *map).collect())
+                .collect::<Result<_, String>>()?;
+            let on_conflict = match insert.on_conflict {
+                ast::OnConflict::Update(ref assignments) => ast::OnConflict::Update(map_assignments(assignments, map)?),
+                ref on_conflict => on_conflict.clone()
+            };
+            ast::Statement::Insert(ast::InsertStatement {
+                rows,
+                on_conflict,
+                ..insert.clone()
+            })
+        },
+        ast::Statement::Select(ref select) => {
+            let projection = select.projection.iter()
+                .map(|item| match *item {
+                    ast::SelectItem::Wildcard => Ok(ast::SelectItem::Wildcard),
+                    ast::SelectItem::Expr { ref expr, ref alias } => Ok(ast::SelectItem::Expr {
+                        expr: expr::map_literals(expr, map)?,
+                        alias: alias.clone()
+                    })
+                })
+                .collect::<Result<_, String>>()?;
+            let where_clause = map_where_clause(&select.where_clause, map)?;
+            let group_by = select.group_by.iter()
+                .map(|e| expr::map_literals(e, map))
+                .collect::<Result<_, String>>()?;
+            let order_by = select.order_by.iter()
+                .map(|item| Ok(ast::OrderByItem {
+                    expr: expr::map_literals(&item.expr, map)?,
+                    descending: item.descending
+                }))
+                .collect::<Result<_, String>>()?;
+            ast::Statement::Select(ast::SelectStatement {
+                projection,
+                where_clause,
+                group_by,
+                order_by,
+                ..select.clone()
+            })
+        },
+        ast::Statement::Delete(ref delete) => ast::Statement::Delete(ast::DeleteStatement {
+            table_name: delete.table_name.clone(),
+            where_clause: map_where_clause(&delete.where_clause, map)?
+        }),
+        ast::Statement::Update(ref update) => ast::Statement::Update(ast::UpdateStatement {
+            table_name: update.table_name.clone(),
+            assignments: map_assignments(&update.assignments, map)?,
+            where_clause: map_where_clause(&update.where_clause, map)?
+        })
+    })
 }
 
-impl fmt::Display for InsertStatement {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt.write_char('{')?;
-        fmt.write_str(" InsertStatement : Id: '")?;
-        fmt.write_str(self.id.to_string().as_str())?;
-        fmt.write_str("', username: '")?;
-        fmt.write_str(String::from_utf8(self.username.to_vec()).expect("Unable to get username from utf8").as_str())?;
-        fmt.write_str("', email: '")?;
-        fmt.write_str(String::from_utf8(self.email.to_vec()).expect("Unable to get email from utf8").as_str())?;
-        fmt.write_str("' }")?;
-        Ok(())
-    }
+fn map_assignments<F>(assignments: &[ast::Assignment], map: &mut F) -> Result<Vec<ast::Assignment>, String>
+    where F: FnMut(&ast::Literal) -> Result<ast::Literal, String>
+{
+    assignments.iter()
+        .map(|a| Ok(ast::Assignment {
+            column: a.column.clone(),
+            value: expr::map_literals(&a.value, map)?
+        }))
+        .collect()
 }
 
-impl InsertStatement {
-    pub fn id(&self) -> i32 {
-        self.id
+fn map_where_clause<F>(where_clause: &Option<ast::Expr>, map: &mut F) -> Result<Option<ast::Expr>, String>
+    where F: FnMut(&ast::Literal) -> Result<ast::Literal, String>
+{
+    match *where_clause {
+        Some(ref e) => Ok(Some(expr::map_literals(e, map)?)),
+        None => Ok(None)
     }
-}
\ No newline at end of file
+}
@@ -1,76 +1,54 @@
-use sqliters::{pager, consts, page};
+use sqliters::{schema, index, pager, error};
 
-#[derive(Debug)]
+// A table is a B+tree rooted at root_page_num whose rows follow schema.
+// Its indexes are kept up to date with its rows.
+#[derive(Debug, Clone)]
 pub struct Table {
-    pager: pager::Pager
+    name: String,
+    root_page_num: u64,
+    schema: schema::Schema,
+    indexes: Vec<index::Index>
 }
 
 impl Table {
-    pub fn new(db_filepath: &str) -> Result<Self, String> {
-        let pager = pager::Pager::new(consts::PAGE_SIZE, consts::TABLE_MAX_PAGES, db_filepath)?;
-
-        Ok(Table {
-            pager: pager
-        })
-    }
-
-    pub fn get_page(&mut self, page_num: usize) -> Result<&mut page::Page, String> {
-        self.pager.get_page(page_num)
-    }
-
-    pub fn delete_db(&mut self) -> Result<(), String> {
-        self.pager.delete_db_file()
-    }
-
-    pub fn close_db(&mut self) -> Result<(), String> {
-        for page_num in 0..self.num_pages() {
-            self.pager.flush_page(page_num as usize)?;
+    pub fn new(name: &str, root_page_num: u64, schema: schema::Schema) -> Self {
+        Table {
+            name: name.to_string(),
+            root_page_num,
+            schema,
+            indexes: vec![]
         }
-
-        self.pager.close_db()
     }
 
-    pub fn num_pages(&mut self) -> u64 {
-        self.pager.num_pages()
+    pub fn name(&self) -> &str {
+        &self.name
     }
 
-    pub fn split_page(&mut self, page_num: u64) -> Result<(), String> {
-        self.pager.split_page(page_num as usize)
+    pub fn root_page_num(&self) -> u64 {
+        self.root_page_num
     }
 
-    pub fn print(&self) -> bool {
-        self.pager.print();
-        true
+    pub fn schema(&self) -> &schema::Schema {
+        &self.schema
     }
 
-    pub fn find_key_pos(&mut self, key: i32) -> Result<(u64, u64), String> {
-        // let root_page = self.pager.get_page(0)?;
-        // root_page.find_key_pos(key)
-        self.pager.find_key_pos(key)
-
-
-        // root is always 0
-        // let mut leaf_page = Err("");
-        // let mut leaf_page_num = 0;
-        // let mut pager = &mut self.pager;
-        // // return page_num and cell_num
-        // loop
-        // {
-        //     let l_leaf_page = tmp.get_page(leaf_page_num as usize)?;
-        //     if !l_leaf_page.is_leaf() {
-        //         break;
-        //     }
-
-        //     leaf_page_num = l_leaf_page.find_key_pos(key);
-        //     leaf_page = Ok(l_leaf_page);
-        // }
+    pub fn indexes(&self) -> &[index::Index] {
+        &self.indexes
+    }
 
-        // Ok ((leaf_page_num, leaf_page.unwrap().find_key_pos(key)))
+    pub fn add_index(&mut self, index: index::Index) {
+        self.indexes.push(index);
     }
-}
 
-impl Drop for Table {
-    fn drop(&mut self) {
-        self.close_db().expect("Unable to close db.")
+    // bytes a serialized row can take in a leaf cell ; all leaves of the tree have the cell size of the first leaf.
+    pub fn max_row_size(&self, pager: &mut pager::Pager) -> Result<usize, error::Error> {
+        let mut page_num = self.root_page_num;
+        loop {
+            let page = pager.get_page(page_num as usize)?;
+            if page.is_leaf() {
+                return Ok(page.cell_size() - self.schema.key_size())
+            }
+            page_num = page.get_page_num(0);
+        }
     }
 }
@@ -0,0 +1,111 @@
+// Timestamps are seconds since 1970-01-01 00:00:00 UTC.
+// They are written as 'YYYY-MM-DD HH:MM:SS' ; a date alone is at midnight and 'T' can separate date and time.
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+pub fn parse(text: &str) -> Result<i64, String> {
+    let error = || format!("Invalid timestamp '{}' : expected 'YYYY-MM-DD HH:MM:SS'", text);
+    let (date, time) = match text.find([' ', 'T']) {
+        Some(pos) => (&text[.. pos], Some(&text[pos + 1 ..])),
+        None => (text, None)
+    };
+
+    let date = fields(date, '-', &[4, 2, 2]).ok_or_else(error)?;
+    let (year, month, day) = (date[0], date[1], date[2]);
+    if !(1 ..= 12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return Err(error())
+    }
+
+    let seconds = match time {
+        Some(time) => {
+            let time = fields(time, ':', &[2, 2, 2]).ok_or_else(error)?;
+            if time[0] > 23 || time[1] > 59 || time[2] > 59 {
+                return Err(error())
+            }
+            time[0] * 3600 + time[1] * 60 + time[2]
+        },
+        None => 0
+    };
+    Ok(days_from_civil(year, month, day) * SECONDS_PER_DAY + seconds)
+}
+
+pub fn format(timestamp: i64) -> String {
+    let days = timestamp.div_euclid(SECONDS_PER_DAY);
+    let seconds = timestamp.rem_euclid(SECONDS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+// numbers separated by separator with given number of digits each.
+fn fields(text: &str, separator: char, digits: &[usize]) -> Option<Vec<i64>> {
+    let parts: Vec<&str> = text.split(separator).collect();
+    if parts.len() != digits.len() {
+        return None
+    }
+    parts.iter().zip(digits.iter())
+        .map(|(part, &len)| if part.len() == len && part.bytes().all(|b| b.is_ascii_digit()) { part.parse().ok() } else { None })
+        .collect()
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31
+    }
+}
+
+// days since 1970-01-01 of a date in proleptic gregorian calendar ; years start in march so that
+// leap day is the last day of a year.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamp() {
+        let cases = [
+            ("1970-01-01 00:00:00", 0),
+            ("1969-12-31 23:59:59", -1),
+            ("2000-02-29 12:30:05", 951827405),
+            ("2038-01-19 03:14:08", 2147483648),
+            ("0001-01-01 00:00:00", -62135596800)
+        ];
+        for &(text, seconds) in cases.iter() {
+            assert!(parse(text) == Ok(seconds), "{} : {:?}", text, parse(text));
+            assert!(format(seconds) == text, "{} : {}", seconds, format(seconds));
+        }
+
+        assert!(parse("2024-03-01") == parse("2024-03-01 00:00:00"), "date alone is at midnight");
+        assert!(parse("2024-03-01T10:00:00") == parse("2024-03-01 10:00:00"), "T separates date and time");
+        for bad in ["2023-02-29", "2024-13-01", "2024-1-01", "2024-01-01 24:00:00", "2024-01-01 10:00", "yesterday", ""].iter() {
+            assert!(parse(bad).is_err(), "'{}' should not parse", bad);
+        }
+    }
+}